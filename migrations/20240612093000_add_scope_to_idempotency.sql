@@ -0,0 +1,11 @@
+-- Idempotency records are no longer tied to a logged-in user:
+-- anonymous clients get a scope derived from the request itself.
+BEGIN;
+    ALTER TABLE idempotency ADD COLUMN scope TEXT NULL;
+    UPDATE idempotency
+        SET scope = 'user:' || user_id::text;
+    ALTER TABLE idempotency ALTER COLUMN scope SET NOT NULL;
+    ALTER TABLE idempotency DROP CONSTRAINT idempotency_pkey;
+    ALTER TABLE idempotency ALTER COLUMN user_id DROP NOT NULL;
+    ALTER TABLE idempotency ADD PRIMARY KEY (scope, idempotency_key);
+COMMIT;
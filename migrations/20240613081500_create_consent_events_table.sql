@@ -0,0 +1,13 @@
+-- Proof of consent: every subscribe request, confirmation
+-- and unsubscribe is recorded together with its evidence.
+CREATE TABLE consent_events (
+  consent_event_id uuid PRIMARY KEY,
+  subscriber_id uuid NOT NULL
+    REFERENCES subscriptions (id),
+  event_type TEXT NOT NULL,
+  occurred_at timestamptz NOT NULL,
+  ip_address TEXT NULL,
+  user_agent TEXT NULL,
+  consent_text TEXT NULL
+);
+CREATE INDEX consent_events_subscriber_id_idx ON consent_events (subscriber_id);
@@ -0,0 +1,10 @@
+-- One row per processed delivery task, so that we can tell
+-- a subscriber what we sent them and when.
+CREATE TABLE issue_delivery_log (
+  newsletter_issue_id UUID NOT NULL
+    REFERENCES newsletter_issues (newsletter_issue_id),
+  subscriber_email TEXT NOT NULL,
+  outcome TEXT NOT NULL,
+  recorded_at timestamptz NOT NULL
+);
+CREATE INDEX issue_delivery_log_subscriber_email_idx ON issue_delivery_log (subscriber_email);
@@ -0,0 +1,9 @@
+-- Addresses we must not send to anymore, as reported
+-- by the email provider (hard bounces, spam complaints).
+CREATE TABLE email_suppressions (
+  email TEXT PRIMARY KEY,
+  reason TEXT NOT NULL,
+  provider TEXT NOT NULL,
+  details TEXT NULL,
+  created_at timestamptz NOT NULL
+);
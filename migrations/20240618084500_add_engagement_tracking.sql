@@ -0,0 +1,18 @@
+-- Tracking is opt-in, per issue
+ALTER TABLE newsletter_issues ADD COLUMN track_opens BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE newsletter_issues ADD COLUMN track_clicks BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE issue_engagement_events (
+  engagement_event_id UUID PRIMARY KEY,
+  newsletter_issue_id UUID NOT NULL
+    REFERENCES newsletter_issues (newsletter_issue_id),
+  subscriber_id UUID NOT NULL
+    REFERENCES subscriptions (id),
+  event_type TEXT NOT NULL,
+  url TEXT NULL,
+  occurred_at timestamptz NOT NULL
+);
+CREATE INDEX issue_engagement_events_issue_idx
+  ON issue_engagement_events (newsletter_issue_id, event_type);
+CREATE INDEX issue_engagement_events_subscriber_idx
+  ON issue_engagement_events (subscriber_id);
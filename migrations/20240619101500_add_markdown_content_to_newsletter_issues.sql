@@ -0,0 +1,3 @@
+-- The Markdown source of issues authored in Markdown,
+-- kept to edit and re-render them later
+ALTER TABLE newsletter_issues ADD COLUMN markdown_content TEXT NULL;
@@ -0,0 +1,9 @@
+-- Transactional email templates edited by an admin. They take
+-- precedence over the defaults shipped in the templates directory.
+CREATE TABLE email_templates (
+  name TEXT PRIMARY KEY,
+  html_body TEXT NOT NULL,
+  text_body TEXT NOT NULL,
+  updated_at timestamptz NOT NULL,
+  updated_by uuid NULL REFERENCES users (user_id)
+);
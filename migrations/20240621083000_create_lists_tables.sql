@@ -0,0 +1,43 @@
+-- We run several newsletters: subscribers join them one by one.
+CREATE TABLE lists (
+  list_id uuid PRIMARY KEY,
+  slug TEXT NOT NULL UNIQUE,
+  name TEXT NOT NULL,
+  created_at timestamptz NOT NULL
+);
+-- The list everybody subscribed to before there were several
+INSERT INTO lists (list_id, slug, name, created_at)
+VALUES ('9a3f1c2e-5b7d-4e8a-9c1f-2d6b8e4a7c30', 'newsletter', 'Newsletter', now());
+
+-- `subscriptions.status` tracks the address (confirmed, bounced, erased...),
+-- `list_subscriptions.status` tracks each list.
+CREATE TABLE list_subscriptions (
+  list_id uuid NOT NULL REFERENCES lists (list_id),
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+  status TEXT NOT NULL,
+  subscribed_at timestamptz NOT NULL,
+  PRIMARY KEY (list_id, subscriber_id)
+);
+CREATE INDEX list_subscriptions_subscriber_id_idx ON list_subscriptions (subscriber_id);
+INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+SELECT '9a3f1c2e-5b7d-4e8a-9c1f-2d6b8e4a7c30', id, status, subscribed_at
+FROM subscriptions
+WHERE status IN ('pending_confirmation', 'confirmed', 'unsubscribed');
+
+-- Confirmation and unsubscribe links act on a single list
+ALTER TABLE subscription_tokens ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+UPDATE subscription_tokens SET list_id = '9a3f1c2e-5b7d-4e8a-9c1f-2d6b8e4a7c30';
+ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
+
+ALTER TABLE consent_events ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+UPDATE consent_events SET list_id = '9a3f1c2e-5b7d-4e8a-9c1f-2d6b8e4a7c30'
+WHERE event_type IN ('subscription_requested', 'subscription_confirmed', 'unsubscribed');
+
+CREATE TABLE newsletter_issue_lists (
+  newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+  list_id uuid NOT NULL REFERENCES lists (list_id),
+  PRIMARY KEY (newsletter_issue_id, list_id)
+);
+INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+SELECT newsletter_issue_id, '9a3f1c2e-5b7d-4e8a-9c1f-2d6b8e4a7c30'
+FROM newsletter_issues;
@@ -0,0 +1,11 @@
+-- Free-form labels ("beta", "paid") used to target issues at a segment
+CREATE TABLE subscriber_tags (
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+  tag TEXT NOT NULL,
+  created_at timestamptz NOT NULL,
+  PRIMARY KEY (subscriber_id, tag)
+);
+CREATE INDEX subscriber_tags_tag_idx ON subscriber_tags (tag);
+
+-- The segment expression an issue was sent to, if any
+ALTER TABLE newsletter_issues ADD COLUMN segment TEXT NULL;
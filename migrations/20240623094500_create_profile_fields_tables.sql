@@ -0,0 +1,21 @@
+-- Extra profile fields, defined by the admins
+CREATE TABLE profile_fields (
+  key TEXT PRIMARY KEY,
+  label TEXT NOT NULL,
+  -- 'text', 'enum' or 'boolean'
+  kind TEXT NOT NULL,
+  -- The allowed values of 'enum' fields
+  options TEXT[] NOT NULL,
+  created_at timestamptz NOT NULL
+);
+
+CREATE TABLE subscriber_profile_values (
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+  field_key TEXT NOT NULL REFERENCES profile_fields (key),
+  value TEXT NOT NULL,
+  updated_at timestamptz NOT NULL,
+  PRIMARY KEY (subscriber_id, field_key)
+);
+
+-- Picked by subscribers in their preference centre
+ALTER TABLE subscriptions ADD COLUMN digest_frequency TEXT NOT NULL DEFAULT 'immediate';
@@ -0,0 +1,3 @@
+-- Whether the issue is listed in the public archive at /issues.
+-- Issues published so far stay private.
+ALTER TABLE newsletter_issues ADD COLUMN is_public BOOLEAN NOT NULL DEFAULT false;
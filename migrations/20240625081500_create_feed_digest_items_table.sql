@@ -0,0 +1,17 @@
+-- Feed digests are created as drafts, waiting for an admin to publish them
+ALTER TABLE newsletter_issues ADD COLUMN status TEXT NOT NULL DEFAULT 'published';
+
+-- The feeds polled so far: the items found on the first poll are not collected
+CREATE TABLE feed_sources (
+  feed_source TEXT PRIMARY KEY,
+  first_polled_at timestamptz NOT NULL
+);
+
+-- The feed items already seen, and the digest they went into
+CREATE TABLE feed_digest_items (
+  feed_source TEXT NOT NULL REFERENCES feed_sources (feed_source),
+  item_id TEXT NOT NULL,
+  newsletter_issue_id uuid NULL REFERENCES newsletter_issues (newsletter_issue_id),
+  seen_at timestamptz NOT NULL,
+  PRIMARY KEY (feed_source, item_id)
+);
@@ -0,0 +1,15 @@
+-- The token bucket shared by every delivery worker, and the pause
+-- requested by the email provider when it rate limits us
+CREATE TABLE delivery_throttle (
+  name TEXT PRIMARY KEY,
+  tokens DOUBLE PRECISION NOT NULL,
+  refilled_at timestamptz NOT NULL,
+  paused_until timestamptz NULL
+);
+INSERT INTO delivery_throttle (name, tokens, refilled_at) VALUES ('issue_delivery', 0, now());
+
+-- The emails handed to the provider each day (UTC), for the daily quota
+CREATE TABLE delivery_daily_counts (
+  day DATE PRIMARY KEY,
+  sent INTEGER NOT NULL
+);
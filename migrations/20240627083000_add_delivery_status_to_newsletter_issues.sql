@@ -0,0 +1,2 @@
+-- Admins can pause, resume or cancel the delivery of a published issue
+ALTER TABLE newsletter_issues ADD COLUMN delivery_status TEXT NOT NULL DEFAULT 'active';
@@ -0,0 +1,10 @@
+-- Passed to the email provider, so that a retried delivery can be recognised
+ALTER TABLE issue_delivery_queue ADD COLUMN message_key uuid NOT NULL DEFAULT gen_random_uuid();
+
+-- Committed before a delivery is handed to the provider: a task found with
+-- an attempt may have been accepted by the provider already.
+-- Deliberately not a foreign key, workers hold a lock on the queue row.
+CREATE TABLE issue_delivery_attempts (
+  message_key uuid PRIMARY KEY,
+  started_at timestamptz NOT NULL
+);
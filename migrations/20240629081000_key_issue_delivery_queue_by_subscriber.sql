@@ -0,0 +1,13 @@
+-- Tasks point at the subscriber rather than at a copy of their address:
+-- the worker mails their current address, if they still want the issue
+ALTER TABLE issue_delivery_queue ADD COLUMN subscriber_id uuid NULL REFERENCES subscriptions (id);
+UPDATE issue_delivery_queue q
+SET subscriber_id = s.id
+FROM subscriptions s
+WHERE s.email = q.subscriber_email;
+-- Nobody has the address anymore
+DELETE FROM issue_delivery_queue WHERE subscriber_id IS NULL;
+ALTER TABLE issue_delivery_queue DROP CONSTRAINT issue_delivery_queue_pkey;
+ALTER TABLE issue_delivery_queue DROP COLUMN subscriber_email;
+ALTER TABLE issue_delivery_queue ALTER COLUMN subscriber_id SET NOT NULL;
+ALTER TABLE issue_delivery_queue ADD PRIMARY KEY (newsletter_issue_id, subscriber_id);
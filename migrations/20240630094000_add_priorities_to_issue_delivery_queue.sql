@@ -0,0 +1,34 @@
+-- The queue also carries transactional emails, e.g. confirmations or
+-- password resets: workers send them before the bulk of newsletter issues
+ALTER TABLE issue_delivery_queue DROP CONSTRAINT issue_delivery_queue_pkey;
+ALTER TABLE issue_delivery_queue ADD PRIMARY KEY (message_key);
+ALTER TABLE issue_delivery_queue ALTER COLUMN newsletter_issue_id DROP NOT NULL;
+ALTER TABLE issue_delivery_queue ALTER COLUMN subscriber_id DROP NOT NULL;
+ALTER TABLE issue_delivery_queue
+    ADD CONSTRAINT issue_delivery_queue_issue_subscriber_key
+    UNIQUE (newsletter_issue_id, subscriber_id);
+ALTER TABLE issue_delivery_queue
+    ADD COLUMN kind TEXT NOT NULL DEFAULT 'bulk',
+    -- Higher first, within a kind
+    ADD COLUMN priority SMALLINT NOT NULL DEFAULT 0,
+    ADD COLUMN enqueued_at timestamptz NOT NULL DEFAULT now(),
+    -- Transactional emails are enqueued ready to send
+    ADD COLUMN recipient TEXT NULL,
+    ADD COLUMN subject TEXT NULL,
+    ADD COLUMN html_content TEXT NULL,
+    ADD COLUMN text_content TEXT NULL;
+ALTER TABLE issue_delivery_queue ADD CONSTRAINT issue_delivery_queue_kind_check CHECK (
+    (
+        kind = 'bulk'
+        AND newsletter_issue_id IS NOT NULL
+        AND subscriber_id IS NOT NULL
+    ) OR (
+        kind = 'transactional'
+        AND recipient IS NOT NULL
+        AND subject IS NOT NULL
+        AND html_content IS NOT NULL
+        AND text_content IS NOT NULL
+    )
+);
+CREATE INDEX issue_delivery_queue_order_idx
+    ON issue_delivery_queue (kind, priority DESC, enqueued_at);
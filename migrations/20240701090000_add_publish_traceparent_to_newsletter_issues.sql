@@ -0,0 +1,3 @@
+-- The W3C traceparent of the request that published the issue:
+-- the spans of the delivery workers link back to it
+ALTER TABLE newsletter_issues ADD COLUMN publish_traceparent TEXT NULL;
@@ -0,0 +1,7 @@
+-- Delivery worker processes record that they are alive,
+-- the readiness check reports on the latest heartbeat
+CREATE TABLE worker_heartbeats(
+    instance_id uuid NOT NULL,
+    beat_at timestamptz NOT NULL,
+    PRIMARY KEY(instance_id)
+);
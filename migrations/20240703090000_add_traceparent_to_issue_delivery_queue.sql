@@ -0,0 +1,3 @@
+-- The W3C traceparent of the request that queued a transactional email,
+-- e.g. a subscription: the span of the delivery worker links back to it
+ALTER TABLE issue_delivery_queue ADD COLUMN traceparent TEXT NULL;
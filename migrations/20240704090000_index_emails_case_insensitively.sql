@@ -0,0 +1,5 @@
+-- Addresses are matched regardless of their case, e.g. when a subscriber
+-- asks for their data or when the provider reports a bounce
+CREATE INDEX subscriptions_lower_email_idx ON subscriptions (lower(email));
+CREATE INDEX issue_delivery_log_lower_subscriber_email_idx
+    ON issue_delivery_log (lower(subscriber_email));
@@ -0,0 +1,31 @@
+-- Issues held back for the subscribers who asked for a daily or weekly
+-- digest, until their next digest is sent
+CREATE TABLE digest_queue(
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    held_at timestamptz NOT NULL,
+    PRIMARY KEY(subscriber_id, newsletter_issue_id)
+);
+-- Digests go through the queue as bulk emails, ready to send
+ALTER TABLE issue_delivery_queue DROP CONSTRAINT issue_delivery_queue_kind_check;
+ALTER TABLE issue_delivery_queue ADD CONSTRAINT issue_delivery_queue_kind_check CHECK (
+    (
+        kind = 'bulk'
+        AND newsletter_issue_id IS NOT NULL
+        AND subscriber_id IS NOT NULL
+    ) OR (
+        kind = 'bulk'
+        AND newsletter_issue_id IS NULL
+        AND subscriber_id IS NOT NULL
+        AND recipient IS NOT NULL
+        AND subject IS NOT NULL
+        AND html_content IS NOT NULL
+        AND text_content IS NOT NULL
+    ) OR (
+        kind = 'transactional'
+        AND recipient IS NOT NULL
+        AND subject IS NOT NULL
+        AND html_content IS NOT NULL
+        AND text_content IS NOT NULL
+    )
+);
@@ -1,28 +1,61 @@
 use secrecy::ExposeSecret;
 use secrecy::Secret;
-use serde_aux::field_attributes::deserialize_number_from_string;
+use serde_aux::field_attributes::{
+    deserialize_number_from_string, deserialize_option_number_from_string,
+};
 use sqlx::postgres::PgConnectOptions;
 use sqlx::postgres::PgSslMode;
 use sqlx::ConnectOptions;
 
+use crate::delivery_throttle::DeliveryThrottle;
 use crate::domain::SubscriberEmail;
 use crate::email_client::EmailClient;
+use crate::email_templates::EmailTemplates;
+use crate::issue_delivery_worker::QueueScheduler;
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub email_templates: EmailTemplateSettings,
+    pub delivery: DeliverySettings,
     pub redis_uri: Secret<String>,
+    // Digests are only created if a feed is configured
+    pub feed_digest: Option<FeedDigestSettings>,
+    // Spans are only exported if an endpoint is configured
+    pub otlp: Option<OtlpSettings>,
 }
 
 #[derive(serde::Deserialize, Clone)]
 pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
+    // Serves `/metrics`, it should not be reachable from the internet
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub admin_port: u16,
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    // How long in-flight requests and deliveries get to complete on shutdown
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub shutdown_grace_period_seconds: u64,
+    pub readiness: ReadinessSettings,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct ReadinessSettings {
+    // Each check of `/health/ready` fails once it takes longer
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+    // The provider is outside of our control: only check it if asked to
+    pub check_email_provider: bool,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct OtlpSettings {
+    // The gRPC endpoint of the collector, e.g. `http://localhost:4317`
+    pub endpoint: String,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -42,6 +75,87 @@ pub struct EmailClientSettings {
     pub sender_email: String,
     pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    // Shared with the provider to authenticate its webhooks
+    pub webhook_secret: Secret<String>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct EmailTemplateSettings {
+    // The default templates, admins can override them from the dashboard
+    pub directory: String,
+}
+
+impl EmailTemplateSettings {
+    pub fn templates(&self) -> EmailTemplates {
+        EmailTemplates::new(&self.directory)
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct DeliverySettings {
+    // Concurrent workers in each process
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub workers: u16,
+    // Shared by every worker instance
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub messages_per_second: f64,
+    // How many emails can go out at once after a quiet period
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub burst: u32,
+    // Bulk delivery pauses until midnight (UTC) once reached
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub daily_quota: Option<u32>,
+    // Transactional emails go first, but bulk deliveries keep at least
+    // this share of the dequeues
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub bulk_share: f64,
+}
+
+impl DeliverySettings {
+    /// The throttle divides by the rate, and a bucket holds at least a token.
+    fn validate(&self) -> Result<(), String> {
+        if !self.messages_per_second.is_finite() || self.messages_per_second <= 0.0 {
+            return Err(format!(
+                "delivery.messages_per_second must be a positive number, got {}.",
+                self.messages_per_second
+            ));
+        }
+        if self.burst < 1 {
+            return Err("delivery.burst must be at least 1.".into());
+        }
+        if self.daily_quota == Some(0) {
+            return Err(
+                "delivery.daily_quota cannot be 0, leave it out to disable the quota.".into(),
+            );
+        }
+        Ok(())
+    }
+
+    pub fn throttle(&self) -> DeliveryThrottle {
+        DeliveryThrottle::new(self.messages_per_second, self.burst, self.daily_quota)
+    }
+
+    pub fn scheduler(&self) -> QueueScheduler {
+        QueueScheduler::new(self.bulk_share)
+    }
+}
+
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct FeedDigestSettings {
+    // An RSS or Atom feed: an `http(s)` URL, or the path of a local file
+    pub source: String,
+    // How often the feed is polled for new items
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub interval_seconds: u64,
+    // A Handlebars template, rendering the items into the Markdown body
+    pub template: String,
+    // Followed by the date of the digest
+    pub title: String,
+    // The slug of the list digests go to
+    pub list: String,
+    // Publish digests right away, rather than leaving them as drafts
+    #[serde(default)]
+    pub auto_publish: bool,
 }
 
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
@@ -71,7 +185,12 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
 
     // Try to convert the configuration values
     // it read into our Settings type
-    settings.try_deserialize::<Settings>()
+    let settings = settings.try_deserialize::<Settings>()?;
+    settings
+        .delivery
+        .validate()
+        .map_err(config::ConfigError::Message)?;
+    Ok(settings)
 }
 
 /// The possible runtime environment for our application
@@ -154,3 +273,58 @@ impl EmailClientSettings {
         std::time::Duration::from_millis(self.timeout_milliseconds)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::DeliverySettings;
+    use claim::{assert_err, assert_ok};
+
+    fn delivery_settings() -> DeliverySettings {
+        DeliverySettings {
+            workers: 4,
+            messages_per_second: 10.0,
+            burst: 20,
+            daily_quota: Some(50_000),
+            bulk_share: 0.1,
+        }
+    }
+
+    #[test]
+    fn valid_delivery_settings_are_accepted() {
+        assert_ok!(delivery_settings().validate());
+        let settings = DeliverySettings {
+            daily_quota: None,
+            ..delivery_settings()
+        };
+        assert_ok!(settings.validate());
+    }
+
+    #[test]
+    fn a_rate_that_is_not_positive_is_rejected() {
+        for messages_per_second in [0.0, -1.0, f64::NAN] {
+            let settings = DeliverySettings {
+                messages_per_second,
+                ..delivery_settings()
+            };
+            assert_err!(settings.validate());
+        }
+    }
+
+    #[test]
+    fn an_empty_bucket_is_rejected() {
+        let settings = DeliverySettings {
+            burst: 0,
+            ..delivery_settings()
+        };
+        assert_err!(settings.validate());
+    }
+
+    #[test]
+    fn a_daily_quota_of_zero_is_rejected() {
+        let settings = DeliverySettings {
+            daily_quota: Some(0),
+            ..delivery_settings()
+        };
+        assert_err!(settings.validate());
+    }
+}
@@ -0,0 +1,136 @@
+use actix_web::dev::Payload;
+use actix_web::http::header::USER_AGENT;
+use actix_web::{FromRequest, HttpRequest};
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::future::{ready, Ready};
+use uuid::Uuid;
+
+/// The wording shown next to the subscription form.
+/// It is stored alongside every subscribe request: if it ever changes,
+/// historical records keep the text the subscriber actually agreed to.
+pub const CONSENT_TEXT: &str = "I agree to receive the newsletter by email. \
+    I can unsubscribe at any time using the link included in every email.";
+
+#[derive(Debug, Clone, Copy)]
+pub enum ConsentEventKind {
+    SubscriptionRequested,
+    SubscriptionConfirmed,
+    Unsubscribed,
+    DataErased,
+}
+
+impl ConsentEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentEventKind::SubscriptionRequested => "subscription_requested",
+            ConsentEventKind::SubscriptionConfirmed => "subscription_confirmed",
+            ConsentEventKind::Unsubscribed => "unsubscribed",
+            ConsentEventKind::DataErased => "data_erased",
+        }
+    }
+}
+
+/// Who performed an action, as far as we can tell from the request.
+#[derive(Debug, Default)]
+pub struct ConsentEvidence {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl FromRequest for ConsentEvidence {
+    type Error = actix_web::Error;
+    // No I/O is involved, see `TypedSession`
+    type Future = Ready<Result<ConsentEvidence, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        // The address of the peer connecting to us: clients can set the
+        // `Forwarded` and `X-Forwarded-For` headers to anything they like
+        let ip_address = req.peer_addr().map(|addr| addr.ip().to_string());
+        let user_agent = req
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(ToOwned::to_owned);
+        ready(Ok(ConsentEvidence {
+            ip_address,
+            user_agent,
+        }))
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct ConsentRecord {
+    pub event_type: String,
+    /// The slug of the list the event is about, if any
+    pub list: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub consent_text: Option<String>,
+}
+
+#[tracing::instrument(
+    name = "Record a consent event",
+    skip(transaction, evidence, consent_text)
+)]
+pub async fn record_consent_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Option<Uuid>,
+    kind: ConsentEventKind,
+    evidence: &ConsentEvidence,
+    consent_text: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_events (
+            consent_event_id,
+            subscriber_id,
+            list_id,
+            event_type,
+            occurred_at,
+            ip_address,
+            user_agent,
+            consent_text
+        )
+        VALUES ($1, $2, $3, $4, now(), $5, $6, $7)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        list_id,
+        kind.as_str(),
+        evidence.ip_address,
+        evidence.user_agent,
+        consent_text,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get the consent history of a subscriber", skip(pool))]
+pub async fn get_consent_history(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConsentRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        ConsentRecord,
+        r#"
+        SELECT
+            c.event_type,
+            l.slug AS "list?",
+            c.occurred_at,
+            c.ip_address,
+            c.user_agent,
+            c.consent_text
+        FROM consent_events c
+        LEFT JOIN lists l USING (list_id)
+        WHERE c.subscriber_id = $1
+        ORDER BY c.occurred_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,204 @@
+use crate::issue_delivery_worker::TaskKind;
+use anyhow::Context;
+use chrono::{Duration as ChronoDuration, Utc};
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// How long to back off when the provider rate limits us
+/// without telling us for how long.
+const DEFAULT_BACK_OFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, PartialEq)]
+pub enum Permit {
+    Granted,
+    /// Try again later: the bucket is empty, or the provider asked us
+    /// to slow down.
+    Wait(Duration),
+    /// No more bulk emails until the quota resets, after the given delay.
+    /// Transactional emails can still go out.
+    QuotaReached(Duration),
+}
+
+/// A token bucket throttling issue deliveries, with a daily quota.
+///
+/// The bucket lives in Postgres: every worker instance draws
+/// from the same one.
+#[derive(Clone, Debug)]
+pub struct DeliveryThrottle {
+    messages_per_second: f64,
+    burst: f64,
+    daily_quota: Option<u32>,
+}
+
+impl DeliveryThrottle {
+    pub fn new(messages_per_second: f64, burst: u32, daily_quota: Option<u32>) -> Self {
+        Self {
+            messages_per_second,
+            // The bucket must hold at least the token being spent
+            burst: f64::from(burst.max(1)),
+            daily_quota,
+        }
+    }
+
+    /// Take a token from the bucket, and count bulk emails against the quota.
+    #[tracing::instrument(skip(self, pool))]
+    pub async fn acquire(&self, pool: &PgPool, kind: TaskKind) -> Result<Permit, anyhow::Error> {
+        let taken = sqlx::query!(
+            r#"
+            UPDATE delivery_throttle
+            SET
+                tokens = LEAST(
+                    $2::float8,
+                    tokens + EXTRACT(EPOCH FROM now() - refilled_at)::float8 * $1::float8
+                ) - 1,
+                refilled_at = now()
+            WHERE name = 'issue_delivery'
+            AND (paused_until IS NULL OR paused_until <= now())
+            AND LEAST(
+                $2::float8,
+                tokens + EXTRACT(EPOCH FROM now() - refilled_at)::float8 * $1::float8
+            ) >= 1
+            "#,
+            self.messages_per_second,
+            self.burst,
+        )
+        .execute(pool)
+        .await
+        .context("Failed to take a token from the delivery throttle.")?
+        .rows_affected()
+            == 1;
+        if !taken {
+            let bucket = sqlx::query!(
+                r#"
+                SELECT
+                    tokens,
+                    EXTRACT(EPOCH FROM now() - refilled_at)::float8 AS "elapsed!",
+                    EXTRACT(EPOCH FROM paused_until - now())::float8 AS paused_for
+                FROM delivery_throttle
+                WHERE name = 'issue_delivery'
+                "#,
+            )
+            .fetch_one(pool)
+            .await
+            .context("Failed to read the delivery throttle.")?;
+            let wait = match bucket.paused_for {
+                Some(paused_for) if paused_for > 0.0 => Duration::from_secs_f64(paused_for),
+                _ => self.refill_wait(bucket.tokens, bucket.elapsed),
+            };
+            return Ok(Permit::Wait(wait));
+        }
+
+        // A confirmation cannot wait until tomorrow
+        let daily_quota = match self.daily_quota {
+            Some(daily_quota) if kind == TaskKind::Bulk => daily_quota,
+            _ => return Ok(Permit::Granted),
+        };
+        let counted = daily_quota > 0
+            && sqlx::query!(
+                r#"
+                INSERT INTO delivery_daily_counts (day, sent)
+                VALUES ((now() AT TIME ZONE 'utc')::date, 1)
+                ON CONFLICT (day) DO UPDATE
+                SET sent = delivery_daily_counts.sent + 1
+                WHERE delivery_daily_counts.sent < $1
+                "#,
+                daily_quota as i32,
+            )
+            .execute(pool)
+            .await
+            .context("Failed to count an email against the daily quota.")?
+            .rows_affected()
+                == 1;
+        if counted {
+            Ok(Permit::Granted)
+        } else {
+            tracing::warn!("The daily delivery quota has been reached.");
+            // Nothing was sent: the token is for the next transactional email
+            self.give_back(pool).await?;
+            Ok(Permit::QuotaReached(until_tomorrow()))
+        }
+    }
+
+    /// Return a token taken for an email that is not sent after all.
+    async fn give_back(&self, pool: &PgPool) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE delivery_throttle SET tokens = LEAST($1::float8, tokens + 1)
+            WHERE name = 'issue_delivery'
+            "#,
+            self.burst,
+        )
+        .execute(pool)
+        .await
+        .context("Failed to give a token back to the delivery throttle.")?;
+        Ok(())
+    }
+
+    /// Pause every worker, e.g. after the provider answered with a 429.
+    #[tracing::instrument(skip(self, pool))]
+    pub async fn back_off(
+        &self,
+        pool: &PgPool,
+        retry_after: Option<Duration>,
+    ) -> Result<Duration, anyhow::Error> {
+        let pause = retry_after.unwrap_or(DEFAULT_BACK_OFF);
+        sqlx::query!(
+            r#"
+            UPDATE delivery_throttle
+            SET paused_until = GREATEST(paused_until, now() + make_interval(secs => $1::float8))
+            WHERE name = 'issue_delivery'
+            "#,
+            pause.as_secs_f64(),
+        )
+        .execute(pool)
+        .await
+        .context("Failed to pause the delivery throttle.")?;
+        Ok(pause)
+    }
+
+    /// How long until the bucket holds a whole token again.
+    fn refill_wait(&self, tokens: f64, elapsed_seconds: f64) -> Duration {
+        let available = self
+            .burst
+            .min(tokens + elapsed_seconds * self.messages_per_second);
+        let seconds = (1.0 - available) / self.messages_per_second;
+        // Never spin
+        Duration::from_secs_f64(seconds.max(0.001))
+    }
+}
+
+fn until_tomorrow() -> Duration {
+    let now = Utc::now();
+    let tomorrow = (now.date_naive() + ChronoDuration::days(1))
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc();
+    (tomorrow - now).to_std().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeliveryThrottle;
+    use std::time::Duration;
+
+    #[test]
+    fn an_empty_bucket_waits_for_a_whole_token() {
+        let throttle = DeliveryThrottle::new(10.0, 20, None);
+
+        assert_eq!(throttle.refill_wait(0.0, 0.0), Duration::from_secs_f64(0.1));
+        assert_eq!(
+            throttle.refill_wait(0.5, 0.0),
+            Duration::from_secs_f64(0.05)
+        );
+    }
+
+    #[test]
+    fn the_wait_is_never_zero() {
+        let throttle = DeliveryThrottle::new(10.0, 20, None);
+
+        assert_eq!(
+            throttle.refill_wait(5.0, 1.0),
+            Duration::from_secs_f64(0.001)
+        );
+    }
+}
@@ -0,0 +1,57 @@
+/// How often a subscriber would like to hear from us.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DigestFrequency {
+    Immediate,
+    Daily,
+    Weekly,
+}
+
+impl DigestFrequency {
+    pub const ALL: [DigestFrequency; 3] = [
+        DigestFrequency::Immediate,
+        DigestFrequency::Daily,
+        DigestFrequency::Weekly,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DigestFrequency::Immediate => "immediate",
+            DigestFrequency::Daily => "daily",
+            DigestFrequency::Weekly => "weekly",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DigestFrequency::Immediate => "Every issue",
+            DigestFrequency::Daily => "A daily digest",
+            DigestFrequency::Weekly => "A weekly digest",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<DigestFrequency, String> {
+        Self::ALL
+            .into_iter()
+            .find(|f| f.as_str() == s)
+            .ok_or_else(|| format!("{} is not a digest frequency.", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::DigestFrequency;
+    use claim::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn every_frequency_parses_back() {
+        for frequency in DigestFrequency::ALL {
+            assert_ok_eq!(DigestFrequency::parse(frequency.as_str()), frequency);
+        }
+    }
+
+    #[test]
+    fn unknown_frequencies_are_rejected() {
+        assert_err!(DigestFrequency::parse("hourly"));
+        assert_err!(DigestFrequency::parse("Weekly"));
+    }
+}
@@ -0,0 +1,60 @@
+/// The identifier of a mailing list in forms and URLs, e.g. `rust-weekly`.
+#[derive(Debug)]
+pub struct ListSlug(String);
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl ListSlug {
+    /// Returns an instance of `ListSlug` if the input is made of
+    /// lowercase ASCII letters, digits and inner hyphens.
+    pub fn parse(s: String) -> Result<ListSlug, String> {
+        let is_valid = !s.is_empty()
+            && s.len() <= 64
+            && s.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            && !s.starts_with('-')
+            && !s.ends_with('-');
+        if is_valid {
+            Ok(Self(s))
+        } else {
+            Err(format!("{} is not a valid list slug.", s))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::ListSlug;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn a_valid_slug_is_parsed_successfully() {
+        assert_ok!(ListSlug::parse("rust-weekly-2".into()));
+    }
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(ListSlug::parse("".into()));
+    }
+
+    #[test]
+    fn a_slug_longer_than_64_characters_is_rejected() {
+        assert_err!(ListSlug::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn uppercase_letters_and_spaces_are_rejected() {
+        assert_err!(ListSlug::parse("Rust".into()));
+        assert_err!(ListSlug::parse("rust weekly".into()));
+    }
+
+    #[test]
+    fn leading_and_trailing_hyphens_are_rejected() {
+        assert_err!(ListSlug::parse("-rust".into()));
+        assert_err!(ListSlug::parse("rust-".into()));
+    }
+}
@@ -0,0 +1,116 @@
+use handlebars::{no_escape, Handlebars, RenderError};
+
+/// The placeholders available to issue templates, e.g. `{{name}}`.
+#[derive(serde::Serialize, Debug)]
+pub struct TemplateContext {
+    pub name: String,
+    pub unsubscribe_url: String,
+    pub preferences_url: String,
+    pub confirmation_date: String,
+}
+
+impl TemplateContext {
+    fn example() -> Self {
+        Self {
+            name: "Ursula".into(),
+            unsubscribe_url: "https://example.com/subscriptions/unsubscribe".into(),
+            preferences_url: "https://example.com/subscriptions/preferences".into(),
+            confirmation_date: "2024-01-01".into(),
+        }
+    }
+}
+
+/// The content of an issue, rendered once per recipient.
+#[derive(Debug)]
+pub struct NewsletterTemplate(String);
+
+impl AsRef<str> for NewsletterTemplate {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl NewsletterTemplate {
+    /// Returns an instance of `NewsletterTemplate` if the input compiles
+    /// and only refers to the placeholders of `TemplateContext`.
+    /// Broken templates are caught before the issue is enqueued,
+    /// rather than halfway through its delivery.
+    pub fn parse(s: String) -> Result<NewsletterTemplate, String> {
+        let template = Self(s);
+        match template.render_text(&TemplateContext::example()) {
+            Ok(_) => Ok(template),
+            Err(e) => Err(format!("The template is not valid: {}", e)),
+        }
+    }
+
+    /// Placeholder values are HTML-escaped.
+    pub fn render_html(&self, context: &TemplateContext) -> Result<String, RenderError> {
+        registry(true).render_template(&self.0, context)
+    }
+
+    pub fn render_text(&self, context: &TemplateContext) -> Result<String, RenderError> {
+        registry(false).render_template(&self.0, context)
+    }
+}
+
+fn registry(escape_html: bool) -> Handlebars<'static> {
+    let mut registry = Handlebars::new();
+    // Fail on unknown placeholders instead of rendering them as empty strings
+    registry.set_strict_mode(true);
+    if !escape_html {
+        registry.register_escape_fn(no_escape);
+    }
+    registry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NewsletterTemplate, TemplateContext};
+    use claim::{assert_err, assert_ok};
+
+    fn context() -> TemplateContext {
+        TemplateContext {
+            name: "Ursula <Le Guin>".into(),
+            unsubscribe_url: "http://127.0.0.1/subscriptions/unsubscribe?subscription_token=abc"
+                .into(),
+            preferences_url: "http://127.0.0.1/subscriptions/preferences?subscriber_id=abc".into(),
+            confirmation_date: "2024-06-01".into(),
+        }
+    }
+
+    #[test]
+    fn a_template_without_placeholders_is_valid() {
+        assert_ok!(NewsletterTemplate::parse("Hello!".into()));
+    }
+
+    #[test]
+    fn a_template_with_known_placeholders_is_valid() {
+        assert_ok!(NewsletterTemplate::parse(
+            "Hi {{name}}, since {{confirmation_date}}. {{unsubscribe_url}}".into()
+        ));
+    }
+
+    #[test]
+    fn a_template_with_an_unknown_placeholder_is_rejected() {
+        assert_err!(NewsletterTemplate::parse("Hi {{nmae}}".into()));
+    }
+
+    #[test]
+    fn a_template_that_does_not_compile_is_rejected() {
+        assert_err!(NewsletterTemplate::parse("Hi {{#if name}}".into()));
+    }
+
+    #[test]
+    fn placeholders_are_escaped_in_html_only() {
+        let template = NewsletterTemplate::parse("Hi {{name}}".into()).unwrap();
+
+        assert_eq!(
+            template.render_html(&context()).unwrap(),
+            "Hi Ursula &lt;Le Guin&gt;"
+        );
+        assert_eq!(
+            template.render_text(&context()).unwrap(),
+            "Hi Ursula <Le Guin>"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+/// The identifier of a profile field in forms and segments, e.g. `company`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileFieldKey(String);
+
+impl AsRef<str> for ProfileFieldKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl ProfileFieldKey {
+    /// Returns an instance of `ProfileFieldKey` if the input is made of
+    /// lowercase ASCII letters, digits and underscores, starting with
+    /// a letter.
+    pub fn parse(s: String) -> Result<ProfileFieldKey, String> {
+        let is_valid = s.len() <= 32
+            && s.starts_with(|c: char| c.is_ascii_lowercase())
+            && s.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if is_valid {
+            Ok(Self(s))
+        } else {
+            Err(format!("{} is not a valid field key.", s))
+        }
+    }
+}
+
+/// What a profile field holds.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProfileFieldKind {
+    /// Free text, on a single line
+    Text,
+    /// One of the given options
+    Enum(Vec<String>),
+    Boolean,
+}
+
+impl ProfileFieldKind {
+    /// `options` are only allowed, and required, for enum fields.
+    pub fn parse(kind: &str, options: Vec<String>) -> Result<ProfileFieldKind, String> {
+        let options: Vec<String> = options
+            .into_iter()
+            .map(|o| o.trim().to_owned())
+            .filter(|o| !o.is_empty())
+            .collect();
+        match kind {
+            "text" | "boolean" if !options.is_empty() => Err(format!(
+                "Only enum fields have options, not {} fields.",
+                kind
+            )),
+            "text" => Ok(Self::Text),
+            "boolean" => Ok(Self::Boolean),
+            "enum" if options.is_empty() => Err("Enum fields need at least one option.".into()),
+            "enum" => Ok(Self::Enum(options)),
+            _ => Err(format!("{} is not a kind of field.", kind)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProfileFieldKind::Text => "text",
+            ProfileFieldKind::Enum(_) => "enum",
+            ProfileFieldKind::Boolean => "boolean",
+        }
+    }
+
+    pub fn options(&self) -> &[String] {
+        match self {
+            ProfileFieldKind::Enum(options) => options,
+            _ => &[],
+        }
+    }
+}
+
+/// An extra piece of information subscribers can give us.
+#[derive(Debug, Clone)]
+pub struct ProfileField {
+    pub key: ProfileFieldKey,
+    pub label: String,
+    pub kind: ProfileFieldKind,
+}
+
+/// A value that passed the validation of its field.
+#[derive(Debug, PartialEq)]
+pub struct ProfileValue(String);
+
+impl AsRef<str> for ProfileValue {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+const MAX_TEXT_LENGTH: usize = 256;
+
+impl ProfileField {
+    /// Validate what a subscriber submitted for this field:
+    /// `None` clears the field. Unchecked checkboxes are not submitted,
+    /// boolean fields are always set.
+    pub fn parse_value(&self, input: Option<&str>) -> Result<Option<ProfileValue>, String> {
+        let input = input.map(str::trim).filter(|s| !s.is_empty());
+        match (&self.kind, input) {
+            (ProfileFieldKind::Boolean, input) => {
+                let value = matches!(input, Some("true") | Some("on"));
+                Ok(Some(ProfileValue(value.to_string())))
+            }
+            (_, None) => Ok(None),
+            (ProfileFieldKind::Text, Some(s)) => {
+                if s.chars().count() > MAX_TEXT_LENGTH {
+                    Err(format!(
+                        "{} cannot be longer than {} characters.",
+                        self.label, MAX_TEXT_LENGTH
+                    ))
+                } else if s.chars().any(char::is_control) {
+                    Err(format!("{} must fit on a single line.", self.label))
+                } else {
+                    Ok(Some(ProfileValue(s.to_owned())))
+                }
+            }
+            (ProfileFieldKind::Enum(options), Some(s)) => {
+                if options.iter().any(|o| o == s) {
+                    Ok(Some(ProfileValue(s.to_owned())))
+                } else {
+                    Err(format!("{} is not a valid choice for {}.", s, self.label))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{ProfileField, ProfileFieldKey, ProfileFieldKind};
+    use claim::{assert_err, assert_none, assert_ok};
+
+    fn field(kind: ProfileFieldKind) -> ProfileField {
+        ProfileField {
+            key: ProfileFieldKey::parse("field".into()).unwrap(),
+            label: "Field".into(),
+            kind,
+        }
+    }
+
+    #[test]
+    fn keys_are_lowercase_identifiers() {
+        assert_ok!(ProfileFieldKey::parse("company_size2".into()));
+        assert_err!(ProfileFieldKey::parse("".into()));
+        assert_err!(ProfileFieldKey::parse("2nd_language".into()));
+        assert_err!(ProfileFieldKey::parse("Company".into()));
+        assert_err!(ProfileFieldKey::parse("company-size".into()));
+        assert_err!(ProfileFieldKey::parse("a".repeat(33)));
+    }
+
+    #[test]
+    fn only_enum_fields_have_options() {
+        assert_ok!(ProfileFieldKind::parse(
+            "enum",
+            vec!["a".into(), " ".into()]
+        ));
+        assert_err!(ProfileFieldKind::parse("enum", vec![" ".into()]));
+        assert_err!(ProfileFieldKind::parse("text", vec!["a".into()]));
+        assert_err!(ProfileFieldKind::parse("number", vec![]));
+    }
+
+    #[test]
+    fn text_values_are_trimmed_and_blank_ones_cleared() {
+        let field = field(ProfileFieldKind::Text);
+        assert_eq!(
+            field.parse_value(Some(" Acme ")).unwrap().unwrap().as_ref(),
+            "Acme"
+        );
+        assert_none!(field.parse_value(Some("  ")).unwrap());
+        assert_none!(field.parse_value(None).unwrap());
+    }
+
+    #[test]
+    fn long_or_multiline_text_values_are_rejected() {
+        let field = field(ProfileFieldKind::Text);
+        assert_err!(field.parse_value(Some(&"a".repeat(257))));
+        assert_err!(field.parse_value(Some("Acme\nInc")));
+    }
+
+    #[test]
+    fn enum_values_must_be_one_of_the_options() {
+        let field = field(ProfileFieldKind::Enum(vec!["small".into(), "large".into()]));
+        assert_ok!(field.parse_value(Some("large")));
+        assert_err!(field.parse_value(Some("Large")));
+        assert_none!(field.parse_value(Some("")).unwrap());
+    }
+
+    #[test]
+    fn boolean_fields_are_always_set() {
+        let field = field(ProfileFieldKind::Boolean);
+        assert_eq!(
+            field.parse_value(Some("true")).unwrap().unwrap().as_ref(),
+            "true"
+        );
+        assert_eq!(field.parse_value(None).unwrap().unwrap().as_ref(), "false");
+    }
+}
@@ -0,0 +1,60 @@
+/// A label attached to subscribers, e.g. `beta` or `paid`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriberTag(String);
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl SubscriberTag {
+    /// Returns an instance of `SubscriberTag` if the input is made of
+    /// lowercase ASCII letters, digits, hyphens and underscores.
+    /// Tags are case-insensitive: the input is lowercased first.
+    pub fn parse(s: String) -> Result<SubscriberTag, String> {
+        let s = s.trim().to_lowercase();
+        let is_valid = !s.is_empty()
+            && s.len() <= 32
+            && s.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+        if is_valid {
+            Ok(Self(s))
+        } else {
+            Err(format!("{} is not a valid tag.", s))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberTag;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn a_valid_tag_is_parsed_successfully() {
+        assert_ok!(SubscriberTag::parse("early_adopter-2".into()));
+    }
+
+    #[test]
+    fn tags_are_lowercased() {
+        let tag = SubscriberTag::parse(" Beta ".into()).unwrap();
+        assert_eq!(tag.as_ref(), "beta");
+    }
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert_err!(SubscriberTag::parse(" ".into()));
+    }
+
+    #[test]
+    fn a_tag_longer_than_32_characters_is_rejected() {
+        assert_err!(SubscriberTag::parse("a".repeat(33)));
+    }
+
+    #[test]
+    fn spaces_and_punctuation_are_rejected() {
+        assert_err!(SubscriberTag::parse("paid user".into()));
+        assert_err!(SubscriberTag::parse("paid:yes".into()));
+    }
+}
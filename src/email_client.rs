@@ -1,7 +1,24 @@
-use reqwest::Client;
+use reqwest::header::{ACCEPT, RETRY_AFTER};
+use reqwest::{Client, RequestBuilder, StatusCode};
 use secrecy::{ExposeSecret, Secret};
+use std::time::Duration;
 
 use crate::domain::SubscriberEmail;
+use crate::metrics::metrics;
+use crate::telemetry::trace_context_headers;
+
+/// The provider behind the client, as reported in the metrics.
+const PROVIDER: &str = "postmark";
+
+#[derive(thiserror::Error, Debug)]
+pub enum SendEmailError {
+    /// The provider asked us to slow down. `Retry-After` is only
+    /// understood as a number of seconds, not as a date.
+    #[error("The email provider is rate limiting us.")]
+    RateLimited { retry_after: Option<Duration> },
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+}
 
 pub struct EmailClient {
     sender: SubscriberEmail,
@@ -30,7 +47,96 @@ impl EmailClient {
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+    ) -> Result<(), SendEmailError> {
+        self.send(recipient, subject, html_content, text_content, None)
+            .await
+    }
+
+    /// The key is stored as metadata of the message, for `was_accepted`
+    /// to find it.
+    pub async fn send_email_with_key(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        message_key: &str,
+    ) -> Result<(), SendEmailError> {
+        self.send(
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            Some(message_key),
+        )
+        .await
+    }
+
+    /// Whether a message sent with the key was accepted by the provider.
+    pub async fn was_accepted(&self, message_key: &str) -> Result<bool, reqwest::Error> {
+        let url = format!("{}/messages/outbound", self.base_url);
+        let messages: OutboundMessages = with_trace_context(self.http_client.get(&url))
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .header(ACCEPT, "application/json")
+            .query(&[
+                ("count", "1"),
+                ("offset", "0"),
+                ("metadata_message_key", message_key),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(messages.total_count > 0)
+    }
+
+    /// Whether the provider is reachable and accepts our token.
+    pub async fn check(&self) -> Result<(), reqwest::Error> {
+        let url = format!("{}/server", self.base_url);
+        with_trace_context(self.http_client.get(&url))
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .header(ACCEPT, "application/json")
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn send(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        message_key: Option<&str>,
+    ) -> Result<(), SendEmailError> {
+        let outcome = self
+            .post_email(recipient, subject, html_content, text_content, message_key)
+            .await;
+        let label = match &outcome {
+            Ok(()) => "sent",
+            Err(SendEmailError::RateLimited { .. }) => "rate_limited",
+            Err(SendEmailError::Request(_)) => "failed",
+        };
+        metrics().emails.with_label_values(&[PROVIDER, label]).inc();
+        outcome
+    }
+
+    async fn post_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        message_key: Option<&str>,
+    ) -> Result<(), SendEmailError> {
         let url = format!("{}/email", self.base_url);
         let request_body = SendEmailRequest {
             from: self.sender.as_ref(),
@@ -38,9 +144,9 @@ impl EmailClient {
             subject,
             html_body: html_content,
             text_body: text_content,
+            metadata: message_key.map(|message_key| Metadata { message_key }),
         };
-        self.http_client
-            .post(&url)
+        let response = with_trace_context(self.http_client.post(&url))
             .header(
                 "X-Postmark-Server-Token",
                 self.authorization_token.expose_secret(),
@@ -49,14 +155,32 @@ impl EmailClient {
             // `send` is async so we need to await
             .send()
             // `send` is a fallible op, so return error using `?`
-            .await?
-            // if the server returned an error
-            // turn the response into an error
-            .error_for_status()?;
+            .await?;
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse().ok())
+                .map(Duration::from_secs);
+            return Err(SendEmailError::RateLimited { retry_after });
+        }
+        // if the server returned an error
+        // turn the response into an error
+        response.error_for_status()?;
         Ok(())
     }
 }
 
+/// Carry the context of the current span over to the provider.
+fn with_trace_context(request: RequestBuilder) -> RequestBuilder {
+    trace_context_headers()
+        .into_iter()
+        .fold(request, |request, (name, value)| {
+            request.header(name, value)
+        })
+}
+
 #[derive(serde::Serialize)]
 #[serde(rename_all = "PascalCase")]
 struct SendEmailRequest<'a> {
@@ -65,12 +189,25 @@ struct SendEmailRequest<'a> {
     subject: &'a str,
     html_body: &'a str,
     text_body: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<Metadata<'a>>,
+}
+
+#[derive(serde::Serialize)]
+struct Metadata<'a> {
+    message_key: &'a str,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct OutboundMessages {
+    total_count: u64,
 }
 
 #[cfg(test)]
 mod tests {
     use crate::domain::SubscriberEmail;
-    use crate::email_client::EmailClient;
+    use crate::email_client::{EmailClient, SendEmailError};
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en;
     use fake::{Fake, Faker};
@@ -206,6 +343,31 @@ mod tests {
         claim::assert_err!(outcome);
     }
 
+    #[tokio::test]
+    async fn send_email_reports_how_long_to_wait_if_the_server_returns_429() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(matchers::any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "30"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        let retry_after = match claim::assert_err!(outcome) {
+            SendEmailError::RateLimited { retry_after } => retry_after,
+            e => panic!("Unexpected error: {:?}", e),
+        };
+        assert_eq!(retry_after, Some(std::time::Duration::from_secs(30)));
+    }
+
     #[tokio::test]
     async fn send_email_times_out_if_the_server_takes_too_long() {
         // Arrange
@@ -229,4 +391,64 @@ mod tests {
         // Assert
         claim::assert_err!(outcome);
     }
+
+    #[tokio::test]
+    async fn send_email_with_key_sends_the_key_as_metadata() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(matchers::path("/email"))
+            .and(matchers::method("POST"))
+            .and(SendEmailBodyMatcher)
+            .and(matchers::body_partial_json(serde_json::json!({
+                "Metadata": { "message_key": "the-key" }
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email_with_key(&email(), &subject(), &content(), &content(), "the-key")
+            .await;
+
+        // Assert
+        claim::assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn was_accepted_looks_the_key_up() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(matchers::path("/messages/outbound"))
+            .and(matchers::method("GET"))
+            .and(matchers::query_param("metadata_message_key", "sent"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "TotalCount": 1, "Messages": [{}] })),
+            )
+            .mount(&mock_server)
+            .await;
+        Mock::given(matchers::path("/messages/outbound"))
+            .and(matchers::method("GET"))
+            .and(matchers::query_param("metadata_message_key", "lost"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "TotalCount": 0, "Messages": [] })),
+            )
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let sent = email_client.was_accepted("sent").await;
+        let lost = email_client.was_accepted("lost").await;
+
+        // Assert
+        assert!(claim::assert_ok!(sent));
+        assert!(!claim::assert_ok!(lost));
+    }
 }
@@ -0,0 +1,311 @@
+use anyhow::Context;
+use handlebars::{no_escape, Handlebars, RenderError};
+use sqlx::PgPool;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// The emails we send outside of newsletter issues, and the layout
+/// wrapping all of them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmailTemplate {
+    Layout,
+    Confirmation,
+    UnsubscribeReceipt,
+    Digest,
+}
+
+impl EmailTemplate {
+    pub const ALL: [EmailTemplate; 4] = [
+        EmailTemplate::Layout,
+        EmailTemplate::Confirmation,
+        EmailTemplate::UnsubscribeReceipt,
+        EmailTemplate::Digest,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailTemplate::Layout => "layout",
+            EmailTemplate::Confirmation => "confirmation",
+            EmailTemplate::UnsubscribeReceipt => "unsubscribe_receipt",
+            EmailTemplate::Digest => "digest",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.as_str() == s)
+    }
+
+    pub fn subject(&self) -> &'static str {
+        match self {
+            EmailTemplate::Layout => "Preview",
+            EmailTemplate::Confirmation => "Welcome!",
+            EmailTemplate::UnsubscribeReceipt => "You have been unsubscribed",
+            EmailTemplate::Digest => "Your newsletter digest",
+        }
+    }
+
+    /// The placeholders each template can use, with example values.
+    pub fn sample_data(&self) -> serde_json::Value {
+        match self {
+            EmailTemplate::Layout => serde_json::json!({
+                "subject": "Preview",
+                "body": "The content of the email goes here.",
+            }),
+            EmailTemplate::Confirmation => serde_json::json!({
+                "name": "Ursula",
+                "list": "Rust Weekly",
+                "confirmation_link": "https://example.com/subscriptions/confirm?subscription_token=abc",
+            }),
+            EmailTemplate::UnsubscribeReceipt => serde_json::json!({
+                "name": "Ursula",
+                "list": "Rust Weekly",
+            }),
+            EmailTemplate::Digest => serde_json::json!({
+                "name": "Ursula",
+                "issues": [
+                    { "title": "Rust Weekly #1", "url": "https://example.com/issues/1?recipient=abc&signature=def" },
+                    { "title": "Rust Weekly #2", "url": "https://example.com/issues/2?recipient=abc&signature=ghi" },
+                ],
+                "preferences_url": "https://example.com/subscriptions/preferences?token=jkl",
+            }),
+        }
+    }
+}
+
+/// The HTML and plain text variants of a template.
+#[derive(Debug, Clone)]
+pub struct TemplateSource {
+    pub html: String,
+    pub text: String,
+}
+
+#[derive(Debug)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+/// Templates are read from `{directory}/{name}.html.hbs` and
+/// `{directory}/{name}.txt.hbs`, unless an admin edited them:
+/// edits are stored in the database and take precedence.
+#[derive(Clone)]
+pub struct EmailTemplates {
+    directory: PathBuf,
+}
+
+impl EmailTemplates {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    pub async fn render(
+        &self,
+        pool: &PgPool,
+        template: EmailTemplate,
+        data: &serde_json::Value,
+    ) -> Result<RenderedEmail, anyhow::Error> {
+        let layout = self.source(pool, EmailTemplate::Layout).await?;
+        let source = self.source(pool, template).await?;
+        render(&layout, &source, template, data)
+            .with_context(|| format!("Failed to render the {} email.", template.as_str()))
+    }
+
+    /// The current version of a template.
+    #[tracing::instrument(name = "Get email template", skip(self, pool))]
+    pub async fn source(
+        &self,
+        pool: &PgPool,
+        template: EmailTemplate,
+    ) -> Result<TemplateSource, anyhow::Error> {
+        if let Some(source) = get_edited_template(pool, template).await? {
+            return Ok(source);
+        }
+        self.default_source(template)
+    }
+
+    /// The version of a template shipped in the templates directory.
+    pub fn default_source(&self, template: EmailTemplate) -> Result<TemplateSource, anyhow::Error> {
+        let read = |extension: &str| {
+            let path = self
+                .directory
+                .join(format!("{}.{}.hbs", template.as_str(), extension));
+            std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))
+        };
+        Ok(TemplateSource {
+            html: read("html")?,
+            text: read("txt")?,
+        })
+    }
+}
+
+/// Render a template, and the layout around it, with the given data.
+///
+/// Placeholders missing from the data are an error rather than an
+/// empty string: edits are validated against the sample data.
+pub fn render(
+    layout: &TemplateSource,
+    source: &TemplateSource,
+    template: EmailTemplate,
+    data: &serde_json::Value,
+) -> Result<RenderedEmail, RenderError> {
+    let html_registry = registry(true);
+    let text_registry = registry(false);
+    let subject = template.subject();
+
+    let (html, text) = if template == EmailTemplate::Layout {
+        (
+            html_registry.render_template(&source.html, data)?,
+            text_registry.render_template(&source.text, data)?,
+        )
+    } else {
+        let html_body = html_registry.render_template(&source.html, data)?;
+        let text_body = text_registry.render_template(&source.text, data)?;
+        (
+            html_registry.render_template(
+                &layout.html,
+                &serde_json::json!({ "subject": subject, "body": html_body }),
+            )?,
+            text_registry.render_template(
+                &layout.text,
+                &serde_json::json!({ "subject": subject, "body": text_body.trim_end() }),
+            )?,
+        )
+    };
+    Ok(RenderedEmail {
+        subject: subject.into(),
+        html,
+        text,
+    })
+}
+
+fn registry(escape_html: bool) -> Handlebars<'static> {
+    let mut registry = Handlebars::new();
+    registry.set_strict_mode(true);
+    if !escape_html {
+        registry.register_escape_fn(no_escape);
+    }
+    registry
+}
+
+#[tracing::instrument(name = "Get edited email template", skip(pool))]
+async fn get_edited_template(
+    pool: &PgPool,
+    template: EmailTemplate,
+) -> Result<Option<TemplateSource>, anyhow::Error> {
+    let source = sqlx::query_as!(
+        TemplateSource,
+        r#"
+        SELECT html_body AS html, text_body AS text
+        FROM email_templates
+        WHERE name = $1
+        "#,
+        template.as_str(),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve an edited email template.")?;
+    Ok(source)
+}
+
+#[tracing::instrument(name = "Save email template", skip(pool, source))]
+pub async fn save_template(
+    pool: &PgPool,
+    template: EmailTemplate,
+    source: &TemplateSource,
+    user_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_templates (name, html_body, text_body, updated_at, updated_by)
+        VALUES ($1, $2, $3, now(), $4)
+        ON CONFLICT (name) DO UPDATE
+        SET html_body = EXCLUDED.html_body,
+            text_body = EXCLUDED.text_body,
+            updated_at = EXCLUDED.updated_at,
+            updated_by = EXCLUDED.updated_by
+        "#,
+        template.as_str(),
+        source.html,
+        source.text,
+        user_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to save an email template.")?;
+    Ok(())
+}
+
+/// Go back to the version shipped in the templates directory.
+#[tracing::instrument(name = "Reset email template", skip(pool))]
+pub async fn reset_template(pool: &PgPool, template: EmailTemplate) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM email_templates WHERE name = $1",
+        template.as_str()
+    )
+    .execute(pool)
+    .await
+    .context("Failed to reset an email template.")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, EmailTemplate, EmailTemplates, TemplateSource};
+    use claim::{assert_err, assert_ok};
+
+    fn layout() -> TemplateSource {
+        TemplateSource {
+            html: "<div>{{{body}}}</div>".into(),
+            text: "{{{body}}}\n--\nFooter".into(),
+        }
+    }
+
+    #[test]
+    fn templates_are_wrapped_in_the_layout() {
+        let source = TemplateSource {
+            html: "<p>Hi {{name}}</p>".into(),
+            text: "Hi {{name}}\n".into(),
+        };
+        let data = serde_json::json!({ "name": "Ursula <Le Guin>" });
+
+        let rendered = assert_ok!(render(
+            &layout(),
+            &source,
+            EmailTemplate::UnsubscribeReceipt,
+            &data
+        ));
+
+        assert_eq!(rendered.html, "<div><p>Hi Ursula &lt;Le Guin&gt;</p></div>");
+        assert_eq!(rendered.text, "Hi Ursula <Le Guin>\n--\nFooter");
+        assert_eq!(rendered.subject, "You have been unsubscribed");
+    }
+
+    #[test]
+    fn unknown_placeholders_are_rejected() {
+        let source = TemplateSource {
+            html: "<p>Hi {{nmae}}</p>".into(),
+            text: "Hi {{name}}".into(),
+        };
+
+        assert_err!(render(
+            &layout(),
+            &source,
+            EmailTemplate::UnsubscribeReceipt,
+            &EmailTemplate::UnsubscribeReceipt.sample_data()
+        ));
+    }
+
+    #[test]
+    fn the_shipped_templates_render_with_their_sample_data() {
+        let templates = EmailTemplates::new("templates/email");
+        let layout = templates.default_source(EmailTemplate::Layout).unwrap();
+        for template in EmailTemplate::ALL {
+            let source = templates.default_source(template).unwrap();
+            assert_ok!(render(&layout, &source, template, &template.sample_data()));
+        }
+    }
+}
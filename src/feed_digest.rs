@@ -0,0 +1,259 @@
+use crate::configuration::{FeedDigestSettings, Settings};
+use crate::domain::{ListSlug, NewsletterTemplate};
+use crate::lists::get_lists_by_slug;
+use crate::markdown;
+use crate::metrics::metrics;
+use crate::newsletter_issues::{
+    enqueue_delivery_tasks, insert_newsletter_issue, IssueStatus, NewsletterIssue,
+};
+use crate::sanitizer::sanitize;
+use crate::shutdown::Shutdown;
+use crate::startup::get_connection_pool;
+use anyhow::Context;
+use handlebars::{no_escape, Handlebars};
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Poll the configured feed on a schedule, until shutdown is triggered.
+pub async fn run_digest_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    let settings = match configuration.feed_digest {
+        Some(settings) => settings,
+        None => {
+            tracing::info!("No feed is configured, digests are disabled.");
+            // Nothing to do, but returning would stop the application
+            shutdown.triggered().await;
+            return Ok(());
+        }
+    };
+    let pool = get_connection_pool(&configuration.database);
+    metrics().watch_pool("digest", &pool);
+    while !shutdown.is_triggered() {
+        if let Err(e) = try_run_digest(&pool, &settings).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to create a feed digest",
+            );
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(settings.interval_seconds)) => {}
+            _ = shutdown.triggered() => {}
+        }
+    }
+    Ok(())
+}
+
+/// Collect the items published since the last run into a digest issue,
+/// a draft unless `auto_publish` is set. Returns the id of the issue.
+///
+/// The items found on the first run are only recorded: subscribers
+/// do not get the whole history of the feed at once.
+#[tracing::instrument(skip(pool), err)]
+pub async fn try_run_digest(
+    pool: &PgPool,
+    settings: &FeedDigestSettings,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let items = fetch_items(&settings.source).await?;
+
+    let mut transaction = pool.begin().await?;
+    let first_run = sqlx::query!(
+        r#"
+        INSERT INTO feed_sources (feed_source, first_polled_at)
+        VALUES ($1, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        settings.source,
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected()
+        == 1;
+    // Only the items this run inserted are new, even if several
+    // instances poll the feed at the same time
+    let item_ids: Vec<_> = items.iter().map(|i| i.id.clone()).collect();
+    let new_ids: Vec<String> = sqlx::query!(
+        r#"
+        INSERT INTO feed_digest_items (feed_source, item_id, seen_at)
+        SELECT $1, UNNEST($2::text[]), now()
+        ON CONFLICT DO NOTHING
+        RETURNING item_id
+        "#,
+        settings.source,
+        &item_ids,
+    )
+    .fetch_all(&mut transaction)
+    .await?
+    .into_iter()
+    .map(|r| r.item_id)
+    .collect();
+    let new_items: Vec<_> = items
+        .into_iter()
+        .filter(|i| new_ids.contains(&i.id))
+        .collect();
+    if first_run || new_items.is_empty() {
+        transaction.commit().await?;
+        return Ok(None);
+    }
+
+    let issue = render_digest(settings, &new_items).await?;
+    let lists = get_lists_by_slug(&mut transaction, &issue.lists).await?;
+    if lists.is_empty() {
+        anyhow::bail!("There is no list called {}.", settings.list);
+    }
+    let list_ids: Vec<_> = lists.iter().map(|l| l.list_id).collect();
+    let status = if settings.auto_publish {
+        IssueStatus::Published
+    } else {
+        IssueStatus::Draft
+    };
+    let issue_id = insert_newsletter_issue(&mut transaction, &issue, &list_ids, status)
+        .await
+        .context("Failed to store the digest")?;
+    sqlx::query!(
+        r#"
+        UPDATE feed_digest_items SET newsletter_issue_id = $1
+        WHERE feed_source = $2 AND item_id = ANY($3)
+        "#,
+        issue_id,
+        settings.source,
+        &new_ids,
+    )
+    .execute(&mut transaction)
+    .await?;
+    if settings.auto_publish {
+        enqueue_delivery_tasks(&mut transaction, issue_id, &list_ids, None)
+            .await
+            .context("Failed to enqueue delivery tasks")?;
+    }
+    transaction.commit().await?;
+    Ok(Some(issue_id))
+}
+
+/// An entry of the feed, as plain text.
+#[derive(serde::Serialize, Debug)]
+struct FeedItem {
+    #[serde(skip)]
+    id: String,
+    title: String,
+    link: String,
+    /// `YYYY-MM-DD`, or empty if the feed does not tell
+    published: String,
+    summary: String,
+}
+
+async fn fetch_items(source: &str) -> Result<Vec<FeedItem>, anyhow::Error> {
+    let body = if source.starts_with("http://") || source.starts_with("https://") {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()?
+            .get(source)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?
+            .to_vec()
+    } else {
+        tokio::fs::read(source)
+            .await
+            .with_context(|| format!("Failed to read {}", source))?
+    };
+    let feed = feed_rs::parser::parse(body.as_slice()).context("Failed to parse the feed")?;
+    Ok(feed
+        .entries
+        .into_iter()
+        .map(|entry| FeedItem {
+            title: entry
+                .title
+                .map(|t| plain_text(&t.content))
+                .unwrap_or_default(),
+            link: entry
+                .links
+                .first()
+                .map(|l| plain_text(&l.href))
+                .unwrap_or_default(),
+            published: entry
+                .published
+                .or(entry.updated)
+                .map(|d| d.format("%Y-%m-%d").to_string())
+                .unwrap_or_default(),
+            summary: entry
+                .summary
+                .map(|t| t.content)
+                .or_else(|| entry.content.and_then(|c| c.body))
+                .map(|s| plain_text(&s))
+                .unwrap_or_default(),
+            id: entry.id,
+        })
+        .collect())
+}
+
+/// Feeds carry HTML: keep the text, on a single line. Braces are
+/// split so that the issue template does not take them for placeholders.
+fn plain_text(s: &str) -> String {
+    let text = ammonia::Builder::empty().clean(s).to_string();
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("{{", "{ {")
+        .replace("}}", "} }")
+}
+
+async fn render_digest(
+    settings: &FeedDigestSettings,
+    items: &[FeedItem],
+) -> Result<NewsletterIssue, anyhow::Error> {
+    let template = tokio::fs::read_to_string(&settings.template)
+        .await
+        .with_context(|| format!("Failed to read {}", settings.template))?;
+    let mut registry = Handlebars::new();
+    registry.set_strict_mode(true);
+    // The template renders Markdown, not HTML
+    registry.register_escape_fn(no_escape);
+    let date = chrono::Utc::now().format("%Y-%m-%d").to_string();
+    let markdown_content = registry
+        .render_template(
+            &template,
+            &serde_json::json!({ "date": date, "items": items }),
+        )
+        .context("Failed to render the digest template")?;
+
+    let rendered = markdown::render(&markdown_content);
+    let sanitized = sanitize(&rendered.html).context("Failed to sanitize the digest")?;
+    NewsletterTemplate::parse(sanitized.html.clone()).map_err(anyhow::Error::msg)?;
+    NewsletterTemplate::parse(rendered.text.clone()).map_err(anyhow::Error::msg)?;
+    Ok(NewsletterIssue {
+        title: format!("{} - {}", settings.title, date),
+        text_content: rendered.text,
+        html_content: sanitized.html,
+        markdown_content: Some(markdown_content),
+        track_opens: false,
+        track_clicks: false,
+        is_public: false,
+        lists: vec![ListSlug::parse(settings.list.clone()).map_err(anyhow::Error::msg)?],
+        segment: None,
+        warnings: sanitized.warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::plain_text;
+
+    #[test]
+    fn tags_and_line_breaks_are_removed() {
+        assert_eq!(
+            plain_text("<p>Hello\n  <b>world</b></p>"),
+            "Hello world".to_string()
+        );
+    }
+
+    #[test]
+    fn braces_cannot_become_placeholders() {
+        assert_eq!(plain_text("{{unsubscribe_url}}"), "{ {unsubscribe_url} }");
+    }
+}
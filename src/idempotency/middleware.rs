@@ -0,0 +1,177 @@
+use super::{save_response, try_processing, IdempotencyKey, IdempotencyScope, NextAction};
+use crate::session_state::TypedSession;
+use crate::utils::{e400, e500};
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest};
+use actix_web_lab::middleware::Next;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::future::{ready, Ready};
+use std::sync::Arc;
+use tokio::sync::{MappedMutexGuard, Mutex, MutexGuard};
+
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+const IDEMPOTENCY_KEY_FIELD: &str = "idempotency_key";
+const ANONYMOUS_SUBJECT_FIELD: &str = "email";
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+/// The transaction opened by `idempotent_requests` on behalf of a handler.
+///
+/// The idempotency record is inserted in this transaction: everything the
+/// handler writes through it is committed together with the saved response,
+/// or rolled back if the handler fails.
+#[derive(Clone)]
+pub struct IdempotentTransaction(Arc<Mutex<Option<PgTransaction>>>);
+
+impl IdempotentTransaction {
+    fn new(transaction: PgTransaction) -> Self {
+        Self(Arc::new(Mutex::new(Some(transaction))))
+    }
+
+    pub async fn lock(&self) -> Result<MappedMutexGuard<'_, PgTransaction>, anyhow::Error> {
+        MutexGuard::try_map(self.0.lock().await, |t| t.as_mut())
+            .map_err(|_| anyhow::anyhow!("The idempotency transaction has already been closed"))
+    }
+
+    async fn take(&self) -> Option<PgTransaction> {
+        self.0.lock().await.take()
+    }
+}
+
+impl FromRequest for IdempotentTransaction {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<IdempotentTransaction, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        // The middleware only opens a transaction if the request
+        // carried an idempotency key
+        let transaction = req
+            .extensions()
+            .get::<IdempotentTransaction>()
+            .cloned()
+            .ok_or_else(|| e400("The request is missing an idempotency key"));
+        ready(transaction)
+    }
+}
+
+/// Make the mutating requests carrying an idempotency key safe to retry.
+///
+/// Mount it on the routes whose handlers take an `IdempotentTransaction`:
+/// it buffers the body of form submissions to look for the key.
+///
+/// The key is read from the `Idempotency-Key` header or, for HTML forms,
+/// from the `idempotency_key` field.
+/// The first request for a key runs the handler and stores its response,
+/// retries get the stored response back without touching the handler.
+///
+/// Keys are scoped to the logged-in user or, for anonymous clients,
+/// to the route and the `email` form field. Requests we cannot scope
+/// go through untouched.
+pub async fn idempotent_requests(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let (idempotency_key, scope) = match idempotency_scope(&mut req).await? {
+        Some(scope) => scope,
+        None => {
+            return next
+                .call(req)
+                .await
+                .map(ServiceResponse::map_into_boxed_body)
+        }
+    };
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500("The database pool is not available"))?
+        .clone();
+
+    let transaction = match try_processing(&pool, &idempotency_key, &scope)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(t) => IdempotentTransaction::new(t),
+        // Return early if we have a saved response in the database
+        NextAction::ReturnSavedResponse(saved_response) => {
+            return Ok(req.into_response(saved_response));
+        }
+    };
+    req.extensions_mut().insert(transaction.clone());
+
+    let response = next.call(req).await?;
+    let transaction = transaction
+        .take()
+        .await
+        .ok_or_else(|| e500("The idempotency transaction was closed by the handler"))?;
+    // Failed requests are not saved: dropping the transaction rolls back
+    // the idempotency record, so the client is free to retry.
+    let status = response.status();
+    if status.is_client_error() || status.is_server_error() {
+        return Ok(response.map_into_boxed_body());
+    }
+
+    let (request, response) = response.into_parts();
+    let response = save_response(
+        transaction,
+        &idempotency_key,
+        &scope,
+        response.map_into_boxed_body(),
+    )
+    .await
+    .map_err(e500)?;
+    Ok(ServiceResponse::new(request, response))
+}
+
+async fn idempotency_scope(
+    req: &mut ServiceRequest,
+) -> Result<Option<(IdempotencyKey, IdempotencyScope)>, actix_web::Error> {
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        return Ok(None);
+    }
+    let form = read_form(req).await?;
+    let form_field = |field: &str| {
+        form.iter()
+            .find(|(name, _)| name == field)
+            .map(|(_, value)| value.to_owned())
+    };
+
+    let idempotency_key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) => value.to_str().map_err(e400)?.to_owned(),
+        None => match form_field(IDEMPOTENCY_KEY_FIELD) {
+            Some(key) => key,
+            None => return Ok(None),
+        },
+    };
+    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    let scope = match session.get_user_id().map_err(e500)? {
+        Some(user_id) => IdempotencyScope::User(user_id),
+        None => match form_field(ANONYMOUS_SUBJECT_FIELD) {
+            Some(subject) => IdempotencyScope::anonymous(req.path(), &subject),
+            None => return Ok(None),
+        },
+    };
+    Ok(Some((idempotency_key, scope)))
+}
+
+/// Parse the body of HTML form submissions, leaving it in place
+/// for the handler to consume.
+async fn read_form(req: &mut ServiceRequest) -> Result<Vec<(String, String)>, actix_web::Error> {
+    if req.content_type() != "application/x-www-form-urlencoded" {
+        return Ok(Vec::new());
+    }
+    let body = req.extract::<web::Bytes>().await?;
+    req.set_payload(bytes_to_payload(body.clone()));
+    Ok(serde_urlencoded::from_bytes(&body).unwrap_or_default())
+}
+
+fn bytes_to_payload(buf: web::Bytes) -> Payload {
+    let (_, mut payload) = actix_http::h1::Payload::create(true);
+    payload.unread_data(buf);
+    Payload::from(payload)
+}
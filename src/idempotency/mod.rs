@@ -1,7 +1,11 @@
 mod key;
+mod middleware;
 mod persistence;
+mod scope;
 
 pub use key::IdempotencyKey;
+pub use middleware::{idempotent_requests, IdempotentTransaction};
 pub use persistence::get_saved_response;
 pub use persistence::save_response;
 pub use persistence::{try_processing, NextAction};
+pub use scope::IdempotencyScope;
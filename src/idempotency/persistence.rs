@@ -1,11 +1,10 @@
-use super::IdempotencyKey;
+use super::{IdempotencyKey, IdempotencyScope};
 use actix_web::body::to_bytes;
 use actix_web::http::StatusCode;
 use actix_web::HttpResponse;
 use sqlx::postgres::PgHasArrayType;
 use sqlx::PgPool;
 use sqlx::{Postgres, Transaction};
-use uuid::Uuid;
 
 #[derive(Debug, sqlx::Type)]
 #[sqlx(type_name = "header_pair")]
@@ -38,20 +37,22 @@ pub enum NextAction {
 pub async fn try_processing(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
+    scope: &IdempotencyScope,
 ) -> Result<NextAction, anyhow::Error> {
     let mut transaction = pool.begin().await?;
     let n_inserted_rows = sqlx::query!(
         r#"
         INSERT INTO idempotency (
-            user_id, 
+            scope,
+            user_id,
             idempotency_key,
             created_at
-        ) 
-        VALUES ($1, $2, now()) 
+        )
+        VALUES ($1, $2, $3, now())
         ON CONFLICT DO NOTHING
         "#,
-        user_id,
+        scope.to_string(),
+        scope.user_id(),
         idempotency_key.as_ref()
     )
     .execute(&mut transaction)
@@ -60,7 +61,7 @@ pub async fn try_processing(
     if n_inserted_rows > 0 {
         Ok(NextAction::StartProcessing(transaction))
     } else {
-        let saved_response = get_saved_response(pool, idempotency_key, user_id)
+        let saved_response = get_saved_response(pool, idempotency_key, scope)
             .await?
             .ok_or_else(|| anyhow::anyhow!("We expected a saved response, we didn't find it"))?;
         Ok(NextAction::ReturnSavedResponse(saved_response))
@@ -70,7 +71,7 @@ pub async fn try_processing(
 pub async fn get_saved_response(
     pool: &PgPool,
     idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
+    scope: &IdempotencyScope,
 ) -> Result<Option<HttpResponse>, anyhow::Error> {
     let saved_response = sqlx::query!(
         r#"
@@ -79,11 +80,11 @@ pub async fn get_saved_response(
             response_headers as "response_headers!: Vec<HeaderPairRecord>",
             response_body as "response_body!"
         FROM idempotency
-        WHERE 
-          user_id = $1 AND
+        WHERE
+          scope = $1 AND
           idempotency_key = $2
         "#,
-        user_id,
+        scope.to_string(),
         idempotency_key.as_ref()
     )
     .fetch_optional(pool)
@@ -103,7 +104,7 @@ pub async fn get_saved_response(
 pub async fn save_response(
     mut transaction: Transaction<'static, Postgres>,
     idempotency_key: &IdempotencyKey,
-    user_id: Uuid,
+    scope: &IdempotencyScope,
     http_response: HttpResponse,
 ) -> Result<HttpResponse, anyhow::Error> {
     let (response_head, body) = http_response.into_parts();
@@ -126,10 +127,10 @@ pub async fn save_response(
             response_headers = $4,
             response_body = $5
         WHERE
-            user_id = $1 AND
+            scope = $1 AND
             idempotency_key = $2
         "#,
-        user_id,
+        scope.to_string(),
         idempotency_key.as_ref(),
         status_code,
         headers,
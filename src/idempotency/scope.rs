@@ -0,0 +1,66 @@
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// Who an idempotency key belongs to.
+///
+/// Keys are client-supplied: two clients can pick the same one,
+/// so every saved response is namespaced by its scope.
+#[derive(Debug)]
+pub enum IdempotencyScope {
+    User(Uuid),
+    Anonymous(String),
+}
+
+impl IdempotencyScope {
+    /// Anonymous requests are scoped to the route they hit and to the
+    /// subscriber they act on (e.g. the email in the subscription form).
+    /// We only store a digest of the pair, not the email itself.
+    pub fn anonymous(path: &str, subject: &str) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(path.as_bytes());
+        hasher.update(b"\n");
+        hasher.update(subject.trim().to_lowercase().as_bytes());
+        Self::Anonymous(hex::encode(hasher.finalize()))
+    }
+
+    pub fn user_id(&self) -> Option<Uuid> {
+        match self {
+            Self::User(user_id) => Some(*user_id),
+            Self::Anonymous(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for IdempotencyScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::User(user_id) => write!(f, "user:{}", user_id),
+            Self::Anonymous(digest) => write!(f, "anonymous:{}", digest),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IdempotencyScope;
+
+    #[test]
+    fn anonymous_scopes_ignore_case_and_whitespace_in_the_subject() {
+        let a = IdempotencyScope::anonymous("/subscriptions", "Ursula@Example.com ");
+        let b = IdempotencyScope::anonymous("/subscriptions", "ursula@example.com");
+        assert_eq!(a.to_string(), b.to_string());
+    }
+
+    #[test]
+    fn anonymous_scopes_differ_across_routes() {
+        let a = IdempotencyScope::anonymous("/subscriptions", "ursula@example.com");
+        let b = IdempotencyScope::anonymous("/unsubscribe", "ursula@example.com");
+        assert_ne!(a.to_string(), b.to_string());
+    }
+
+    #[test]
+    fn anonymous_scopes_do_not_leak_the_subject() {
+        let scope = IdempotencyScope::anonymous("/subscriptions", "ursula@example.com");
+        assert!(!scope.to_string().contains("ursula"));
+    }
+}
@@ -1,136 +1,729 @@
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::delivery_throttle::{DeliveryThrottle, Permit};
+use crate::domain::{NewsletterTemplate, SubscriberEmail, TemplateContext};
+use crate::email_client::{EmailClient, SendEmailError};
+use crate::metrics::metrics;
+use crate::routes::preferences_url;
+use crate::shutdown::Shutdown;
+use crate::subscriber_digest::run_digests_until_stopped;
+use crate::telemetry::{current_traceparent, link_to_traceparent};
+use crate::tracking::Tracker;
 use crate::{configuration::Settings, startup::get_connection_pool};
 
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
+use sqlx::postgres::PgListener;
 use sqlx::{PgPool, Postgres, Transaction};
-use std::time::Duration;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::task::JoinSet;
 use tracing::{field::display, Span};
 use uuid::Uuid;
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
-    loop {
-        match try_execute_task(&pool, &email_client).await {
-            Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
+/// The channel notified whenever delivery tasks are enqueued.
+pub const NEW_TASKS_CHANNEL: &str = "issue_delivery_queue";
+
+/// Workers poll the queue on top of listening for notifications,
+/// in case one was missed.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often each worker process records that it is alive.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TaskKind {
+    /// A ready-made email, e.g. a confirmation, that must not wait
+    /// behind a newsletter issue
+    Transactional,
+    /// A newsletter issue, or a digest of issues, to one of its recipients
+    Bulk,
+}
+
+impl TaskKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskKind::Transactional => "transactional",
+            TaskKind::Bulk => "bulk",
+        }
+    }
+}
+
+impl TryFrom<String> for TaskKind {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "transactional" => Ok(TaskKind::Transactional),
+            "bulk" => Ok(TaskKind::Bulk),
+            other => Err(format!("{} is not a valid task kind.", other)),
+        }
+    }
+}
+
+/// Decides which kind of task a worker looks at first.
+///
+/// Transactional emails always go first, except for one dequeue in
+/// `bulk_every`: a steady flow of them cannot starve newsletter issues.
+/// Bulk tasks are left aside while the daily quota is reached.
+#[derive(Debug)]
+pub struct QueueScheduler {
+    bulk_every: u64,
+    dequeued: AtomicU64,
+    bulk_held_until: Mutex<Option<Instant>>,
+}
+
+impl QueueScheduler {
+    /// `bulk_share` is the minimum share of dequeues reserved for bulk tasks,
+    /// between 0 (never) and 1 (always).
+    pub fn new(bulk_share: f64) -> Self {
+        let bulk_every = if bulk_share > 0.0 {
+            (1.0 / bulk_share.min(1.0)).round() as u64
+        } else {
+            u64::MAX
+        };
+        Self {
+            bulk_every,
+            dequeued: AtomicU64::new(0),
+            bulk_held_until: Mutex::new(None),
+        }
+    }
+
+    /// Only look for transactional tasks for a while.
+    pub fn hold_bulk(&self, wait: Duration) {
+        *self.bulk_held_until.lock().unwrap() = Some(Instant::now() + wait);
+    }
+
+    /// The kinds of task to look for, in order.
+    fn next_kinds(&self) -> &'static [TaskKind] {
+        let held = matches!(
+            *self.bulk_held_until.lock().unwrap(),
+            Some(until) if Instant::now() < until
+        );
+        if held {
+            &[TaskKind::Transactional]
+        } else if self.prefers_bulk() {
+            &[TaskKind::Bulk, TaskKind::Transactional]
+        } else {
+            &[TaskKind::Transactional, TaskKind::Bulk]
+        }
+    }
+
+    fn prefers_bulk(&self) -> bool {
+        let dequeued = self.dequeued.fetch_add(1, Ordering::Relaxed);
+        dequeued % self.bulk_every == self.bulk_every - 1
+    }
+}
+
+/// Queue a transactional email, sent before any pending newsletter issue.
+/// Returns its message key, or `None` if the address is suppressed.
+#[tracing::instrument(skip(transaction, html_content, text_content))]
+pub async fn enqueue_transactional_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+    priority: i16,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let message_key = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            kind, priority, recipient, subject, html_content, text_content, traceparent
+        )
+        SELECT $1, $2, $3, $4, $5, $6, $7
+        WHERE lower($3) NOT IN (SELECT lower(email) FROM email_suppressions)
+        RETURNING message_key
+        "#,
+        TaskKind::Transactional.as_str(),
+        priority,
+        recipient.as_ref(),
+        subject,
+        html_content,
+        text_content,
+        current_traceparent(),
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    .map(|r| r.message_key);
+    if message_key.is_none() {
+        tracing::info!("The address is suppressed, not queuing the email.");
+        return Ok(None);
+    }
+    sqlx::query("SELECT pg_notify($1, '')")
+        .bind(NEW_TASKS_CHANNEL)
+        .execute(&mut *transaction)
+        .await?;
+    Ok(message_key)
+}
+
+/// Queue a digest of issues for a subscriber. It counts against the daily
+/// quota like any newsletter issue.
+#[tracing::instrument(skip(transaction, html_content, text_content))]
+pub async fn enqueue_digest_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    recipient: &SubscriberEmail,
+    subject: &str,
+    html_content: &str,
+    text_content: &str,
+) -> Result<Uuid, sqlx::Error> {
+    let message_key = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            kind, subscriber_id, recipient, subject, html_content, text_content
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING message_key
+        "#,
+        TaskKind::Bulk.as_str(),
+        subscriber_id,
+        recipient.as_ref(),
+        subject,
+        html_content,
+        text_content,
+    )
+    .fetch_one(&mut *transaction)
+    .await?
+    .message_key;
+    sqlx::query("SELECT pg_notify($1, '')")
+        .bind(NEW_TASKS_CHANNEL)
+        .execute(&mut *transaction)
+        .await?;
+    Ok(message_key)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn worker_loop(
+    worker_id: u16,
+    pool: PgPool,
+    email_client: EmailClient,
+    throttle: DeliveryThrottle,
+    scheduler: QueueScheduler,
+    tracker: Tracker,
+    base_url: String,
+    hmac_secret: Secret<String>,
+    new_tasks: Arc<Notify>,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    tracing::info!("Delivery worker {} started.", worker_id);
+    // The current task always runs to completion: the email may already
+    // be out, the task must not be left in the queue
+    while !shutdown.is_triggered() {
+        // Register interest before looking at the queue, so that
+        // tasks enqueued in the meantime are not missed
+        let new_task = new_tasks.notified();
+        tokio::pin!(new_task);
+        new_task.as_mut().enable();
+        let outcome = try_execute_task(
+            &pool,
+            &email_client,
+            &throttle,
+            &scheduler,
+            &tracker,
+            &base_url,
+            &hmac_secret,
+        )
+        .await;
+        let wait = async {
+            match outcome {
+                Ok(ExecutionOutcome::EmptyQueue) => {
+                    let _ = tokio::time::timeout(POLL_INTERVAL, new_task).await;
+                }
+                Ok(ExecutionOutcome::Throttled(wait)) => {
+                    tokio::time::sleep(wait).await;
+                }
+                // Transactional emails keep going out in the meantime
+                Ok(ExecutionOutcome::BulkQuotaReached(wait)) => scheduler.hold_bulk(wait),
+                Err(_) => {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                Ok(ExecutionOutcome::TaskCompleted) => {}
             }
-            Err(_) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
+        };
+        tokio::select! {
+            _ = wait => {}
+            _ = shutdown.triggered() => {}
+        }
+    }
+    tracing::info!("Delivery worker {} stopped.", worker_id);
+    Ok(())
+}
+
+/// Wake every idle worker when delivery tasks are enqueued.
+async fn listen_for_new_tasks(
+    pool: PgPool,
+    new_tasks: Arc<Notify>,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    let mut listener = PgListener::connect_with(&pool)
+        .await
+        .context("Failed to connect the delivery task listener.")?;
+    listener
+        .listen(NEW_TASKS_CHANNEL)
+        .await
+        .context("Failed to listen for delivery tasks.")?;
+    loop {
+        tokio::select! {
+            // `None` means that the connection was lost, and notifications
+            // may have been missed: let the workers check the queue
+            notification = listener.try_recv() => {
+                let _ = notification.context("Failed to receive a delivery task notification.")?;
+                new_tasks.notify_waiters();
             }
-            Ok(ExecutionOutcome::TaskCompleted) => {}
+            _ = shutdown.triggered() => return Ok(()),
         }
     }
 }
 
+/// Record that the process is alive, until shutdown is triggered.
+async fn send_heartbeats(pool: PgPool, shutdown: Shutdown) -> Result<(), anyhow::Error> {
+    let instance_id = Uuid::new_v4();
+    while !shutdown.is_triggered() {
+        let beat = sqlx::query!(
+            r#"
+            INSERT INTO worker_heartbeats (instance_id, beat_at)
+            VALUES ($1, now())
+            ON CONFLICT (instance_id) DO UPDATE SET beat_at = now()
+            "#,
+            instance_id,
+        )
+        .execute(&pool)
+        .await;
+        // Missing a beat is not worth stopping the workers
+        if let Err(e) = beat {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to record a worker heartbeat."
+            );
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(HEARTBEAT_INTERVAL) => {}
+            _ = shutdown.triggered() => {}
+        }
+    }
+    // A stopped process is not a stale one
+    sqlx::query!(
+        "DELETE FROM worker_heartbeats WHERE instance_id = $1",
+        instance_id,
+    )
+    .execute(&pool)
+    .await
+    .context("Failed to remove the worker heartbeat.")?;
+    Ok(())
+}
+
 pub enum ExecutionOutcome {
     TaskCompleted,
     EmptyQueue,
+    /// The task was left in the queue, try again after the given delay
+    Throttled(Duration),
+    /// The bulk task was left in the queue: the daily quota resets
+    /// after the given delay
+    BulkQuotaReached(Duration),
 }
 
 #[tracing::instrument(
     skip_all,
     fields(
         newsletter_issue_id=tracing::field::Empty,
-        subscriber_email=tracing::field::Empty
+        subscriber_id=tracing::field::Empty
         ),
         err
     )]
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    throttle: &DeliveryThrottle,
+    scheduler: &QueueScheduler,
+    tracker: &Tracker,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    let task = dequeue_task(pool).await?;
+    let task = dequeue_task(pool, scheduler.next_kinds()).await?;
     if task.is_none() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
-    let (transaction, issue_id, email) = task.unwrap();
+    let (transaction, message_key, task) = task.unwrap();
+    let (issue_id, subscriber_id) = match task {
+        Task::Issue {
+            issue_id,
+            subscriber_id,
+        } => (issue_id, subscriber_id),
+        Task::Prepared(kind, email) => {
+            // Sends are traced separately, but linked to the request queuing them
+            if let Some(traceparent) = &email.traceparent {
+                link_to_traceparent(traceparent);
+            }
+            return execute_prepared_task(
+                pool,
+                email_client,
+                throttle,
+                transaction,
+                message_key,
+                kind,
+                email,
+            )
+            .await;
+        }
+    };
     Span::current()
-        .record("newsletter_issue_id", &display(issue_id))
-        .record("subscriber_email", &display(&email));
+        .record("newsletter_issue_id", display(issue_id))
+        .record("subscriber_id", display(subscriber_id));
+    // Deliveries are traced separately, but linked to the publication
+    if let Some(traceparent) = get_publish_traceparent(pool, issue_id).await? {
+        link_to_traceparent(&traceparent);
+    }
+
+    // The subscriber may have changed address, or left, since the
+    // issue was published
+    let recipient = get_recipient(pool, subscriber_id, issue_id).await?;
+    if !recipient.wants_issue {
+        tracing::info!("The subscriber does not want the issue anymore. Skipping.");
+        delete_task(
+            transaction,
+            issue_id,
+            &recipient.email,
+            message_key,
+            DeliveryOutcome::Skipped,
+        )
+        .await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+    let email = recipient.email.clone();
 
     // send email
-    match SubscriberEmail::parse(email.clone()) {
+    let outcome = match SubscriberEmail::parse(email.clone()) {
+        // A previous attempt got through, but the worker died before
+        // recording it: there is nothing to send, nor to throttle
+        Ok(_) if already_accepted(pool, email_client, message_key).await? => {
+            tracing::info!("The email was already accepted, not sending it again.");
+            DeliveryOutcome::Delivered
+        }
         Ok(email) => {
+            if let Some(outcome) = refusal(throttle.acquire(pool, TaskKind::Bulk).await?) {
+                forget_attempt(pool, message_key).await?;
+                release_task(transaction).await?;
+                return Ok(outcome);
+            }
             let issue = get_issue(pool, issue_id).await?;
-            if let Err(e) = email_client
-                .send_email(
-                    &email,
-                    &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
+            match personalise_issue(&issue, issue_id, recipient, tracker, base_url, hmac_secret) {
+                Ok((html_content, text_content)) => match email_client
+                    .send_email_with_key(
+                        &email,
+                        &issue.title,
+                        &html_content,
+                        &text_content,
+                        &message_key.to_string(),
+                    )
+                    .await
+                {
+                    Ok(()) => DeliveryOutcome::Delivered,
+                    Err(SendEmailError::RateLimited { retry_after }) => {
+                        // The email was refused, there is nothing to recover
+                        forget_attempt(pool, message_key).await?;
+                        // Every worker pauses, the task stays in the queue
+                        let pause = throttle.back_off(pool, retry_after).await?;
+                        release_task(transaction).await?;
+                        tracing::warn!(
+                            "The email provider is rate limiting us, pausing delivery for {:?}.",
+                            pause
+                        );
+                        return Ok(ExecutionOutcome::Throttled(pause));
+                    }
+                    Err(e) => {
+                        tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        "Failed to deliver issue to a confirmed subscriber. \
+                        Skipping.",
+                        );
+                        DeliveryOutcome::Failed
+                    }
+                },
+                Err(e) => {
+                    tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to render issue for a confirmed subscriber. \
+                    Skipping.",
+                    );
+                    DeliveryOutcome::Failed
+                }
+            }
+        }
+        Err(e) => {
+            tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Skipping a confirmed subscriber. \
+            Their stored contact details are invalid",
+            );
+            DeliveryOutcome::InvalidAddress
+        }
+    };
+
+    delete_task(transaction, issue_id, &email, message_key, outcome).await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+/// Send a ready-made email, e.g. a confirmation or a digest. It is not
+/// tied to an issue: its outcome only goes to the logs.
+#[tracing::instrument(skip_all, fields(message_key=%message_key))]
+async fn execute_prepared_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    throttle: &DeliveryThrottle,
+    transaction: PgTransaction,
+    message_key: Uuid,
+    kind: TaskKind,
+    email: PreparedEmail,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    match SubscriberEmail::parse(email.recipient) {
+        Ok(_) if already_accepted(pool, email_client, message_key).await? => {
+            tracing::info!("The email was already accepted, not sending it again.");
+        }
+        Ok(recipient) => {
+            if let Some(outcome) = refusal(throttle.acquire(pool, kind).await?) {
+                forget_attempt(pool, message_key).await?;
+                release_task(transaction).await?;
+                return Ok(outcome);
+            }
+            match email_client
+                .send_email_with_key(
+                    &recipient,
+                    &email.subject,
+                    &email.html_content,
+                    &email.text_content,
+                    &message_key.to_string(),
                 )
                 .await
             {
-                tracing::error!(
-                error.cause_chain = ?e,
-                error.message = %e,
-                "Failed to deliver issue to a confirmed subscriber. \
-                Skipping.",
-                );
-            };
+                Ok(()) => {}
+                Err(SendEmailError::RateLimited { retry_after }) => {
+                    forget_attempt(pool, message_key).await?;
+                    let pause = throttle.back_off(pool, retry_after).await?;
+                    release_task(transaction).await?;
+                    tracing::warn!(
+                        "The email provider is rate limiting us, pausing delivery for {:?}.",
+                        pause
+                    );
+                    return Ok(ExecutionOutcome::Throttled(pause));
+                }
+                Err(e) => {
+                    tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to send a {} email. Skipping.",
+                    kind.as_str(),
+                    );
+                }
+            }
         }
         Err(e) => {
             tracing::error!(
             error.cause_chain = ?e,
             error.message = %e,
-            "Skipping a confirmed subscriber. \
-            Their stored contact details are invalid",
+            "Skipping a {} email to an invalid address.",
+            kind.as_str(),
             );
         }
     }
 
-    delete_task(transaction, issue_id, &email).await?;
-
+    delete_prepared_task(transaction, message_key).await?;
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
+/// What to tell the worker when the throttle holds the task back,
+/// `None` if the email can go out.
+fn refusal(permit: Permit) -> Option<ExecutionOutcome> {
+    match permit {
+        Permit::Granted => None,
+        Permit::Wait(wait) => Some(ExecutionOutcome::Throttled(wait)),
+        Permit::QuotaReached(wait) => Some(ExecutionOutcome::BulkQuotaReached(wait)),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum DeliveryOutcome {
+    Delivered,
+    Failed,
+    InvalidAddress,
+    /// The subscriber left, or was suppressed, after the issue was published
+    Skipped,
+}
+
+impl DeliveryOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryOutcome::Delivered => "delivered",
+            DeliveryOutcome::Failed => "failed",
+            DeliveryOutcome::InvalidAddress => "invalid_address",
+            DeliveryOutcome::Skipped => "skipped",
+        }
+    }
+}
+
 type PgTransaction = Transaction<'static, Postgres>;
 
-#[tracing::instrument(skip_all)]
+enum Task {
+    Issue {
+        issue_id: Uuid,
+        subscriber_id: Uuid,
+    },
+    /// A transactional email, or a digest
+    Prepared(TaskKind, PreparedEmail),
+}
+
+struct PreparedEmail {
+    recipient: String,
+    subject: String,
+    html_content: String,
+    text_content: String,
+    traceparent: Option<String>,
+}
+
+/// Lock the next task, with its message key.
+///
+/// Each kind is looked for separately, in the order of the scheduler:
+/// both queries walk `issue_delivery_queue_order_idx`.
+#[tracing::instrument(skip(pool))]
 async fn dequeue_task(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+    kinds: &[TaskKind],
+) -> Result<Option<(PgTransaction, Uuid, Task)>, anyhow::Error> {
     let mut transaction = pool.begin().await?;
+    for kind in kinds {
+        let task = match kind {
+            TaskKind::Transactional => dequeue_transactional_task(&mut transaction).await?,
+            TaskKind::Bulk => dequeue_bulk_task(&mut transaction).await?,
+        };
+        if let Some((message_key, task)) = task {
+            return Ok(Some((transaction, message_key, task)));
+        }
+    }
+    Ok(None)
+}
+
+// The queue's check constraint guarantees the columns of each kind
+async fn dequeue_transactional_task(
+    transaction: &mut PgTransaction,
+) -> Result<Option<(Uuid, Task)>, anyhow::Error> {
     let r = sqlx::query!(
         r#"
-        SELECT newsletter_issue_id, subscriber_email
+        SELECT
+            message_key,
+            recipient AS "recipient!",
+            subject AS "subject!",
+            html_content AS "html_content!",
+            text_content AS "text_content!",
+            traceparent
         FROM issue_delivery_queue
+        WHERE kind = 'transactional'
+        ORDER BY priority DESC, enqueued_at
         FOR UPDATE
         SKIP LOCKED
         LIMIT 1
         "#,
     )
-    .fetch_optional(&mut transaction)
+    .fetch_optional(&mut *transaction)
     .await?;
-    if let Some(r) = r {
-        Ok(Some((
-            transaction,
-            r.newsletter_issue_id,
-            r.subscriber_email,
-        )))
-    } else {
-        Ok(None)
-    }
+    Ok(r.map(|r| {
+        let email = PreparedEmail {
+            recipient: r.recipient,
+            subject: r.subject,
+            html_content: r.html_content,
+            text_content: r.text_content,
+            traceparent: r.traceparent,
+        };
+        (
+            r.message_key,
+            Task::Prepared(TaskKind::Transactional, email),
+        )
+    }))
+}
+
+async fn dequeue_bulk_task(
+    transaction: &mut PgTransaction,
+) -> Result<Option<(Uuid, Task)>, anyhow::Error> {
+    let r = sqlx::query!(
+        r#"
+        SELECT
+            q.message_key,
+            q.newsletter_issue_id,
+            q.subscriber_id AS "subscriber_id!",
+            q.recipient,
+            q.subject,
+            q.html_content,
+            q.text_content
+        FROM issue_delivery_queue q
+        LEFT JOIN newsletter_issues i USING (newsletter_issue_id)
+        -- The tasks of paused issues stay in the queue,
+        -- digests are not tied to an issue
+        WHERE q.kind = 'bulk'
+        AND (q.newsletter_issue_id IS NULL OR i.delivery_status = 'active')
+        ORDER BY q.priority DESC, q.enqueued_at
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let r = match r {
+        Some(r) => r,
+        None => return Ok(None),
+    };
+    let task = match r.newsletter_issue_id {
+        Some(issue_id) => Task::Issue {
+            issue_id,
+            subscriber_id: r.subscriber_id,
+        },
+        None => Task::Prepared(
+            TaskKind::Bulk,
+            PreparedEmail {
+                recipient: r.recipient.context("A digest has no recipient.")?,
+                subject: r.subject.context("A digest has no subject.")?,
+                html_content: r.html_content.context("A digest has no HTML content.")?,
+                text_content: r.text_content.context("A digest has no text content.")?,
+                traceparent: None,
+            },
+        ),
+    };
+    Ok(Some((r.message_key, task)))
 }
 
 // When you've processed the task
 // delete it. This is why we don't
 // need a status field in the
-// `issue_delivery_queue`
+// `issue_delivery_queue`.
+// The outcome is kept in `issue_delivery_log`.
 #[tracing::instrument(skip_all)]
 async fn delete_task(
     mut transaction: PgTransaction,
     issue_id: Uuid,
     email: &str,
+    message_key: Uuid,
+    outcome: DeliveryOutcome,
 ) -> Result<(), anyhow::Error> {
+    remove_from_queue(&mut transaction, message_key).await?;
     sqlx::query!(
         r#"
-        DELETE FROM issue_delivery_queue
-        WHERE 
-        newsletter_issue_id = $1 AND
-        subscriber_email =$2
+        INSERT INTO issue_delivery_log (
+            newsletter_issue_id,
+            subscriber_email,
+            outcome,
+            recorded_at
+        )
+        VALUES ($1, $2, $3, now())
         "#,
         issue_id,
-        email
+        email,
+        outcome.as_str()
     )
     .execute(&mut transaction)
     .await?;
@@ -138,10 +731,95 @@ async fn delete_task(
     Ok(())
 }
 
+#[tracing::instrument(skip_all)]
+async fn delete_prepared_task(
+    mut transaction: PgTransaction,
+    message_key: Uuid,
+) -> Result<(), anyhow::Error> {
+    remove_from_queue(&mut transaction, message_key).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+async fn remove_from_queue(
+    transaction: &mut PgTransaction,
+    message_key: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM issue_delivery_queue WHERE message_key = $1",
+        message_key,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM issue_delivery_attempts WHERE message_key = $1",
+        message_key,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+/// Leave the task in the queue, to be attempted again later. The lock is
+/// released right away: a dropped transaction is only rolled back once its
+/// connection is reused, and `SKIP LOCKED` would hide the task until then.
+async fn release_task(transaction: PgTransaction) -> Result<(), anyhow::Error> {
+    transaction
+        .rollback()
+        .await
+        .context("Failed to release a delivery task.")?;
+    Ok(())
+}
+
+/// Whether a previous attempt at the task reached the provider, e.g. before
+/// the worker died. Otherwise, the attempt about to be made is recorded: it
+/// is committed right away, the task only once the email is out.
+#[tracing::instrument(skip(pool, email_client))]
+async fn already_accepted(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    message_key: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let recorded = sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_attempts (message_key, started_at)
+        VALUES ($1, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        message_key,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record a delivery attempt.")?
+    .rows_affected()
+        == 1;
+    if recorded {
+        return Ok(false);
+    }
+    email_client
+        .was_accepted(&message_key.to_string())
+        .await
+        .context("Failed to ask the email provider about a previous attempt.")
+}
+
+#[tracing::instrument(skip(pool))]
+async fn forget_attempt(pool: &PgPool, message_key: Uuid) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM issue_delivery_attempts WHERE message_key = $1",
+        message_key,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to forget a delivery attempt.")?;
+    Ok(())
+}
+
 struct NewsletterIssue {
     title: String,
     text_content: String,
     html_content: String,
+    track_opens: bool,
+    track_clicks: bool,
 }
 
 #[tracing::instrument(skip_all)]
@@ -149,7 +827,7 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     let issue = sqlx::query_as!(
         NewsletterIssue,
         r#"
-        SELECT title, text_content, html_content
+        SELECT title, text_content, html_content, track_opens, track_clicks
         FROM newsletter_issues
         WHERE 
         newsletter_issue_id = $1
@@ -161,9 +839,240 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     Ok(issue)
 }
 
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+#[tracing::instrument(skip_all)]
+async fn get_publish_traceparent(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<String>, anyhow::Error> {
+    let traceparent = sqlx::query!(
+        "SELECT publish_traceparent FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        issue_id,
+    )
+    .fetch_one(pool)
+    .await?
+    .publish_traceparent;
+    Ok(traceparent)
+}
+
+struct Recipient {
+    id: Uuid,
+    /// The current address of the subscriber
+    email: String,
+    name: String,
+    confirmed_at: DateTime<Utc>,
+    subscription_token: Option<String>,
+    /// Still confirmed on one of the lists of the issue, and not suppressed
+    wants_issue: bool,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_recipient(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    issue_id: Uuid,
+) -> Result<Recipient, anyhow::Error> {
+    // Subscribers confirmed before we started recording consent
+    // events fall back to their subscription date
+    let recipient = sqlx::query_as!(
+        Recipient,
+        r#"
+        SELECT
+            s.id,
+            s.email,
+            s.name,
+            COALESCE(
+                (
+                    SELECT MAX(c.occurred_at) FROM consent_events c
+                    WHERE c.subscriber_id = s.id
+                    AND c.event_type = 'subscription_confirmed'
+                ),
+                s.subscribed_at
+            ) AS "confirmed_at!",
+            (
+                -- Unsubscribe from one of the lists the issue went to
+                SELECT t.subscription_token FROM subscription_tokens t
+                JOIN newsletter_issue_lists il USING (list_id)
+                JOIN list_subscriptions ls USING (list_id, subscriber_id)
+                WHERE t.subscriber_id = s.id
+                AND il.newsletter_issue_id = $2
+                ORDER BY ls.status = 'confirmed' DESC
+                LIMIT 1
+            ) AS subscription_token,
+            (
+                s.status = 'confirmed'
+                AND lower(s.email) NOT IN (SELECT lower(email) FROM email_suppressions)
+                AND EXISTS (
+                    SELECT 1 FROM list_subscriptions ls
+                    JOIN newsletter_issue_lists il USING (list_id)
+                    WHERE ls.subscriber_id = s.id
+                    AND ls.status = 'confirmed'
+                    AND il.newsletter_issue_id = $2
+                )
+            ) AS "wants_issue!"
+        FROM subscriptions s
+        WHERE s.id = $1
+        "#,
+        subscriber_id,
+        issue_id,
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(recipient)
+}
+
+/// Render the issue templates for the recipient, append the link to
+/// the web view and, if enabled, embed the tracking pixel and rewrite
+/// the links.
+/// Returns the HTML and the plain text content.
+fn personalise_issue(
+    issue: &NewsletterIssue,
+    issue_id: Uuid,
+    recipient: Recipient,
+    tracker: &Tracker,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+) -> Result<(String, String), anyhow::Error> {
+    let subscription_token = recipient
+        .subscription_token
+        .context("The subscriber has no subscription token.")?;
+    let context = TemplateContext {
+        name: recipient.name,
+        unsubscribe_url: format!(
+            "{}/subscriptions/unsubscribe?subscription_token={}",
+            base_url, subscription_token
+        ),
+        preferences_url: preferences_url(base_url, recipient.id, hmac_secret),
+        confirmation_date: recipient.confirmed_at.format("%Y-%m-%d").to_string(),
+    };
+
+    // Links are rewritten before rendering: the unsubscribe link
+    // must not go through the click redirect.
+    let mut html_content = issue.html_content.clone();
+    if issue.track_clicks {
+        html_content = tracker.rewrite_links(&html_content, issue_id, recipient.id);
+    }
+    let mut html_content = NewsletterTemplate::parse(html_content)
+        .map_err(anyhow::Error::msg)?
+        .render_html(&context)?;
+    html_content = tracker.add_view_link(&html_content, issue_id, recipient.id);
+    if issue.track_opens {
+        html_content = tracker.add_open_pixel(&html_content, issue_id, recipient.id);
+    }
+    let mut text_content = NewsletterTemplate::parse(issue.text_content.clone())
+        .map_err(anyhow::Error::msg)?
+        .render_text(&context)?;
+    write!(
+        text_content,
+        "\n\nView this issue in your browser: {}\n",
+        tracker.view_url(issue_id, recipient.id)
+    )
+    .unwrap();
+    Ok((html_content, text_content))
+}
+
+/// Run `delivery.workers` concurrent workers, and queue the digests that
+/// are due, until shutdown is triggered and the workers have finished
+/// their current task.
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
+    metrics().watch_pool("worker", &connection_pool);
+    let throttle = configuration.delivery.throttle();
+    let tracker = Tracker::new(
+        configuration.application.base_url.clone(),
+        configuration.application.hmac_secret.clone(),
+    );
+    let new_tasks = Arc::new(Notify::new());
+
+    let mut workers = JoinSet::new();
+    workers.spawn(send_heartbeats(connection_pool.clone(), shutdown.clone()));
+    workers.spawn(listen_for_new_tasks(
+        connection_pool.clone(),
+        new_tasks.clone(),
+        shutdown.clone(),
+    ));
+    workers.spawn(run_digests_until_stopped(
+        connection_pool.clone(),
+        configuration.email_templates.templates(),
+        tracker.clone(),
+        configuration.application.base_url.clone(),
+        configuration.application.hmac_secret.clone(),
+        shutdown.clone(),
+    ));
+    for worker_id in 0..configuration.delivery.workers.max(1) {
+        workers.spawn(worker_loop(
+            worker_id,
+            connection_pool.clone(),
+            configuration.email_client.clone().client(),
+            throttle.clone(),
+            configuration.delivery.scheduler(),
+            tracker.clone(),
+            configuration.application.base_url.clone(),
+            configuration.application.hmac_secret.clone(),
+            new_tasks.clone(),
+            shutdown.clone(),
+        ));
+    }
+    let mut failure = None;
+    while let Some(outcome) = workers.join_next().await {
+        if let Err(e) = outcome.map_err(anyhow::Error::from).and_then(|o| o) {
+            // Stop the other workers without interrupting their current task
+            shutdown.trigger();
+            failure.get_or_insert(e);
+        }
+    }
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{QueueScheduler, TaskKind};
+    use std::time::Duration;
 
-    let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+    fn bulk_turns(scheduler: &QueueScheduler, dequeues: usize) -> usize {
+        (0..dequeues).filter(|_| scheduler.prefers_bulk()).count()
+    }
+
+    #[test]
+    fn bulk_tasks_get_their_share_of_dequeues() {
+        let scheduler = QueueScheduler::new(0.25);
+
+        assert!(!scheduler.prefers_bulk());
+        assert!(!scheduler.prefers_bulk());
+        assert!(!scheduler.prefers_bulk());
+        assert!(scheduler.prefers_bulk());
+        assert_eq!(bulk_turns(&scheduler, 100), 25);
+    }
+
+    #[test]
+    fn a_share_of_zero_never_prefers_bulk_tasks() {
+        let scheduler = QueueScheduler::new(0.0);
+
+        assert_eq!(bulk_turns(&scheduler, 100), 0);
+    }
+
+    #[test]
+    fn a_share_of_one_always_prefers_bulk_tasks() {
+        let scheduler = QueueScheduler::new(1.0);
+
+        assert_eq!(bulk_turns(&scheduler, 100), 100);
+    }
+
+    #[test]
+    fn held_bulk_tasks_are_left_aside_until_the_hold_expires() {
+        let scheduler = QueueScheduler::new(1.0);
+
+        scheduler.hold_bulk(Duration::from_secs(60));
+        assert_eq!(scheduler.next_kinds(), &[TaskKind::Transactional]);
+        scheduler.hold_bulk(Duration::ZERO);
+        assert_eq!(
+            scheduler.next_kinds(),
+            &[TaskKind::Bulk, TaskKind::Transactional]
+        );
+    }
 }
@@ -1,11 +1,26 @@
 pub mod authentication;
 pub mod configuration;
+pub mod consent;
+pub mod delivery_throttle;
 pub mod domain;
 pub mod email_client;
+pub mod email_templates;
+pub mod feed_digest;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod lists;
+pub mod markdown;
+pub mod metrics;
+pub mod newsletter_issues;
+pub mod profile;
 pub mod routes;
+pub mod sanitizer;
+pub mod segment;
 pub mod session_state;
+pub mod shutdown;
+pub mod signed_link;
 pub mod startup;
+pub mod subscriber_digest;
 pub mod telemetry;
+pub mod tracking;
 pub mod utils;
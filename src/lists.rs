@@ -0,0 +1,74 @@
+use crate::domain::ListSlug;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+/// The list that subscribe requests and issues go to when they
+/// do not name one: the only list before there were several.
+pub const DEFAULT_LIST: &str = "newsletter";
+
+#[derive(Debug)]
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+}
+
+#[tracing::instrument(name = "Get mailing lists", skip(pool))]
+pub async fn get_lists(pool: &PgPool) -> Result<Vec<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        "SELECT list_id, slug, name FROM lists ORDER BY created_at",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get a mailing list", skip(pool))]
+pub async fn get_list(pool: &PgPool, slug: &ListSlug) -> Result<Option<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        "SELECT list_id, slug, name FROM lists WHERE slug = $1",
+        slug.as_ref(),
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Unknown slugs are left out.
+#[tracing::instrument(name = "Get mailing lists by slug", skip(executor))]
+pub async fn get_lists_by_slug(
+    executor: impl PgExecutor<'_>,
+    slugs: &[ListSlug],
+) -> Result<Vec<MailingList>, sqlx::Error> {
+    let slugs: Vec<String> = slugs.iter().map(|s| s.as_ref().to_owned()).collect();
+    sqlx::query_as!(
+        MailingList,
+        "SELECT list_id, slug, name FROM lists WHERE slug = ANY($1) ORDER BY created_at",
+        &slugs[..],
+    )
+    .fetch_all(executor)
+    .await
+}
+
+/// Returns `None` if a list with the same slug already exists.
+#[tracing::instrument(name = "Create a mailing list", skip(pool))]
+pub async fn insert_list(
+    pool: &PgPool,
+    slug: &ListSlug,
+    name: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let list = sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, slug, name, created_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (slug) DO NOTHING
+        RETURNING list_id
+        "#,
+        Uuid::new_v4(),
+        slug.as_ref(),
+        name,
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(list.map(|r| r.list_id))
+}
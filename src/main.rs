@@ -1,41 +1,142 @@
 use std::fmt::{Debug, Display};
-use tokio::task::JoinError;
+use std::future::Future;
+use std::time::Duration;
+use tokio::task::{JoinError, JoinSet};
 use zero2prod::configuration::get_configuration;
+use zero2prod::feed_digest::run_digest_until_stopped;
 use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod::metrics::AdminServer;
+use zero2prod::shutdown::{wait_for_signal, Shutdown};
 use zero2prod::startup::Application;
-use zero2prod::telemetry::{get_subscriber, init_subscriber};
+use zero2prod::telemetry::{flush_spans, get_subscriber, init_subscriber, otlp_tracer};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    // Panic if we can't read configuration
+    let configuration = get_configuration().expect("Failed to read configuration.");
+
     // `Subscriber` Trait representing the functions required to collect trace data.
     // This subscriber has nothing to do with our email newsletter subscriber
-    let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
+    let tracer = configuration
+        .otlp
+        .as_ref()
+        .map(|settings| otlp_tracer("zero2prod", settings))
+        .transpose()?;
+    let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout, tracer);
     init_subscriber(subscriber);
+    let grace_period = Duration::from_secs(configuration.application.shutdown_grace_period_seconds);
 
-    // Panic if we can't read configuration
-    let configuration = get_configuration().expect("Failed to read configuration.");
-
-    // Run the API application
-    let application = Application::build(configuration.clone()).await?;
-    // spawn a `tokio` task
-    let application_task = tokio::spawn(application.run_until_stopped());
+    // SIGTERM and SIGINT ask every task to wrap up
+    let shutdown = Shutdown::new();
+    tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move {
+            match wait_for_signal().await {
+                Ok(()) => shutdown.trigger(),
+                Err(e) => tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to listen for shutdown signals"
+                ),
+            }
+        }
+    });
 
-    // Run the background worker for processing
-    // the newsletters from the queue
-    // by spawning it as a `tokio` task
-    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
+    // `zero2prod api` and `zero2prod worker` run the API and the
+    // delivery workers separately, so that they can be scaled independently.
+    // `zero2prod` alone runs both.
+    let mut tasks = JoinSet::new();
+    // Every process serves its own metrics
+    let admin_server = AdminServer::build(&configuration)?;
+    spawn_task(
+        &mut tasks,
+        "Admin server",
+        admin_server.run_until_stopped(shutdown.clone()),
+        &shutdown,
+    );
+    let mode = std::env::args().nth(1);
+    match mode.as_deref() {
+        None => {
+            let application = Application::build(configuration.clone()).await?;
+            spawn_task(
+                &mut tasks,
+                "API",
+                application.run_until_stopped(shutdown.clone()),
+                &shutdown,
+            );
+            spawn_task(
+                &mut tasks,
+                "Background worker",
+                run_worker_until_stopped(configuration.clone(), shutdown.clone()),
+                &shutdown,
+            );
+            spawn_task(
+                &mut tasks,
+                "Feed digest",
+                run_digest_until_stopped(configuration, shutdown.clone()),
+                &shutdown,
+            );
+        }
+        Some("api") => {
+            let application = Application::build(configuration.clone()).await?;
+            spawn_task(
+                &mut tasks,
+                "API",
+                application.run_until_stopped(shutdown.clone()),
+                &shutdown,
+            );
+            // Digests are created next to the API
+            spawn_task(
+                &mut tasks,
+                "Feed digest",
+                run_digest_until_stopped(configuration, shutdown.clone()),
+                &shutdown,
+            );
+        }
+        Some("worker") => {
+            spawn_task(
+                &mut tasks,
+                "Background worker",
+                run_worker_until_stopped(configuration, shutdown.clone()),
+                &shutdown,
+            );
+        }
+        Some(mode) => anyhow::bail!("Unknown mode {}, expected `api` or `worker`.", mode),
+    }
 
-    // `tokio::select!` will run these tasks concurrently
-    // and will return as soon as one of the two tasks completes
-    // or errors out.
-    tokio::select! {
-        o = application_task => report_exit("API", o),
-        o = worker_task => report_exit("Background worker", o),
-    };
+    // Either a signal was received, or one of the tasks exited
+    // and the others cannot go on without it
+    shutdown.triggered().await;
+    let stopped = tokio::time::timeout(grace_period, async {
+        while tasks.join_next().await.is_some() {}
+    })
+    .await;
+    if stopped.is_err() {
+        tracing::warn!(
+            "Some tasks did not stop within {:?}, exiting anyway.",
+            grace_period
+        );
+    }
+    flush_spans().await;
 
     Ok(())
 }
 
+/// Run the task in the background. Once it exits, shutdown is triggered
+/// for the other tasks.
+fn spawn_task<F, E>(tasks: &mut JoinSet<()>, task_name: &'static str, task: F, shutdown: &Shutdown)
+where
+    F: Future<Output = Result<(), E>> + Send + 'static,
+    E: Debug + Display + Send + 'static,
+{
+    let shutdown = shutdown.clone();
+    tasks.spawn(async move {
+        let outcome = tokio::spawn(task).await;
+        report_exit(task_name, outcome);
+        shutdown.trigger();
+    });
+}
+
 fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
     match outcome {
         Ok(Ok(())) => {
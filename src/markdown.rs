@@ -0,0 +1,129 @@
+use pulldown_cmark::{html, Event, LinkType, Options, Parser, Tag};
+
+/// The two bodies of an issue, generated from the same Markdown source.
+#[derive(Debug)]
+pub struct RenderedMarkdown {
+    pub html: String,
+    pub text: String,
+}
+
+pub fn render(markdown: &str) -> RenderedMarkdown {
+    RenderedMarkdown {
+        html: to_html(markdown),
+        text: to_plain_text(markdown),
+    }
+}
+
+fn to_html(markdown: &str) -> String {
+    let mut html = String::new();
+    html::push_html(&mut html, Parser::new_ext(markdown, options()));
+    // Link destinations are percent-encoded: restore template
+    // placeholders such as `[unsubscribe]({{unsubscribe_url}})`
+    let html = html.replace("%7B%7B", "{{").replace("%7D%7D", "}}");
+    // Markdown lets authors write raw HTML
+    ammonia::clean(&html)
+}
+
+fn to_plain_text(markdown: &str) -> String {
+    let mut text = String::new();
+    // The next item number of each open list, `None` for bullet lists
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    for event in Parser::new_ext(markdown, options()) {
+        match event {
+            Event::Start(Tag::List(first)) => {
+                if !lists.is_empty() && !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                lists.push(first);
+            }
+            Event::End(Tag::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    text.push('\n');
+                }
+            }
+            Event::Start(Tag::Item) => {
+                text.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                match lists.last_mut() {
+                    Some(Some(number)) => {
+                        text.push_str(&format!("{}. ", number));
+                        *number += 1;
+                    }
+                    _ => text.push_str("- "),
+                }
+            }
+            Event::End(Tag::Item) if !text.ends_with('\n') => text.push('\n'),
+            Event::End(Tag::Paragraph | Tag::Heading(..) | Tag::CodeBlock(_)) => {
+                if lists.is_empty() {
+                    text.push_str("\n\n");
+                } else {
+                    text.push('\n');
+                }
+            }
+            // Autolinks already show their destination
+            Event::End(Tag::Link(link_type, destination, _))
+                if !matches!(link_type, LinkType::Autolink | LinkType::Email) =>
+            {
+                text.push_str(&format!(" ({})", destination));
+            }
+            Event::Text(t) | Event::Code(t) => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak => text.push('\n'),
+            Event::Rule => text.push_str("----\n\n"),
+            // Raw HTML has no place in the plain text version
+            _ => {}
+        }
+    }
+    format!("{}\n", text.trim_end())
+}
+
+fn options() -> Options {
+    Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+
+    #[test]
+    fn markdown_is_rendered_to_html() {
+        let rendered = render("# Hello\n\nSome *emphasis*.");
+
+        assert_eq!(
+            rendered.html,
+            "<h1>Hello</h1>\n<p>Some <em>emphasis</em>.</p>\n"
+        );
+    }
+
+    #[test]
+    fn markdown_is_rendered_to_plain_text() {
+        let rendered = render(
+            "# Hello\n\nRead [this](https://example.com).\n\n1. one\n2. two\n\n- a\n- b\n\nBye",
+        );
+
+        assert_eq!(
+            rendered.text,
+            "Hello\n\nRead this (https://example.com).\n\n1. one\n2. two\n\n- a\n- b\n\nBye\n"
+        );
+    }
+
+    #[test]
+    fn raw_html_is_sanitized() {
+        let rendered = render("Hi <script>alert('boo')</script><b>there</b>");
+
+        assert!(!rendered.html.contains("<script>"));
+        assert!(rendered.html.contains("<b>there</b>"));
+        assert!(!rendered.text.contains("<b>"));
+    }
+
+    #[test]
+    fn template_placeholders_survive_rendering() {
+        let rendered = render("Hi {{name}}, [unsubscribe]({{unsubscribe_url}})");
+
+        assert!(rendered.html.contains("Hi {{name}}"));
+        assert!(rendered.html.contains(r#"href="{{unsubscribe_url}}""#));
+        assert_eq!(
+            rendered.text,
+            "Hi {{name}}, unsubscribe ({{unsubscribe_url}})\n"
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use crate::configuration::Settings;
+use crate::issue_delivery_worker::TaskKind;
+use crate::shutdown::Shutdown;
+use crate::startup::get_connection_pool;
+use crate::utils::e500;
+use actix_web::body::MessageBody;
+use actix_web::dev::{Server, ServiceRequest, ServiceResponse};
+use actix_web::http::header::ContentType;
+use actix_web::web::Data;
+use actix_web::{web, App, HttpResponse, HttpServer};
+use actix_web_lab::middleware::Next;
+use anyhow::Context;
+use prometheus::{
+    GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use sqlx::PgPool;
+use std::net::TcpListener;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+/// The metrics of the process, exported in the Prometheus text format
+/// on the admin port.
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+    /// By outcome: `subscribed`, `invalid` or `error`
+    pub subscriptions: IntCounterVec,
+    /// By outcome: `confirmed`, `unknown_token` or `error`
+    pub confirmations: IntCounterVec,
+    /// By outcome: `success`, `failure` or `error`
+    pub logins: IntCounterVec,
+    /// By provider and outcome: `sent`, `rate_limited` or `failed`
+    pub emails: IntCounterVec,
+    queue_depth: IntGaugeVec,
+    queue_oldest_task_age: GaugeVec,
+    db_pool_connections: IntGaugeVec,
+    /// The pools reported on, sampled at scrape time
+    pools: Mutex<Vec<(&'static str, PgPool)>>,
+}
+
+/// The metrics of the process.
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(|| Metrics::new().expect("Failed to register the metrics."))
+}
+
+impl Metrics {
+    fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new();
+        let metrics = Self {
+            http_requests: IntCounterVec::new(
+                Opts::new("http_requests_total", "HTTP requests, by route"),
+                &["method", "route", "status"],
+            )?,
+            http_request_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "http_request_duration_seconds",
+                    "HTTP request latency, by route",
+                ),
+                &["method", "route"],
+            )?,
+            subscriptions: IntCounterVec::new(
+                Opts::new("subscriptions_total", "Subscription attempts, by outcome"),
+                &["outcome"],
+            )?,
+            confirmations: IntCounterVec::new(
+                Opts::new(
+                    "subscription_confirmations_total",
+                    "Confirmation attempts, by outcome",
+                ),
+                &["outcome"],
+            )?,
+            logins: IntCounterVec::new(
+                Opts::new("logins_total", "Login attempts, by outcome"),
+                &["outcome"],
+            )?,
+            emails: IntCounterVec::new(
+                Opts::new("emails_total", "Emails handed to the provider, by outcome"),
+                &["provider", "outcome"],
+            )?,
+            queue_depth: IntGaugeVec::new(
+                Opts::new("delivery_queue_depth", "Tasks in the delivery queue"),
+                &["kind"],
+            )?,
+            queue_oldest_task_age: GaugeVec::new(
+                Opts::new(
+                    "delivery_queue_oldest_task_age_seconds",
+                    "How long the oldest task has been waiting in the delivery queue",
+                ),
+                &["kind"],
+            )?,
+            db_pool_connections: IntGaugeVec::new(
+                Opts::new("db_pool_connections", "Connections of the Postgres pools"),
+                &["pool", "state"],
+            )?,
+            pools: Mutex::new(Vec::new()),
+            registry,
+        };
+        metrics
+            .registry
+            .register(Box::new(metrics.http_requests.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.http_request_duration.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.subscriptions.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.confirmations.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.logins.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.emails.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.queue_depth.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.queue_oldest_task_age.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.db_pool_connections.clone()))?;
+        Ok(metrics)
+    }
+
+    /// Report the utilisation of the pool under the given name,
+    /// instead of any pool watched under that name before.
+    pub fn watch_pool(&self, name: &'static str, pool: &PgPool) {
+        let mut pools = self.pools.lock().unwrap();
+        pools.retain(|(watched, _)| *watched != name);
+        pools.push((name, pool.clone()));
+    }
+
+    /// Sample the gauges, then encode every metric.
+    async fn render(&self, pool: &PgPool) -> Result<String, anyhow::Error> {
+        let queue = sqlx::query!(
+            r#"
+            SELECT
+                kind,
+                COUNT(*) AS "depth!",
+                EXTRACT(EPOCH FROM now() - MIN(enqueued_at))::float8 AS "oldest_task_age!"
+            FROM issue_delivery_queue
+            GROUP BY kind
+            "#,
+        )
+        .fetch_all(pool)
+        .await
+        .context("Failed to measure the delivery queue.")?;
+        // Concurrent scrapes must not see each other's samples
+        let pools = self.pools.lock().unwrap();
+        // An empty queue has no rows
+        for kind in [TaskKind::Transactional, TaskKind::Bulk] {
+            self.queue_depth.with_label_values(&[kind.as_str()]).set(0);
+            self.queue_oldest_task_age
+                .with_label_values(&[kind.as_str()])
+                .set(0.0);
+        }
+        for r in queue {
+            self.queue_depth.with_label_values(&[&r.kind]).set(r.depth);
+            self.queue_oldest_task_age
+                .with_label_values(&[&r.kind])
+                .set(r.oldest_task_age);
+        }
+        for (name, pool) in pools.iter() {
+            let idle = pool.num_idle() as i64;
+            self.db_pool_connections
+                .with_label_values(&[name, "idle"])
+                .set(idle);
+            self.db_pool_connections
+                .with_label_values(&[name, "in_use"])
+                .set(i64::from(pool.size()) - idle);
+        }
+
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .context("Failed to encode the metrics.")
+    }
+}
+
+/// Count every request, and how long it took, by route.
+pub async fn record_http_metrics(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let started_at = Instant::now();
+    let method = req.method().to_string();
+    // Paths carry ids: the route pattern keeps the labels bounded
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    let response = next.call(req).await;
+    let status = match &response {
+        Ok(response) => response.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    let metrics = metrics();
+    metrics
+        .http_requests
+        .with_label_values(&[&method, &route, status.as_str()])
+        .inc();
+    metrics
+        .http_request_duration
+        .with_label_values(&[&method, &route])
+        .observe(started_at.elapsed().as_secs_f64());
+    response
+}
+
+/// Serves `/metrics` on the admin port, away from the public API.
+pub struct AdminServer {
+    port: u16,
+    server: Server,
+}
+
+impl AdminServer {
+    pub fn build(configuration: &Settings) -> Result<Self, anyhow::Error> {
+        let address = format!(
+            "{}:{}",
+            configuration.application.host, configuration.application.admin_port
+        );
+        let listener = TcpListener::bind(&address)?;
+        let port = listener.local_addr().unwrap().port();
+        let db_pool = Data::new(get_connection_pool(&configuration.database));
+        let server = HttpServer::new(move || {
+            App::new()
+                .route("/metrics", web::get().to(metrics_endpoint))
+                .app_data(db_pool.clone())
+        })
+        .disable_signals()
+        .workers(1)
+        .listen(listener)?
+        .run();
+        Ok(Self { port, server })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub async fn run_until_stopped(self, shutdown: Shutdown) -> Result<(), std::io::Error> {
+        let handle = self.server.handle();
+        tokio::spawn(async move {
+            shutdown.triggered().await;
+            handle.stop(true).await;
+        });
+        self.server.await
+    }
+}
+
+async fn metrics_endpoint(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let body = metrics().render(&pool).await.map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType(prometheus::TEXT_FORMAT.parse().unwrap()))
+        .body(body))
+}
@@ -0,0 +1,417 @@
+use crate::domain::ListSlug;
+use crate::issue_delivery_worker::NEW_TASKS_CHANNEL;
+use crate::segment::Segment;
+use crate::telemetry::current_traceparent;
+use anyhow::Context;
+use sqlx::{PgExecutor, Postgres, QueryBuilder, Transaction};
+use uuid::Uuid;
+
+/// Drafts wait for an admin to publish them, published issues are
+/// delivered to their audience.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IssueStatus {
+    Draft,
+    Published,
+}
+
+impl IssueStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IssueStatus::Draft => "draft",
+            IssueStatus::Published => "published",
+        }
+    }
+}
+
+/// Workers skip the tasks of paused issues, cancelled issues have none left.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeliveryStatus {
+    Active,
+    Paused,
+    Cancelled,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Active => "active",
+            DeliveryStatus::Paused => "paused",
+            DeliveryStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl TryFrom<String> for DeliveryStatus {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "active" => Ok(Self::Active),
+            "paused" => Ok(Self::Paused),
+            "cancelled" => Ok(Self::Cancelled),
+            other => Err(format!("{} is not a valid delivery status.", other)),
+        }
+    }
+}
+
+pub struct NewsletterIssue {
+    pub title: String,
+    pub text_content: String,
+    /// Sanitized, with its CSS inlined
+    pub html_content: String,
+    pub markdown_content: Option<String>,
+    pub track_opens: bool,
+    pub track_clicks: bool,
+    /// Listed in the public archive
+    pub is_public: bool,
+    pub lists: Vec<ListSlug>,
+    /// Only the subscribers of the lists matching it get the issue
+    pub segment: Option<Segment>,
+    /// What the sanitizer removed from the submitted HTML
+    pub warnings: Vec<String>,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn insert_newsletter_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue: &NewsletterIssue,
+    list_ids: &[Uuid],
+    status: IssueStatus,
+) -> Result<Uuid, sqlx::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues(
+        newsletter_issue_id,
+        title,
+        text_content,
+        html_content,
+        markdown_content,
+        published_at,
+        track_opens,
+        track_clicks,
+        segment,
+        is_public,
+        status
+        )
+        VALUES ($1, $2, $3, $4, $5, now(), $6, $7, $8, $9, $10)
+        "#,
+        newsletter_issue_id,
+        issue.title,
+        issue.text_content,
+        issue.html_content,
+        issue.markdown_content,
+        issue.track_opens,
+        issue.track_clicks,
+        issue.segment.as_ref().map(|s| s.to_string()),
+        issue.is_public,
+        status.as_str(),
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+        SELECT $1, UNNEST($2::uuid[])
+        "#,
+        newsletter_issue_id,
+        list_ids,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(newsletter_issue_id)
+}
+
+/// Publish a draft to the lists and segment it was written for.
+/// Returns `false` if there is no such draft, e.g. it was already published.
+#[tracing::instrument(skip(transaction))]
+pub async fn publish_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let draft = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'published', published_at = now()
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+        RETURNING segment
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to publish the draft.")?;
+    let draft = match draft {
+        Some(draft) => draft,
+        None => return Ok(false),
+    };
+    let segment = draft
+        .segment
+        .as_deref()
+        .map(Segment::parse)
+        .transpose()
+        .map_err(anyhow::Error::msg)
+        .context("The segment of the draft is invalid.")?;
+    let list_ids: Vec<_> = sqlx::query!(
+        "SELECT list_id FROM newsletter_issue_lists WHERE newsletter_issue_id = $1",
+        newsletter_issue_id,
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to retrieve the lists of the draft.")?
+    .into_iter()
+    .map(|r| r.list_id)
+    .collect();
+    enqueue_delivery_tasks(
+        transaction,
+        newsletter_issue_id,
+        &list_ids,
+        segment.as_ref(),
+    )
+    .await
+    .context("Failed to enqueue delivery tasks")?;
+    Ok(true)
+}
+
+/// Subscribers of several of the lists get a single copy.
+/// Idle workers are woken up once the transaction commits, and their
+/// spans are linked to the current one.
+///
+/// Subscribers who asked for a digest get the issue in their next one.
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    list_ids: &[Uuid],
+    segment: Option<&Segment>,
+) -> Result<(), sqlx::Error> {
+    let mut query = QueryBuilder::new(
+        "INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_id) SELECT ",
+    );
+    query.push_bind(newsletter_issue_id).push(", s.id");
+    push_audience(&mut query, list_ids, segment);
+    query.push(" AND s.digest_frequency = 'immediate'");
+    let enqueued = query
+        .build()
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+    let mut query = QueryBuilder::new(
+        "INSERT INTO digest_queue (newsletter_issue_id, subscriber_id, held_at) SELECT ",
+    );
+    query.push_bind(newsletter_issue_id).push(", s.id, now()");
+    push_audience(&mut query, list_ids, segment);
+    query.push(" AND s.digest_frequency <> 'immediate'");
+    query.build().execute(&mut *transaction).await?;
+    sqlx::query!(
+        "UPDATE newsletter_issues SET publish_traceparent = $2 WHERE newsletter_issue_id = $1",
+        newsletter_issue_id,
+        current_traceparent(),
+    )
+    .execute(&mut *transaction)
+    .await?;
+    if enqueued > 0 {
+        // Notifications are only sent on commit
+        sqlx::query("SELECT pg_notify($1, '')")
+            .bind(NEW_TASKS_CHANNEL)
+            .execute(&mut *transaction)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Returns `false` unless the delivery of a published issue was active.
+#[tracing::instrument(skip(executor))]
+pub async fn pause_delivery(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let paused = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues SET delivery_status = 'paused'
+        WHERE newsletter_issue_id = $1
+        AND status = 'published' AND delivery_status = 'active'
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(paused == 1)
+}
+
+/// Returns `false` unless the delivery was paused.
+#[tracing::instrument(skip(transaction))]
+pub async fn resume_delivery(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let resumed = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues SET delivery_status = 'active'
+        WHERE newsletter_issue_id = $1 AND delivery_status = 'paused'
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected()
+        == 1;
+    if resumed {
+        // The workers went idle if the paused issue was all that was left
+        sqlx::query("SELECT pg_notify($1, '')")
+            .bind(NEW_TASKS_CHANNEL)
+            .execute(&mut *transaction)
+            .await?;
+    }
+    Ok(resumed)
+}
+
+/// Drop the tasks left in the queue, recording them as cancelled in the
+/// delivery log. Returns how many there were, `None` if the issue is not
+/// published or was already cancelled.
+///
+/// The tasks in the hands of a worker are waited for: their email is
+/// delivered.
+#[tracing::instrument(skip(transaction))]
+pub async fn cancel_delivery(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<u64>, sqlx::Error> {
+    let cancelled = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues SET delivery_status = 'cancelled'
+        WHERE newsletter_issue_id = $1
+        AND status = 'published' AND delivery_status <> 'cancelled'
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected()
+        == 1;
+    if !cancelled {
+        return Ok(None);
+    }
+    // The issue is left out of the digests it was held for
+    sqlx::query!(
+        "DELETE FROM digest_queue WHERE newsletter_issue_id = $1",
+        newsletter_issue_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let dropped = sqlx::query!(
+        r#"
+        WITH dropped AS (
+            DELETE FROM issue_delivery_queue
+            WHERE newsletter_issue_id = $1
+            RETURNING newsletter_issue_id, subscriber_id
+        )
+        INSERT INTO issue_delivery_log (
+            newsletter_issue_id,
+            subscriber_email,
+            outcome,
+            recorded_at
+        )
+        SELECT d.newsletter_issue_id, s.email, 'cancelled', now()
+        FROM dropped d
+        JOIN subscriptions s ON s.id = d.subscriber_id
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    Ok(Some(dropped))
+}
+
+pub struct DeliveryProgress {
+    pub title: String,
+    pub delivery_status: DeliveryStatus,
+    pub queued: i64,
+    pub delivered: i64,
+    /// Including invalid addresses
+    pub failed: i64,
+    pub cancelled: i64,
+    /// The subscriber left after the issue was published
+    pub skipped: i64,
+}
+
+/// Where the delivery of a published issue stands, `None` if there is no such issue.
+#[tracing::instrument(skip(executor))]
+pub async fn get_delivery_progress(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<DeliveryProgress>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            i.title,
+            i.delivery_status,
+            (
+                SELECT COUNT(*) FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id
+            ) AS "queued!",
+            COUNT(*) FILTER (WHERE l.outcome = 'delivered') AS "delivered!",
+            COUNT(*) FILTER (WHERE l.outcome IN ('failed', 'invalid_address')) AS "failed!",
+            COUNT(*) FILTER (WHERE l.outcome = 'cancelled') AS "cancelled!",
+            COUNT(*) FILTER (WHERE l.outcome = 'skipped') AS "skipped!"
+        FROM newsletter_issues i
+        LEFT JOIN issue_delivery_log l USING (newsletter_issue_id)
+        WHERE i.newsletter_issue_id = $1 AND i.status = 'published'
+        GROUP BY i.newsletter_issue_id
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(executor)
+    .await
+    .context("Failed to retrieve the delivery progress.")?;
+    row.map(|r| {
+        Ok(DeliveryProgress {
+            title: r.title,
+            delivery_status: r.delivery_status.try_into().map_err(anyhow::Error::msg)?,
+            queued: r.queued,
+            delivered: r.delivered,
+            failed: r.failed,
+            cancelled: r.cancelled,
+            skipped: r.skipped,
+        })
+    })
+    .transpose()
+}
+
+/// How many subscribers the issue would go to, if it was published now.
+#[tracing::instrument(skip_all)]
+pub async fn count_audience(
+    executor: impl PgExecutor<'_>,
+    list_ids: &[Uuid],
+    segment: Option<&Segment>,
+) -> Result<i64, sqlx::Error> {
+    let mut query = QueryBuilder::new("SELECT COUNT(*)");
+    push_audience(&mut query, list_ids, segment);
+    let (count,): (i64,) = query.build_query_as().fetch_one(executor).await?;
+    Ok(count)
+}
+
+/// The confirmed subscribers of any of the lists, matching the segment,
+/// whose address is not suppressed. `s` is the `subscriptions` row.
+fn push_audience(
+    query: &mut QueryBuilder<'_, Postgres>,
+    list_ids: &[Uuid],
+    segment: Option<&Segment>,
+) {
+    query
+        .push(
+            " FROM subscriptions s \
+            WHERE s.status = 'confirmed' \
+            AND lower(s.email) NOT IN (SELECT lower(email) FROM email_suppressions) \
+            AND EXISTS (SELECT 1 FROM list_subscriptions ls \
+            WHERE ls.subscriber_id = s.id AND ls.status = 'confirmed' AND ls.list_id = ANY(",
+        )
+        .push_bind(list_ids.to_vec())
+        .push("))");
+    if let Some(segment) = segment {
+        query.push(" AND ");
+        segment.push_sql(query);
+    }
+}
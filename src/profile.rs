@@ -0,0 +1,120 @@
+use crate::domain::{ProfileField, ProfileFieldKey, ProfileFieldKind, ProfileValue};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+struct ProfileFieldRow {
+    key: String,
+    label: String,
+    kind: String,
+    options: Vec<String>,
+}
+
+impl TryFrom<ProfileFieldRow> for ProfileField {
+    type Error = String;
+
+    fn try_from(row: ProfileFieldRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            key: ProfileFieldKey::parse(row.key)?,
+            label: row.label,
+            kind: ProfileFieldKind::parse(&row.kind, row.options)?,
+        })
+    }
+}
+
+/// The extra profile fields subscribers can fill in, in the order
+/// they were defined.
+#[tracing::instrument(name = "Get profile fields", skip(pool))]
+pub async fn get_profile_fields(pool: &PgPool) -> Result<Vec<ProfileField>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        ProfileFieldRow,
+        "SELECT key, label, kind, options FROM profile_fields ORDER BY created_at",
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the profile fields.")?;
+    rows.into_iter()
+        .map(|row| ProfileField::try_from(row).map_err(anyhow::Error::msg))
+        .collect::<Result<Vec<_>, _>>()
+        .context("A stored profile field is invalid.")
+}
+
+/// Returns `false` if a field with the same key already exists.
+#[tracing::instrument(name = "Create a profile field", skip(pool))]
+pub async fn insert_profile_field(
+    pool: &PgPool,
+    field: &ProfileField,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO profile_fields (key, label, kind, options, created_at)
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (key) DO NOTHING
+        "#,
+        field.key.as_ref(),
+        field.label,
+        field.kind.as_str(),
+        field.kind.options(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to create a profile field.")?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// The values a subscriber gave, by field key.
+#[tracing::instrument(name = "Get subscriber profile", skip(pool))]
+pub async fn get_profile_values(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<HashMap<String, String>, anyhow::Error> {
+    let rows = sqlx::query!(
+        "SELECT field_key, value FROM subscriber_profile_values WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the profile of a subscriber.")?;
+    Ok(rows.into_iter().map(|r| (r.field_key, r.value)).collect())
+}
+
+/// Set a profile value, or clear it.
+#[tracing::instrument(name = "Save subscriber profile value", skip(transaction, value))]
+pub async fn save_profile_value(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    key: &ProfileFieldKey,
+    value: Option<&ProfileValue>,
+) -> Result<(), sqlx::Error> {
+    match value {
+        Some(value) => {
+            sqlx::query!(
+                r#"
+                INSERT INTO subscriber_profile_values (subscriber_id, field_key, value, updated_at)
+                VALUES ($1, $2, $3, now())
+                ON CONFLICT (subscriber_id, field_key) DO UPDATE
+                SET value = EXCLUDED.value, updated_at = EXCLUDED.updated_at
+                "#,
+                subscriber_id,
+                key.as_ref(),
+                value.as_ref(),
+            )
+            .execute(transaction)
+            .await?;
+        }
+        None => {
+            sqlx::query!(
+                r#"
+                DELETE FROM subscriber_profile_values
+                WHERE subscriber_id = $1 AND field_key = $2
+                "#,
+                subscriber_id,
+                key.as_ref(),
+            )
+            .execute(transaction)
+            .await?;
+        }
+    }
+    Ok(())
+}
@@ -2,7 +2,9 @@ use actix_web::http::header::{ContentType, LOCATION};
 use actix_web::web;
 use actix_web::HttpResponse;
 use anyhow::Context;
+use htmlescape::encode_minimal;
 use sqlx::PgPool;
+use std::fmt::Write;
 use uuid::Uuid;
 
 use crate::session_state::TypedSession;
@@ -19,6 +21,20 @@ pub async fn admin_dashboard(
             .insert_header((LOCATION, "/login"))
             .finish());
     };
+    let mut engagement_html = String::new();
+    for issue in get_issue_engagement(&pool).await.map_err(e500)? {
+        writeln!(
+            engagement_html,
+            r#"<tr><td><a href="/admin/newsletters/{}">{}</a></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>"#,
+            issue.newsletter_issue_id,
+            encode_minimal(&issue.title),
+            encode_minimal(&issue.published_at),
+            issue.delivered,
+            rate(issue.track_opens, issue.opened, issue.delivered),
+            rate(issue.track_clicks, issue.clicked, issue.delivered),
+        )
+        .unwrap();
+    }
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -34,12 +50,23 @@ pub async fn admin_dashboard(
     <ol>
         <li><a href="/admin/password">Change password</a></li>
         <li><a href="/admin/newsletters">Send a newsletter issue</a></li>
+        <li><a href="/admin/newsletters/drafts">Drafts</a></li>
+        <li><a href="/admin/subscribers">Subscribers</a></li>
+        <li><a href="/admin/lists">Mailing lists</a></li>
+        <li><a href="/admin/profile-fields">Profile fields</a></li>
+        <li><a href="/admin/suppressions">Suppression list</a></li>
+        <li><a href="/admin/email-templates">Email templates</a></li>
 <li>
         <form name="logoutForm" action="/admin/logout" method="post">
             <input type="submit" value="Logout">
         </form>
 </li>
     </ol>
+    <p>Recent issues:</p>
+    <table>
+        <tr><th>Title</th><th>Published at</th><th>Delivered</th><th>Open rate</th><th>Click rate</th></tr>
+        {engagement_html}
+    </table>
 </body>
 </html>"#,
         )))
@@ -60,3 +87,60 @@ pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow
     .context("Failed to perform a query to retrieve a username.")?;
     Ok(row.username)
 }
+
+struct IssueEngagement {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: String,
+    track_opens: bool,
+    track_clicks: bool,
+    delivered: i64,
+    opened: i64,
+    clicked: i64,
+}
+
+/// Share of the recipients who opened (or clicked) at least once.
+fn rate(tracked: bool, engaged: i64, delivered: i64) -> String {
+    if !tracked || delivered == 0 {
+        return "-".into();
+    }
+    format!("{:.1}%", engaged as f64 * 100.0 / delivered as f64)
+}
+
+#[tracing::instrument(name = "Get issue engagement", skip(pool))]
+async fn get_issue_engagement(pool: &PgPool) -> Result<Vec<IssueEngagement>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        IssueEngagement,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at,
+            i.track_opens,
+            i.track_clicks,
+            (
+                SELECT COUNT(*) FROM issue_delivery_log l
+                WHERE l.newsletter_issue_id = i.newsletter_issue_id
+                AND l.outcome = 'delivered'
+            ) AS "delivered!",
+            (
+                SELECT COUNT(DISTINCT subscriber_id) FROM issue_engagement_events e
+                WHERE e.newsletter_issue_id = i.newsletter_issue_id
+                AND e.event_type = 'open'
+            ) AS "opened!",
+            (
+                SELECT COUNT(DISTINCT subscriber_id) FROM issue_engagement_events e
+                WHERE e.newsletter_issue_id = i.newsletter_issue_id
+                AND e.event_type = 'click'
+            ) AS "clicked!"
+        FROM newsletter_issues i
+        WHERE i.status = 'published'
+        ORDER BY i.published_at DESC
+        LIMIT 10
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve the issue engagement.")?;
+    Ok(issues)
+}
@@ -0,0 +1,161 @@
+use super::parse_template;
+use crate::email_templates::{render, EmailTemplate, EmailTemplates, RenderedEmail};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn email_templates_list(
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let edited = get_edited_template_names(&pool).await.map_err(e500)?;
+
+    let mut rows_html = String::new();
+    for template in EmailTemplate::ALL {
+        let name = template.as_str();
+        writeln!(
+            rows_html,
+            r#"<tr><td><a href="/admin/email-templates/{name}">{name}</a></td><td>{}</td></tr>"#,
+            if edited.iter().any(|n| n == name) {
+                "Edited"
+            } else {
+                "Default"
+            },
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Email templates</title>
+</head>
+<body>
+    <p>The emails sent to subscribers outside of newsletter issues.
+    Every template is wrapped in the <code>layout</code> template.</p>
+    <table>
+        <tr><th>Template</th><th>Version</th></tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+/// Edit a template, next to a preview of its current version.
+pub async fn email_template_form(
+    name: web::Path<String>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    templates: web::Data<EmailTemplates>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let template = parse_template(&name)?;
+    let name = template.as_str();
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+
+    let layout = templates
+        .source(&pool, EmailTemplate::Layout)
+        .await
+        .map_err(e500)?;
+    let source = templates.source(&pool, template).await.map_err(e500)?;
+    let preview = match render(&layout, &source, template, &template.sample_data()) {
+        Ok(email) => preview_html(&email),
+        Err(e) => format!(
+            "<p><i>The saved template does not render: {}</i></p>",
+            encode_minimal(&e.to_string())
+        ),
+    };
+    let placeholders = placeholders_html(template);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Edit the {name} email</title>
+</head>
+<body>
+    {msg_html}
+    <p>Available placeholders: {placeholders}.</p>
+    <form action="/admin/email-templates/{name}" method="post">
+        <label>HTML version:<br>
+            <textarea name="html_body" rows="20" cols="80">
+{html_body}</textarea>
+        </label>
+        <br>
+        <label>Plain text version:<br>
+            <textarea name="text_body" rows="20" cols="80">
+{text_body}</textarea>
+        </label>
+        <br>
+        <button type="submit" formaction="/admin/email-templates/{name}/preview">Preview</button>
+        <button type="submit">Save</button>
+    </form>
+    <form action="/admin/email-templates/{name}/reset" method="post">
+        <button type="submit">Restore the default version</button>
+    </form>
+    <p>Current version, with sample data:</p>
+    {preview}
+    <p><a href="/admin/email-templates">&lt;- Back</a></p>
+</body>
+</html>"#,
+            html_body = encode_minimal(&source.html),
+            text_body = encode_minimal(&source.text),
+        )))
+}
+
+pub(super) fn preview_html(email: &RenderedEmail) -> String {
+    format!(
+        r#"<p>Subject: {subject}</p>
+    <iframe sandbox srcdoc="{html}" width="600" height="400"></iframe>
+    <pre>{text}</pre>"#,
+        subject = encode_minimal(&email.subject),
+        html = encode_attribute(&email.html),
+        text = encode_minimal(&email.text),
+    )
+}
+
+pub(super) fn placeholders_html(template: EmailTemplate) -> String {
+    let sample_data = template.sample_data();
+    let mut placeholders: Vec<_> = sample_data
+        .as_object()
+        .map(|data| data.keys().cloned().collect())
+        .unwrap_or_default();
+    placeholders.sort();
+    placeholders
+        .iter()
+        .map(|name| {
+            // The layout includes the rendered body as is, and links
+            // must not be escaped: it would mangle their query string
+            if (template == EmailTemplate::Layout && name == "body") || name.ends_with("_link") {
+                format!("<code>{{{{{{{}}}}}}}</code>", name)
+            } else {
+                format!("<code>{{{{{}}}}}</code>", name)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[tracing::instrument(name = "Get edited email template names", skip(pool))]
+async fn get_edited_template_names(pool: &PgPool) -> Result<Vec<String>, anyhow::Error> {
+    let rows = sqlx::query!("SELECT name FROM email_templates")
+        .fetch_all(pool)
+        .await
+        .context("Failed to retrieve the edited email templates.")?;
+    Ok(rows.into_iter().map(|r| r.name).collect())
+}
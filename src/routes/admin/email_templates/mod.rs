@@ -0,0 +1,12 @@
+mod get;
+mod post;
+
+pub use get::{email_template_form, email_templates_list};
+pub use post::{preview_email_template, reset_email_template, save_email_template};
+
+use crate::email_templates::EmailTemplate;
+
+fn parse_template(name: &str) -> Result<EmailTemplate, actix_web::Error> {
+    EmailTemplate::parse(name)
+        .ok_or_else(|| actix_web::error::ErrorNotFound("There is no such email template."))
+}
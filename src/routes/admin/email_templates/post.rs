@@ -0,0 +1,129 @@
+use super::get::{placeholders_html, preview_html};
+use super::parse_template;
+use crate::authentication::UserId;
+use crate::email_templates::{
+    render, reset_template, save_template, EmailTemplate, EmailTemplates, RenderedEmail,
+    TemplateSource,
+};
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use handlebars::RenderError;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    html_body: String,
+    text_body: String,
+}
+
+impl From<FormData> for TemplateSource {
+    fn from(form: FormData) -> Self {
+        Self {
+            html: form.html_body,
+            text: form.text_body,
+        }
+    }
+}
+
+/// Templates are only saved if they render with the sample data:
+/// a typo in a placeholder would otherwise break every email sent.
+pub async fn save_email_template(
+    name: web::Path<String>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    templates: web::Data<EmailTemplates>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let template = parse_template(&name)?;
+    let location = format!("/admin/email-templates/{}", template.as_str());
+    let source = TemplateSource::from(form.0);
+
+    if let Err(e) = render_sample(&pool, &templates, template, &source).await? {
+        FlashMessage::error(format!("The template is not valid: {}", e)).send();
+        return Ok(see_other(&location));
+    }
+    save_template(&pool, template, &source, *user_id)
+        .await
+        .map_err(e500)?;
+    FlashMessage::info("The template has been saved.").send();
+    Ok(see_other(&location))
+}
+
+/// Render unsaved edits with the sample data.
+pub async fn preview_email_template(
+    name: web::Path<String>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    templates: web::Data<EmailTemplates>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let template = parse_template(&name)?;
+    let name = template.as_str();
+    let source = TemplateSource::from(form.0);
+
+    let preview = match render_sample(&pool, &templates, template, &source).await? {
+        Ok(email) => preview_html(&email),
+        Err(e) => format!(
+            "<p><i>The template is not valid: {}</i></p>",
+            encode_minimal(&e.to_string())
+        ),
+    };
+    let placeholders = placeholders_html(template);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Preview the {name} email</title>
+</head>
+<body>
+    <p>Available placeholders: {placeholders}.</p>
+    {preview}
+    <form action="/admin/email-templates/{name}" method="post">
+        <textarea hidden name="html_body">
+{html_body}</textarea>
+        <textarea hidden name="text_body">
+{text_body}</textarea>
+        <button type="submit">Save</button>
+    </form>
+    <p><a href="/admin/email-templates/{name}">&lt;- Back</a></p>
+</body>
+</html>"#,
+            html_body = encode_minimal(&source.html),
+            text_body = encode_minimal(&source.text),
+        )))
+}
+
+pub async fn reset_email_template(
+    name: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let template = parse_template(&name)?;
+    reset_template(&pool, template).await.map_err(e500)?;
+    FlashMessage::info("The default version of the template has been restored.").send();
+    Ok(see_other(&format!(
+        "/admin/email-templates/{}",
+        template.as_str()
+    )))
+}
+
+/// Render an edited template - or an edited layout around
+/// a sample body - with the sample data.
+async fn render_sample(
+    pool: &PgPool,
+    templates: &EmailTemplates,
+    template: EmailTemplate,
+    source: &TemplateSource,
+) -> Result<Result<RenderedEmail, RenderError>, actix_web::Error> {
+    let layout = templates
+        .source(pool, EmailTemplate::Layout)
+        .await
+        .map_err(e500)?;
+    Ok(render(&layout, source, template, &template.sample_data()))
+}
@@ -0,0 +1,124 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::domain::ListSlug;
+use crate::lists::insert_list;
+use crate::utils::{e500, see_other};
+
+struct ListSummary {
+    slug: String,
+    name: String,
+    confirmed: i64,
+    pending: i64,
+}
+
+pub async fn lists_page(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let mut rows_html = String::new();
+    for list in get_list_summaries(&pool).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            encode_minimal(&list.slug),
+            encode_minimal(&list.name),
+            list.confirmed,
+            list.pending,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Mailing lists</title>
+</head>
+<body>
+    {msg_html}
+    <table>
+        <tr><th>Slug</th><th>Name</th><th>Confirmed</th><th>Pending</th></tr>
+        {rows_html}
+    </table>
+    <form action="/admin/lists" method="post">
+        <label>Slug
+            <input type="text" placeholder="e.g. rust-weekly" name="slug">
+        </label>
+        <label>Name
+            <input type="text" placeholder="e.g. Rust Weekly" name="name">
+        </label>
+        <button type="submit">Create list</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    slug: String,
+    name: String,
+}
+
+pub async fn create_list(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { slug, name } = form.0;
+    let slug = match ListSlug::parse(slug) {
+        Ok(slug) => slug,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        FlashMessage::error("The list needs a name.").send();
+        return Ok(see_other("/admin/lists"));
+    }
+
+    match insert_list(&pool, &slug, name).await.map_err(e500)? {
+        Some(_) => FlashMessage::info(format!("The {} list has been created.", name)).send(),
+        None => {
+            FlashMessage::error(format!("There is already a list called {}.", slug.as_ref())).send()
+        }
+    }
+    Ok(see_other("/admin/lists"))
+}
+
+#[tracing::instrument(name = "Get mailing list summaries", skip(pool))]
+async fn get_list_summaries(pool: &PgPool) -> Result<Vec<ListSummary>, anyhow::Error> {
+    let lists = sqlx::query_as!(
+        ListSummary,
+        r#"
+        SELECT
+            l.slug,
+            l.name,
+            COUNT(*) FILTER (WHERE ls.status = 'confirmed') AS "confirmed!",
+            COUNT(*) FILTER (WHERE ls.status = 'pending_confirmation') AS "pending!"
+        FROM lists l
+        LEFT JOIN list_subscriptions ls USING (list_id)
+        GROUP BY l.list_id
+        ORDER BY l.created_at
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve the mailing lists.")?;
+    Ok(lists)
+}
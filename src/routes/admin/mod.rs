@@ -1,9 +1,19 @@
 mod dashboard;
+mod email_templates;
+mod lists;
 mod logout;
 mod newsletter;
 mod password;
+mod profile_fields;
+mod subscribers;
+mod suppressions;
 
 pub use dashboard::admin_dashboard;
+pub use email_templates::*;
+pub use lists::{create_list, lists_page};
 pub use logout::log_out;
 pub use newsletter::*;
 pub use password::*;
+pub use profile_fields::{create_profile_field, profile_fields_page};
+pub use subscribers::*;
+pub use suppressions::suppression_list;
@@ -0,0 +1,169 @@
+use crate::newsletter_issues::{
+    cancel_delivery, get_delivery_progress, pause_delivery, resume_delivery, DeliveryProgress,
+    DeliveryStatus,
+};
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// The delivery of a published issue, with the controls to stop it.
+/// The page refreshes itself for as long as emails are going out.
+pub async fn newsletter_issue_page(
+    issue_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let progress = get_progress(&pool, issue_id).await?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let in_progress = progress.queued > 0;
+    let mut controls_html = String::new();
+    if in_progress && progress.delivery_status == DeliveryStatus::Active {
+        writeln!(controls_html, "{}", control(issue_id, "pause", "Pause")).unwrap();
+    }
+    if progress.delivery_status == DeliveryStatus::Paused {
+        writeln!(controls_html, "{}", control(issue_id, "resume", "Resume")).unwrap();
+    }
+    if in_progress && progress.delivery_status != DeliveryStatus::Cancelled {
+        writeln!(controls_html, "{}", control(issue_id, "cancel", "Cancel")).unwrap();
+    }
+    let refresh_html = if in_progress && progress.delivery_status == DeliveryStatus::Active {
+        r#"<meta http-equiv="refresh" content="5">"#
+    } else {
+        ""
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    {refresh_html}
+    <title>{title}</title>
+</head>
+<body>
+    {msg_html}
+    <h1>{title}</h1>
+    <p>Delivery: {delivery_status}.</p>
+    <table>
+        <tr><th>Queued</th><th>Delivered</th><th>Failed</th><th>Cancelled</th><th>Skipped</th></tr>
+        <tr><td>{queued}</td><td>{delivered}</td><td>{failed}</td><td>{cancelled}</td><td>{skipped}</td></tr>
+    </table>
+    {controls_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            title = encode_minimal(&progress.title),
+            delivery_status = progress.delivery_status.as_str(),
+            queued = progress.queued,
+            delivered = progress.delivered,
+            failed = progress.failed,
+            cancelled = progress.cancelled,
+            skipped = progress.skipped,
+        )))
+}
+
+fn control(issue_id: Uuid, action: &str, label: &str) -> String {
+    format!(
+        r#"<form action="/admin/newsletters/{}/{}" method="post">
+        <button type="submit">{}</button>
+    </form>"#,
+        issue_id, action, label
+    )
+}
+
+/// The same figures as the issue page, for API clients.
+pub async fn newsletter_delivery_progress(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let progress = get_progress(&pool, issue_id.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "delivery_status": progress.delivery_status.as_str(),
+        "queued": progress.queued,
+        "delivered": progress.delivered,
+        "failed": progress.failed,
+        "cancelled": progress.cancelled,
+        "skipped": progress.skipped,
+    })))
+}
+
+#[tracing::instrument(name = "Pause the delivery of an issue", skip(pool))]
+pub async fn pause_newsletter_delivery(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let paused = pause_delivery(pool.get_ref(), issue_id)
+        .await
+        .context("Failed to pause the delivery.")
+        .map_err(e500)?;
+    if paused {
+        FlashMessage::info("The delivery has been paused.").send();
+    } else {
+        FlashMessage::error("The delivery is not in progress.").send();
+    }
+    Ok(see_other(&format!("/admin/newsletters/{}", issue_id)))
+}
+
+#[tracing::instrument(name = "Resume the delivery of an issue", skip(pool))]
+pub async fn resume_newsletter_delivery(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let resumed = resume_delivery(&mut transaction, issue_id)
+        .await
+        .context("Failed to resume the delivery.")
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    if resumed {
+        FlashMessage::info("The delivery has been resumed.").send();
+    } else {
+        FlashMessage::error("The delivery is not paused.").send();
+    }
+    Ok(see_other(&format!("/admin/newsletters/{}", issue_id)))
+}
+
+#[tracing::instrument(name = "Cancel the delivery of an issue", skip(pool))]
+pub async fn cancel_newsletter_delivery(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let cancelled = cancel_delivery(&mut transaction, issue_id)
+        .await
+        .context("Failed to cancel the delivery.")
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    match cancelled {
+        Some(dropped) => FlashMessage::info(format!(
+            "The delivery has been cancelled - {} emails will not go out.",
+            dropped
+        ))
+        .send(),
+        None => FlashMessage::error("The delivery has already been cancelled.").send(),
+    }
+    Ok(see_other(&format!("/admin/newsletters/{}", issue_id)))
+}
+
+async fn get_progress(pool: &PgPool, issue_id: Uuid) -> Result<DeliveryProgress, actix_web::Error> {
+    get_delivery_progress(pool, issue_id)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("There is no such issue."))
+}
@@ -0,0 +1,109 @@
+use crate::newsletter_issues::publish_draft;
+use crate::utils::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct Draft {
+    newsletter_issue_id: Uuid,
+    title: String,
+    html_content: String,
+    created_at: String,
+}
+
+/// Issues waiting to be published, e.g. the digests of the configured feed.
+pub async fn newsletter_drafts(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let mut drafts_html = String::new();
+    for draft in get_drafts(&pool).await.map_err(e500)? {
+        writeln!(
+            drafts_html,
+            r#"<h2>{title}</h2>
+    <p>Created at {created_at}.</p>
+    <iframe sandbox srcdoc="{html_content}" width="600" height="400"></iframe>
+    <form action="/admin/newsletters/drafts/{id}/publish" method="post">
+        <button type="submit">Publish</button>
+    </form>"#,
+            title = encode_minimal(&draft.title),
+            created_at = encode_minimal(&draft.created_at),
+            html_content = encode_attribute(&draft.html_content),
+            id = draft.newsletter_issue_id,
+        )
+        .unwrap();
+    }
+    if drafts_html.is_empty() {
+        drafts_html.push_str("<p>No drafts.</p>");
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Drafts</title>
+</head>
+<body>
+    {msg_html}
+    {drafts_html}
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+/// Publishing a draft twice is harmless: the second attempt finds no draft.
+#[tracing::instrument(name = "Publish a draft", skip(pool))]
+pub async fn publish_newsletter_draft(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let published = publish_draft(&mut transaction, issue_id.into_inner())
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the publication of a draft.")
+        .map_err(e500)?;
+    if published {
+        FlashMessage::info(
+            "The draft has been published - \
+            emails will go out shortly.",
+        )
+        .send();
+    } else {
+        FlashMessage::error("The draft has already been published.").send();
+    }
+    Ok(see_other("/admin/newsletters/drafts"))
+}
+
+#[tracing::instrument(name = "Get drafts", skip(pool))]
+async fn get_drafts(pool: &PgPool) -> Result<Vec<Draft>, anyhow::Error> {
+    let drafts = sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT newsletter_issue_id, title, html_content, published_at AS created_at
+        FROM newsletter_issues
+        WHERE status = 'draft'
+        ORDER BY published_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the drafts.")?;
+    Ok(drafts)
+}
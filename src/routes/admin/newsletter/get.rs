@@ -1,17 +1,39 @@
+use crate::lists::{get_lists, DEFAULT_LIST};
+use crate::utils::e500;
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
 use std::fmt::Write;
 
 pub async fn publish_newsletter_form(
     flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut msg_html = String::new();
     for m in flash_messages.iter() {
-        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let mut lists_html = String::new();
+    for list in get_lists(&pool).await.map_err(e500)? {
+        writeln!(
+            lists_html,
+            r#"<label>
+            <input type="checkbox" name="lists" value="{}"{}>
+            {}
+        </label>"#,
+            encode_attribute(&list.slug),
+            if list.slug == DEFAULT_LIST {
+                " checked"
+            } else {
+                ""
+            },
+            encode_minimal(&list.name),
+        )
+        .unwrap();
     }
 
-    let idempotency_key = uuid::Uuid::new_v4();
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
         .body(format!(
@@ -23,7 +45,13 @@ pub async fn publish_newsletter_form(
 </head>
 <body>
     {msg_html}
-    <form action="/admin/newsletters" method="post">
+    <p>
+        Both bodies are templates, rendered for each subscriber:
+        use <code>{{{{name}}}}</code>, <code>{{{{unsubscribe_url}}}}</code>,
+        <code>{{{{preferences_url}}}}</code> and <code>{{{{confirmation_date}}}}</code>
+        to personalise them.
+    </p>
+    <form action="/admin/newsletters/preview" method="post">
         <label>Title:<br>
             <input
                 type="text"
@@ -32,6 +60,15 @@ pub async fn publish_newsletter_form(
             >
         </label>
         <br>
+        <label>Markdown content:<br>
+            <textarea
+                placeholder="Enter the content in Markdown, or fill in both fields below"
+                name="markdown_content"
+                rows="20"
+                cols="50"
+            ></textarea>
+        </label>
+        <br>
         <label>Plain text content:<br>
             <textarea
                 placeholder="Enter the content in plain text"
@@ -50,8 +87,39 @@ pub async fn publish_newsletter_form(
             ></textarea>
         </label>
         <br>
-        <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
-        <button type="submit">Publish</button>
+        <p>Send to:</p>
+        {lists_html}
+        <br>
+        <label>Segment:<br>
+            <input
+                type="text"
+                placeholder="e.g. tag:beta and not tag:paid"
+                name="segment"
+                size="50"
+            >
+        </label>
+        <p>
+            Leave empty to send to every subscriber of the lists. Combine
+            <code>tag:NAME</code>, <code>status:STATUS</code>,
+            <code>digest:immediate|daily|weekly</code>,
+            <code>subscribed_before:YYYY-MM-DD</code> and
+            <code>subscribed_after:YYYY-MM-DD</code> with <code>and</code>,
+            <code>or</code>, <code>not</code> and parentheses.
+        </p>
+        <label>
+            <input type="checkbox" name="track_opens" value="true">
+            Track opens
+        </label>
+        <label>
+            <input type="checkbox" name="track_clicks" value="true">
+            Track clicks
+        </label>
+        <label>
+            <input type="checkbox" name="is_public" value="true">
+            Show in the public archive
+        </label>
+        <br>
+        <button type="submit">Preview</button>
     </form>
     <p><a href="/admin/dashboard">&lt;- Back</a></p>
 </body>
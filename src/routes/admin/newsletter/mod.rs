@@ -1,5 +1,14 @@
+mod delivery;
+mod drafts;
 mod get;
 mod post;
+mod preview;
 
+pub use delivery::{
+    cancel_newsletter_delivery, newsletter_delivery_progress, newsletter_issue_page,
+    pause_newsletter_delivery, resume_newsletter_delivery,
+};
+pub use drafts::{newsletter_drafts, publish_newsletter_draft};
 pub use get::publish_newsletter_form;
 pub use post::publish_newsletter;
+pub use preview::preview_newsletter;
@@ -1,21 +1,103 @@
 use crate::authentication::UserId;
-use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::domain::{ListSlug, NewsletterTemplate};
+use crate::idempotency::IdempotentTransaction;
+use crate::lists::{get_lists_by_slug, DEFAULT_LIST};
+use crate::markdown;
+use crate::newsletter_issues::{
+    enqueue_delivery_tasks, insert_newsletter_issue, IssueStatus, NewsletterIssue,
+};
+use crate::sanitizer::{sanitize, SanitizedHtml};
+use crate::segment::Segment;
 use crate::utils::{e400, e500, see_other};
 
 use actix_web::web::ReqData;
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
-use sqlx::PgPool;
-use sqlx::{Postgres, Transaction};
-use uuid::Uuid;
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, Clone)]
 pub struct FormData {
-    title: String,
-    text_content: String,
-    html_content: String,
-    idempotency_key: String,
+    pub(super) title: String,
+    // Either a Markdown body, or both an HTML and a plain text body
+    #[serde(default)]
+    pub(super) markdown_content: String,
+    #[serde(default)]
+    pub(super) text_content: String,
+    #[serde(default)]
+    pub(super) html_content: String,
+    // Unchecked checkboxes are not submitted at all
+    #[serde(default)]
+    pub(super) track_opens: bool,
+    #[serde(default)]
+    pub(super) track_clicks: bool,
+    #[serde(default)]
+    pub(super) is_public: bool,
+    // The slugs of the lists the issue goes to, the default list if empty
+    #[serde(default)]
+    pub(super) lists: Vec<String>,
+    // A filter over tags, status and subscription date, everybody if blank
+    #[serde(default)]
+    pub(super) segment: String,
+}
+
+impl FormData {
+    /// `web::Form` cannot deserialize the repeated `lists` checkboxes.
+    pub(super) fn parse(body: &[u8]) -> Result<Self, actix_web::Error> {
+        serde_html_form::from_bytes(body).map_err(e400)
+    }
+}
+
+impl TryFrom<FormData> for NewsletterIssue {
+    type Error = String;
+
+    fn try_from(form: FormData) -> Result<Self, Self::Error> {
+        let lists = if form.lists.is_empty() {
+            vec![DEFAULT_LIST.into()]
+        } else {
+            form.lists
+        };
+        let lists = lists
+            .into_iter()
+            .map(ListSlug::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        let segment = if form.segment.trim().is_empty() {
+            None
+        } else {
+            Some(Segment::parse(&form.segment)?)
+        };
+        let (text_content, html_content, markdown_content) =
+            if form.markdown_content.trim().is_empty() {
+                (form.text_content, form.html_content, None)
+            } else {
+                let rendered = markdown::render(&form.markdown_content);
+                (rendered.text, rendered.html, Some(form.markdown_content))
+            };
+        if text_content.trim().is_empty() || html_content.trim().is_empty() {
+            return Err(
+                "The issue needs either a Markdown body, or both an HTML and a plain text body."
+                    .into(),
+            );
+        }
+        let SanitizedHtml {
+            html: html_content,
+            warnings,
+        } = sanitize(&html_content).map_err(|e| e.to_string())?;
+        // Both bodies are rendered for each subscriber by the delivery worker
+        NewsletterTemplate::parse(html_content.clone())?;
+        NewsletterTemplate::parse(text_content.clone())?;
+        Ok(Self {
+            title: form.title,
+            text_content,
+            html_content,
+            markdown_content,
+            track_opens: form.track_opens,
+            track_clicks: form.track_clicks,
+            is_public: form.is_public,
+            lists,
+            segment,
+            warnings,
+        })
+    }
 }
 
 #[tracing::instrument(
@@ -24,55 +106,54 @@ pub struct FormData {
     fields(user_id=%&*user_id),
     )]
 pub async fn publish_newsletter(
-    form: web::Form<FormData>,
-    pool: web::Data<PgPool>,
+    body: web::Bytes,
+    // Opened by the idempotency middleware, retries never reach the handler
+    transaction: IdempotentTransaction,
     user_id: ReqData<UserId>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let user_id = user_id.into_inner();
-
-    // We must destructure the form to avoid upsetting
-    // the borrow-checker
-    let FormData {
-        title,
-        text_content,
-        html_content,
-        idempotency_key,
-    } = form.0;
-    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
-
-    let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
-        .await
-        .map_err(e500)?
-    {
-        NextAction::StartProcessing(t) => t,
-        // Return early if we have a saved response in the database
-        NextAction::ReturnSavedResponse(saved_response) => {
-            // send a `FlashMessage`
-            success_message().send();
-            return Ok(saved_response);
+    let issue: NewsletterIssue = match FormData::parse(&body)?.try_into() {
+        Ok(issue) => issue,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/newsletters"));
         }
     };
-
-    // insert newsletter_issue
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
+    let mut transaction = transaction.lock().await.map_err(e500)?;
+    let lists = get_lists_by_slug(&mut *transaction, &issue.lists)
         .await
-        .context("Failed to store newsletter issue details")
+        .context("Failed to retrieve the lists of the issue")
         .map_err(e500)?;
+    if let Some(unknown) = issue
+        .lists
+        .iter()
+        .find(|slug| !lists.iter().any(|l| l.slug == slug.as_ref()))
+    {
+        FlashMessage::error(format!("There is no list called {}.", unknown.as_ref())).send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    let list_ids: Vec<_> = lists.iter().map(|l| l.list_id).collect();
 
-    // enqueue the delivery task
-    enqueue_delivery_tasks(&mut transaction, issue_id)
-        .await
-        .context("Failed to enqueue delivery tasks")
-        .map_err(e500)?;
+    // insert newsletter_issue
+    let issue_id =
+        insert_newsletter_issue(&mut transaction, &issue, &list_ids, IssueStatus::Published)
+            .await
+            .context("Failed to store newsletter issue details")
+            .map_err(e500)?;
 
-    let response = see_other("/admin/newsletters");
-    let response = save_response(transaction, &idempotency_key, *user_id, response)
-        .await
-        .map_err(e500)?;
+    // enqueue the delivery task
+    enqueue_delivery_tasks(
+        &mut transaction,
+        issue_id,
+        &list_ids,
+        issue.segment.as_ref(),
+    )
+    .await
+    .context("Failed to enqueue delivery tasks")
+    .map_err(e500)?;
 
     // send a `FlashMessage`
     success_message().send();
-    Ok(response)
+    Ok(see_other("/admin/newsletters"))
 }
 
 fn success_message() -> FlashMessage {
@@ -81,54 +162,3 @@ fn success_message() -> FlashMessage {
         emails will go out shortly.",
     )
 }
-
-#[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
-    transaction: &mut Transaction<'_, Postgres>,
-    title: &str,
-    text_content: &str,
-    html_content: &str,
-) -> Result<Uuid, sqlx::Error> {
-    let newsletter_issue_id = Uuid::new_v4();
-    sqlx::query!(
-        r#"
-        INSERT INTO newsletter_issues(
-        newsletter_issue_id,
-        title,
-        text_content,
-        html_content,
-        published_at
-        )
-        VALUES ($1, $2, $3, $4, now())
-        "#,
-        newsletter_issue_id,
-        title,
-        text_content,
-        html_content,
-    )
-    .execute(transaction)
-    .await?;
-    Ok(newsletter_issue_id)
-}
-
-#[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
-    transaction: &mut Transaction<'_, Postgres>,
-    newsletter_issue_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"
-        INSERT INTO issue_delivery_queue (
-            newsletter_issue_id,
-            subscriber_email
-        )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-        newsletter_issue_id,
-    )
-    .execute(transaction)
-    .await?;
-    Ok(())
-}
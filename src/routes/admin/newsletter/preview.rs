@@ -0,0 +1,159 @@
+use super::post::FormData;
+use crate::lists::{get_lists_by_slug, MailingList};
+use crate::newsletter_issues::{count_audience, NewsletterIssue};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+/// Show what will be sent, to which lists and how many subscribers,
+/// and what the sanitizer removed, before the issue is published.
+pub async fn preview_newsletter(
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let form = FormData::parse(&body)?;
+    let body = match NewsletterIssue::try_from(form.clone()) {
+        Ok(issue) => {
+            let lists = get_lists_by_slug(pool.get_ref(), &issue.lists)
+                .await
+                .map_err(e500)?;
+            match issue
+                .lists
+                .iter()
+                .find(|slug| !lists.iter().any(|l| l.slug == slug.as_ref()))
+            {
+                Some(unknown) => {
+                    error_html(&format!("There is no list called {}.", unknown.as_ref()))
+                }
+                None => {
+                    let list_ids: Vec<_> = lists.iter().map(|l| l.list_id).collect();
+                    let audience =
+                        count_audience(pool.get_ref(), &list_ids, issue.segment.as_ref())
+                            .await
+                            .map_err(e500)?;
+                    preview_html(&form, &issue, &lists, audience)
+                }
+            }
+        }
+        Err(e) => error_html(&e),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Preview Newsletter Issue</title>
+</head>
+<body>
+    {body}
+    <p><a href="/admin/newsletters">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+fn error_html(e: &str) -> String {
+    format!(
+        r#"<p><i>{}</i></p>
+    <p>Go back to fix the issue.</p>"#,
+        encode_minimal(e)
+    )
+}
+
+fn preview_html(
+    form: &FormData,
+    issue: &NewsletterIssue,
+    lists: &[MailingList],
+    audience: i64,
+) -> String {
+    let mut warnings_html = String::new();
+    if issue.warnings.is_empty() {
+        warnings_html.push_str("<p>No warnings.</p>");
+    } else {
+        warnings_html.push_str("<p>Warnings:</p>\n    <ul>");
+        for warning in &issue.warnings {
+            write!(warnings_html, "<li>{}</li>", encode_minimal(warning)).unwrap();
+        }
+        warnings_html.push_str("</ul>");
+    }
+
+    // The publish form submits the original content again:
+    // it goes through the same pipeline, with the same outcome.
+    // Textareas, unlike text inputs, preserve line breaks - the newline
+    // following their opening tag is dropped by the HTML parser.
+    let checkbox = |name: &str, checked: bool| {
+        if checked {
+            format!(r#"<input hidden type="text" name="{}" value="true">"#, name)
+        } else {
+            String::new()
+        }
+    };
+    let list_names: Vec<_> = lists.iter().map(|l| encode_minimal(&l.name)).collect();
+    let mut lists_html = String::new();
+    for list in lists {
+        write!(
+            lists_html,
+            r#"<input hidden type="text" name="lists" value="{}">"#,
+            encode_attribute(&list.slug)
+        )
+        .unwrap();
+    }
+    let segment_html = match &issue.segment {
+        Some(segment) => format!(
+            " matching <code>{}</code>",
+            encode_minimal(&segment.to_string())
+        ),
+        None => String::new(),
+    };
+    let visibility = if issue.is_public {
+        "Listed in the public archive."
+    } else {
+        "Not listed in the public archive."
+    };
+    let idempotency_key = uuid::Uuid::new_v4();
+    format!(
+        r#"<h1>{title}</h1>
+    <p>Goes to: {list_names}{segment_html}.</p>
+    <p>Audience: {audience} subscribers.</p>
+    <p>{visibility}</p>
+    {warnings_html}
+    <p>HTML content:</p>
+    <iframe sandbox srcdoc="{html_content}" width="600" height="400"></iframe>
+    <p>Plain text content:</p>
+    <pre>{text_content}</pre>
+    <form action="/admin/newsletters" method="post">
+        <input hidden type="text" name="title" value="{form_title}">
+        <textarea hidden name="markdown_content">
+{form_markdown_content}</textarea>
+        <textarea hidden name="html_content">
+{form_html_content}</textarea>
+        <textarea hidden name="text_content">
+{form_text_content}</textarea>
+        {track_opens}
+        {track_clicks}
+        {is_public}
+        {lists_html}
+        <input hidden type="text" name="segment" value="{form_segment}">
+        <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
+        <button type="submit">Publish</button>
+    </form>"#,
+        title = encode_minimal(&issue.title),
+        list_names = list_names.join(", "),
+        html_content = encode_attribute(&issue.html_content),
+        text_content = encode_minimal(&issue.text_content),
+        form_title = encode_attribute(&form.title),
+        form_markdown_content = encode_minimal(&form.markdown_content),
+        form_html_content = encode_minimal(&form.html_content),
+        form_text_content = encode_minimal(&form.text_content),
+        form_segment = encode_attribute(&form.segment),
+        track_opens = checkbox("track_opens", form.track_opens),
+        track_clicks = checkbox("track_clicks", form.track_clicks),
+        is_public = checkbox("is_public", form.is_public),
+    )
+}
@@ -0,0 +1,120 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::domain::{ProfileField, ProfileFieldKey, ProfileFieldKind};
+use crate::profile::{get_profile_fields, insert_profile_field};
+use crate::utils::{e500, see_other};
+
+pub async fn profile_fields_page(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let mut rows_html = String::new();
+    for field in get_profile_fields(&pool).await.map_err(e500)? {
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            encode_minimal(field.key.as_ref()),
+            encode_minimal(&field.label),
+            field.kind.as_str(),
+            encode_minimal(&field.kind.options().join(", ")),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Profile fields</title>
+</head>
+<body>
+    {msg_html}
+    <p>Subscribers fill in these fields from their preference centre.</p>
+    <table>
+        <tr><th>Key</th><th>Label</th><th>Kind</th><th>Options</th></tr>
+        {rows_html}
+    </table>
+    <form action="/admin/profile-fields" method="post">
+        <label>Key
+            <input type="text" placeholder="e.g. company_size" name="key">
+        </label>
+        <label>Label
+            <input type="text" placeholder="e.g. Company size" name="label">
+        </label>
+        <label>Kind
+            <select name="kind">
+                <option value="text">Text</option>
+                <option value="enum">One of several options</option>
+                <option value="boolean">Yes or no</option>
+            </select>
+        </label>
+        <label>Options
+            <input type="text" placeholder="e.g. small, medium, large" name="options">
+        </label>
+        <button type="submit">Create field</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    key: String,
+    label: String,
+    kind: String,
+    // Comma-separated, for enum fields
+    #[serde(default)]
+    options: String,
+}
+
+pub async fn create_profile_field(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let field = match ProfileField::try_from(form.0) {
+        Ok(field) => field,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/profile-fields"));
+        }
+    };
+    if insert_profile_field(&pool, &field).await.map_err(e500)? {
+        FlashMessage::info(format!("The {} field has been created.", field.label)).send();
+    } else {
+        FlashMessage::error(format!(
+            "There is already a field called {}.",
+            field.key.as_ref()
+        ))
+        .send();
+    }
+    Ok(see_other("/admin/profile-fields"))
+}
+
+impl TryFrom<FormData> for ProfileField {
+    type Error = String;
+
+    fn try_from(form: FormData) -> Result<Self, Self::Error> {
+        let key = ProfileFieldKey::parse(form.key.trim().to_owned())?;
+        let label = form.label.trim().to_owned();
+        if label.is_empty() {
+            return Err("The field needs a label.".into());
+        }
+        let options = form.options.split(',').map(ToOwned::to_owned).collect();
+        let kind = ProfileFieldKind::parse(&form.kind, options)?;
+        Ok(Self { key, label, kind })
+    }
+}
@@ -0,0 +1,76 @@
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::consent::get_consent_history;
+use crate::utils::e500;
+
+pub async fn subscriber_consent(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let history = get_consent_history(&pool, subscriber_id)
+        .await
+        .map_err(e500)?;
+
+    let mut rows_html = String::new();
+    for r in history {
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            r.occurred_at.to_rfc3339(),
+            r.event_type,
+            encode_minimal(r.list.as_deref().unwrap_or("-")),
+            encode_minimal(r.ip_address.as_deref().unwrap_or("-")),
+            encode_minimal(r.user_agent.as_deref().unwrap_or("-")),
+            encode_minimal(r.consent_text.as_deref().unwrap_or("-")),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Consent history</title>
+</head>
+<body>
+    <table>
+        <tr><th>Occurred at</th><th>Event</th><th>List</th><th>IP address</th><th>User agent</th><th>Consent text</th></tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/subscribers/{subscriber_id}/consent/export">Export as JSON</a></p>
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+pub async fn export_subscriber_consent(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let history = get_consent_history(&pool, subscriber_id)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "consent-{}.json",
+                subscriber_id
+            ))],
+        })
+        .json(serde_json::json!({
+            "subscriber_id": subscriber_id,
+            "consent_events": history,
+        })))
+}
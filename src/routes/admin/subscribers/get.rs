@@ -0,0 +1,143 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::{PgPool, QueryBuilder};
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::segment::Segment;
+use crate::utils::e500;
+
+#[derive(sqlx::FromRow)]
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    tags: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    // Only list the subscribers matching it, if not blank
+    #[serde(default)]
+    segment: String,
+}
+
+pub async fn subscribers_list(
+    query: web::Query<QueryParams>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let segment = if query.segment.trim().is_empty() {
+        None
+    } else {
+        match Segment::parse(&query.segment) {
+            Ok(segment) => Some(segment),
+            Err(e) => {
+                writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(&e)).unwrap();
+                None
+            }
+        }
+    };
+    let subscribers = get_subscribers(&pool, segment.as_ref())
+        .await
+        .map_err(e500)?;
+
+    let mut rows_html = String::new();
+    for s in subscribers {
+        let mut tags_html = String::new();
+        for tag in &s.tags {
+            write!(
+                tags_html,
+                r#"<form action="/admin/subscribers/{}/tags/remove" method="post">
+                <input hidden type="text" name="tag" value="{}">
+                {} <button type="submit">Remove</button>
+            </form>"#,
+                s.id,
+                encode_attribute(tag),
+                encode_minimal(tag),
+            )
+            .unwrap();
+        }
+        writeln!(
+            rows_html,
+            r#"<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}<form action="/admin/subscribers/{}/tags" method="post">
+                <input type="text" name="tag" placeholder="Tag">
+                <button type="submit">Add</button>
+            </form></td><td><a href="/admin/subscribers/{}/consent">Consent history</a></td></tr>"#,
+            encode_minimal(&s.email),
+            encode_minimal(&s.name),
+            s.status,
+            s.subscribed_at.to_rfc3339(),
+            tags_html,
+            s.id,
+            s.id,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscribers</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/subscribers" method="get">
+        <label>Segment:
+            <input
+                type="text"
+                placeholder="e.g. tag:beta and status:confirmed"
+                name="segment"
+                value="{segment}"
+                size="50"
+            >
+        </label>
+        <button type="submit">Filter</button>
+    </form>
+    <table>
+        <tr><th>Email</th><th>Name</th><th>Status</th><th>Subscribed at</th><th>Tags</th><th></th></tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+            segment = encode_attribute(&query.segment),
+        )))
+}
+
+#[tracing::instrument(name = "Get subscribers", skip(pool))]
+async fn get_subscribers(
+    pool: &PgPool,
+    segment: Option<&Segment>,
+) -> Result<Vec<SubscriberRow>, anyhow::Error> {
+    let mut query = QueryBuilder::new(
+        "SELECT s.id, s.email, s.name, s.status, s.subscribed_at, \
+        ARRAY(SELECT t.tag FROM subscriber_tags t WHERE t.subscriber_id = s.id ORDER BY t.tag) AS tags \
+        FROM subscriptions s",
+    );
+    if let Some(segment) = segment {
+        query.push(" WHERE ");
+        segment.push_sql(&mut query);
+    }
+    query.push(" ORDER BY s.subscribed_at DESC");
+    let subscribers = query
+        .build_query_as()
+        .fetch_all(pool)
+        .await
+        .context("Failed to perform a query to retrieve subscribers.")?;
+    Ok(subscribers)
+}
@@ -0,0 +1,7 @@
+mod consent;
+mod get;
+mod tags;
+
+pub use consent::{export_subscriber_consent, subscriber_consent};
+pub use get::subscribers_list;
+pub use tags::{add_subscriber_tag, remove_subscriber_tag};
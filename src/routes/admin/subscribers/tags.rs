@@ -0,0 +1,84 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::SubscriberTag;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    tag: String,
+}
+
+pub async fn add_subscriber_tag(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let tag = match SubscriberTag::parse(form.0.tag) {
+        Ok(tag) => tag,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    };
+    insert_tag(&pool, subscriber_id, &tag).await.map_err(e500)?;
+    FlashMessage::info(format!("The subscriber has been tagged {}.", tag.as_ref())).send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+pub async fn remove_subscriber_tag(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    // Tags that could not have been added cannot be there either
+    if let Ok(tag) = SubscriberTag::parse(form.0.tag) {
+        delete_tag(&pool, subscriber_id, &tag).await.map_err(e500)?;
+        FlashMessage::info(format!("The {} tag has been removed.", tag.as_ref())).send();
+    }
+    Ok(see_other("/admin/subscribers"))
+}
+
+/// Tagging a subscriber twice is a no-op.
+#[tracing::instrument(name = "Tag a subscriber", skip(pool))]
+async fn insert_tag(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    tag: &SubscriberTag,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag, created_at)
+        SELECT id, $2, now() FROM subscriptions WHERE id = $1
+        ON CONFLICT (subscriber_id, tag) DO NOTHING
+        "#,
+        subscriber_id,
+        tag.as_ref(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to tag a subscriber.")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Untag a subscriber", skip(pool))]
+async fn delete_tag(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    tag: &SubscriberTag,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM subscriber_tags WHERE subscriber_id = $1 AND tag = $2",
+        subscriber_id,
+        tag.as_ref(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to remove a tag from a subscriber.")?;
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::utils::e500;
+
+struct Suppression {
+    email: String,
+    reason: String,
+    provider: String,
+    details: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+pub async fn suppression_list(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let suppressions = get_suppressions(&pool).await.map_err(e500)?;
+
+    let mut rows_html = String::new();
+    for s in suppressions {
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            encode_minimal(&s.email),
+            encode_minimal(&s.reason),
+            encode_minimal(&s.provider),
+            encode_minimal(s.details.as_deref().unwrap_or("-")),
+            s.created_at.to_rfc3339(),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Suppression list</title>
+</head>
+<body>
+    <p>These addresses are excluded from every send.</p>
+    <table>
+        <tr><th>Email</th><th>Reason</th><th>Provider</th><th>Details</th><th>Since</th></tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Get suppressed addresses", skip(pool))]
+async fn get_suppressions(pool: &PgPool) -> Result<Vec<Suppression>, anyhow::Error> {
+    let suppressions = sqlx::query_as!(
+        Suppression,
+        r#"
+        SELECT email, reason, provider, details, created_at
+        FROM email_suppressions
+        ORDER BY created_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve suppressed addresses.")?;
+    Ok(suppressions)
+}
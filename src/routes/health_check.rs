@@ -1,4 +1,18 @@
-use actix_web::HttpResponse;
+use crate::configuration::ReadinessSettings;
+use crate::email_client::EmailClient;
+use crate::issue_delivery_worker::HEARTBEAT_INTERVAL;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// The endpoint is not authenticated: the email provider is only asked
+/// once in a while, not on every hit.
+const EMAIL_PROVIDER_CACHE_TTL: Duration = Duration::from_secs(10);
 
 /// We were returning `impl Responder` at the very beginning.
 /// We are now spelling out the type explicitly given that we have
@@ -7,3 +21,206 @@ use actix_web::HttpResponse;
 pub async fn health_check() -> HttpResponse {
     HttpResponse::Ok().finish()
 }
+
+/// The dependencies checked by `/health/ready`.
+pub struct ReadinessProbe {
+    redis_client: redis::Client,
+    timeout: Duration,
+    check_email_provider: bool,
+    /// The last report on the email provider, and when it was made
+    email_provider: Mutex<Option<(Instant, ComponentReport)>>,
+}
+
+impl ReadinessProbe {
+    pub fn new(
+        settings: &ReadinessSettings,
+        redis_uri: &Secret<String>,
+    ) -> Result<Self, redis::RedisError> {
+        Ok(Self {
+            redis_client: redis::Client::open(redis_uri.expose_secret().as_str())?,
+            timeout: Duration::from_millis(settings.timeout_milliseconds),
+            check_email_provider: settings.check_email_provider,
+            email_provider: Mutex::new(None),
+        })
+    }
+
+    async fn email_provider_report(&self, email_client: &EmailClient) -> ComponentReport {
+        // Concurrent requests wait for the same check
+        let mut cached = self.email_provider.lock().await;
+        match cached.as_ref() {
+            Some((checked_at, report)) if checked_at.elapsed() < EMAIL_PROVIDER_CACHE_TTL => {
+                report.clone()
+            }
+            _ => {
+                let report =
+                    run_check(self.timeout, true, check_email_provider(email_client)).await;
+                *cached = Some((Instant::now(), report.clone()));
+                report
+            }
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct Readiness {
+    /// `ready` or `not_ready`
+    status: &'static str,
+    components: BTreeMap<&'static str, ComponentReport>,
+}
+
+#[derive(serde::Serialize, Clone)]
+struct ComponentReport {
+    /// `ok` when healthy, `error` or `timeout` when the check failed,
+    /// or a component specific status
+    status: &'static str,
+    latency_ms: u64,
+    /// Optional components do not make the instance unready
+    required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+/// The outcome of a check that completed: a status and, optionally,
+/// some detail about it.
+type CheckOutcome = (&'static str, Option<String>);
+
+/// Report on the dependencies of the instance. It is ready, and answers
+/// with a 200, when they all are; otherwise it answers with a 503.
+pub async fn readiness_check(
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    probe: web::Data<ReadinessProbe>,
+) -> HttpResponse {
+    let (database, migrations, redis, workers, email_provider) = tokio::join!(
+        run_check(probe.timeout, true, check_database(&pool)),
+        run_check(probe.timeout, true, check_migrations(&pool)),
+        run_check(probe.timeout, true, check_redis(&probe.redis_client)),
+        // Deliveries may lag behind, requests are still served
+        run_check(probe.timeout, false, check_workers(&pool)),
+        async {
+            if probe.check_email_provider {
+                Some(probe.email_provider_report(&email_client).await)
+            } else {
+                None
+            }
+        },
+    );
+    let mut components = BTreeMap::from([
+        ("database", database),
+        ("migrations", migrations),
+        ("redis", redis),
+        ("workers", workers),
+    ]);
+    if let Some(email_provider) = email_provider {
+        components.insert("email_provider", email_provider);
+    }
+
+    let ready = components.values().all(|c| !c.required || c.status == "ok");
+    let readiness = Readiness {
+        status: if ready { "ready" } else { "not_ready" },
+        components,
+    };
+    if ready {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+async fn run_check(
+    timeout: Duration,
+    required: bool,
+    check: impl Future<Output = Result<CheckOutcome, anyhow::Error>>,
+) -> ComponentReport {
+    let started_at = Instant::now();
+    let (status, detail) = match tokio::time::timeout(timeout, check).await {
+        Ok(Ok(outcome)) => outcome,
+        Ok(Err(e)) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "A readiness check failed."
+            );
+            ("error", None)
+        }
+        Err(_) => ("timeout", None),
+    };
+    ComponentReport {
+        status,
+        latency_ms: started_at.elapsed().as_millis() as u64,
+        required,
+        detail,
+    }
+}
+
+async fn check_database(pool: &PgPool) -> Result<CheckOutcome, anyhow::Error> {
+    sqlx::query("SELECT 1")
+        .execute(pool)
+        .await
+        .context("Failed to reach Postgres.")?;
+    Ok(("ok", None))
+}
+
+/// Every migration shipped with the binary must have been applied.
+async fn check_migrations(pool: &PgPool) -> Result<CheckOutcome, anyhow::Error> {
+    let applied: Vec<i64> =
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await
+            .context("Failed to retrieve the applied migrations.")?;
+    let pending = sqlx::migrate!("./migrations")
+        .iter()
+        .filter(|m| !applied.contains(&m.version))
+        .count();
+    if pending == 0 {
+        Ok(("ok", None))
+    } else {
+        Ok(("pending", Some(format!("{} pending migrations", pending))))
+    }
+}
+
+async fn check_redis(client: &redis::Client) -> Result<CheckOutcome, anyhow::Error> {
+    let mut connection = client
+        .get_multiplexed_async_connection()
+        .await
+        .context("Failed to connect to Redis.")?;
+    redis::cmd("PING")
+        .query_async::<_, String>(&mut connection)
+        .await
+        .context("Failed to ping Redis.")?;
+    Ok(("ok", None))
+}
+
+/// Whether a worker process recorded a heartbeat lately.
+async fn check_workers(pool: &PgPool) -> Result<CheckOutcome, anyhow::Error> {
+    let last_beat = sqlx::query!(
+        r#"
+        SELECT EXTRACT(EPOCH FROM now() - MAX(beat_at))::float8 AS seconds_ago
+        FROM worker_heartbeats
+        "#,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the worker heartbeats.")?
+    .seconds_ago;
+    let seconds_ago = match last_beat {
+        Some(seconds_ago) => seconds_ago,
+        None => return Ok(("missing", None)),
+    };
+    let detail = Some(format!("last heartbeat {:.0}s ago", seconds_ago));
+    // A couple of beats may be missed
+    if seconds_ago > 3.0 * HEARTBEAT_INTERVAL.as_secs_f64() {
+        Ok(("stale", detail))
+    } else {
+        Ok(("ok", detail))
+    }
+}
+
+async fn check_email_provider(email_client: &EmailClient) -> Result<CheckOutcome, anyhow::Error> {
+    match email_client.check().await {
+        Ok(()) => Ok(("ok", None)),
+        // The client may give up before the probe does
+        Err(e) if e.is_timeout() => Ok(("timeout", None)),
+        Err(e) => Err(e).context("Failed to reach the email provider."),
+    }
+}
@@ -1,7 +1,33 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use crate::consent::CONSENT_TEXT;
+use crate::lists::{get_lists, DEFAULT_LIST};
+use crate::utils::e500;
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use std::fmt::Write;
 
-pub async fn home() -> HttpResponse {
-    HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(include_str!("home.html"))
+pub async fn home(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    // A fresh key for every render of the subscription form
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let mut list_options = String::new();
+    for list in get_lists(&pool).await.map_err(e500)? {
+        write!(
+            list_options,
+            r#"<option value="{}"{}>{}</option>"#,
+            encode_attribute(&list.slug),
+            if list.slug == DEFAULT_LIST {
+                " selected"
+            } else {
+                ""
+            },
+            encode_minimal(&list.name),
+        )
+        .unwrap();
+    }
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        include_str!("home.html")
+            .replace("{consent_text}", CONSENT_TEXT)
+            .replace("{list_options}", &list_options)
+            .replace("{idempotency_key}", &idempotency_key),
+    ))
 }
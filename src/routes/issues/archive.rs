@@ -0,0 +1,110 @@
+use super::PAGE_SIZE;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    /// Starts at 1, with the most recent issues
+    page: Option<u32>,
+}
+
+pub async fn issue_archive(
+    query: web::Query<QueryParams>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let page = query.page.unwrap_or(1).max(1);
+    let mut issues = get_public_issues(&pool, page).await.map_err(e500)?;
+    // One more issue than shown is fetched to tell whether there is a next page
+    let has_older = issues.len() as i64 > PAGE_SIZE;
+    issues.truncate(PAGE_SIZE as usize);
+
+    let mut issues_html = String::new();
+    if issues.is_empty() {
+        issues_html.push_str("<p>No issues here.</p>");
+    } else {
+        issues_html.push_str("<ul>\n");
+        for issue in &issues {
+            writeln!(
+                issues_html,
+                r#"<li><a href="/issues/{}">{}</a> - {}</li>"#,
+                issue.newsletter_issue_id,
+                encode_minimal(&issue.title),
+                issue.published_at.format("%Y-%m-%d"),
+            )
+            .unwrap();
+        }
+        issues_html.push_str("</ul>");
+    }
+    let mut navigation_html = String::new();
+    if page > 1 {
+        write!(
+            navigation_html,
+            r#"<a href="/issues?page={}">Newer issues</a> "#,
+            page - 1
+        )
+        .unwrap();
+    }
+    if has_older {
+        write!(
+            navigation_html,
+            r#"<a href="/issues?page={}">Older issues</a>"#,
+            page + 1
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <link rel="alternate" type="application/atom+xml" title="Issues" href="/issues/feed.xml">
+    <title>Issues</title>
+</head>
+<body>
+    <h1>Issues</h1>
+    {issues_html}
+    <p>{navigation_html}</p>
+    <p><a href="/issues/feed.xml">Atom feed</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+struct IssueSummary {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get public issues", skip(pool))]
+async fn get_public_issues(pool: &PgPool, page: u32) -> Result<Vec<IssueSummary>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            published_at::timestamptz AS "published_at!"
+        FROM newsletter_issues
+        WHERE is_public AND status = 'published'
+        ORDER BY published_at::timestamptz DESC
+        LIMIT $1 OFFSET $2
+        "#,
+        PAGE_SIZE + 1,
+        (i64::from(page) - 1) * PAGE_SIZE,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the public issues.")?;
+    Ok(issues)
+}
@@ -0,0 +1,91 @@
+use super::{render_public_html, PAGE_SIZE};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::e500;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+/// An Atom feed of the most recent public issues, with their content.
+pub async fn issue_feed(
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let base_url = &base_url.0;
+    let issues = get_latest_public_issues(&pool).await.map_err(e500)?;
+
+    let mut entries_xml = String::new();
+    for issue in &issues {
+        let html_content =
+            render_public_html(&issue.html_content, issue.published_at, base_url).map_err(e500)?;
+        writeln!(
+            entries_xml,
+            r#"<entry>
+        <id>urn:uuid:{id}</id>
+        <title>{title}</title>
+        <link href="{base_url}/issues/{id}"/>
+        <updated>{updated}</updated>
+        <content type="html">{content}</content>
+    </entry>"#,
+            id = issue.newsletter_issue_id,
+            title = encode_minimal(&issue.title),
+            base_url = encode_minimal(base_url),
+            updated = issue.published_at.to_rfc3339(),
+            content = encode_minimal(&html_content),
+        )
+        .unwrap();
+    }
+    // Feeds must have an update date, even without any entry
+    let updated = issues
+        .first()
+        .map_or_else(Utc::now, |issue| issue.published_at)
+        .to_rfc3339();
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+    <id>{base_url}/issues</id>
+    <title>Issues</title>
+    <link href="{base_url}/issues"/>
+    <link rel="self" href="{base_url}/issues/feed.xml"/>
+    <updated>{updated}</updated>
+    {entries_xml}
+</feed>"#,
+            base_url = encode_minimal(base_url),
+        )))
+}
+
+struct PublicIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get latest public issues", skip(pool))]
+async fn get_latest_public_issues(pool: &PgPool) -> Result<Vec<PublicIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        PublicIssue,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            html_content,
+            published_at::timestamptz AS "published_at!"
+        FROM newsletter_issues
+        WHERE is_public AND status = 'published'
+        ORDER BY published_at::timestamptz DESC
+        LIMIT $1
+        "#,
+        PAGE_SIZE,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the latest public issues.")?;
+    Ok(issues)
+}
@@ -0,0 +1,35 @@
+mod archive;
+mod feed;
+mod view;
+
+pub use archive::issue_archive;
+pub use feed::issue_feed;
+pub use view::issue_web_view;
+
+use crate::domain::{NewsletterTemplate, TemplateContext};
+use chrono::{DateTime, Utc};
+
+/// Issues listed on each page of the archive, and in the feed.
+const PAGE_SIZE: i64 = 20;
+
+/// Render the HTML content of an issue for the web.
+///
+/// The web view is the same for everybody: placeholders get generic values,
+/// and the subscription links point to the page where readers request
+/// a signed link for their own address.
+fn render_public_html(
+    html_content: &str,
+    published_at: DateTime<Utc>,
+    base_url: &str,
+) -> Result<String, anyhow::Error> {
+    let context = TemplateContext {
+        name: "reader".into(),
+        unsubscribe_url: format!("{}/subscriptions/data", base_url),
+        preferences_url: format!("{}/subscriptions/data", base_url),
+        confirmation_date: published_at.format("%Y-%m-%d").to_string(),
+    };
+    let html = NewsletterTemplate::parse(html_content.to_owned())
+        .map_err(anyhow::Error::msg)?
+        .render_html(&context)?;
+    Ok(html)
+}
@@ -0,0 +1,84 @@
+use super::render_public_html;
+use crate::startup::ApplicationBaseUrl;
+use crate::tracking::{EngagementKind, Tracker, TrackingParameters};
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Public issues are open to everybody. Private ones only through the
+/// signed "view in browser" link of their recipients.
+#[tracing::instrument(name = "View an issue", skip(parameters, pool, tracker, base_url))]
+pub async fn issue_web_view(
+    issue_id: web::Path<Uuid>,
+    parameters: Option<web::Query<TrackingParameters>>,
+    pool: web::Data<PgPool>,
+    tracker: web::Data<Tracker>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let issue = get_issue(&pool, issue_id).await.map_err(e500)?;
+    let signed = matches!(
+        &parameters,
+        Some(p) if tracker.verify(EngagementKind::View, issue_id, p).is_ok()
+    );
+    let issue = match issue {
+        Some(issue) if issue.is_public || signed => issue,
+        // Private issues do not exist, as far as other people know
+        _ => return Err(actix_web::error::ErrorNotFound("There is no such issue.")),
+    };
+    let html_content =
+        render_public_html(&issue.html_content, issue.published_at, &base_url.0).map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <h1>{title}</h1>
+    <p>Published on {published_at}.</p>
+    {html_content}
+    <p><a href="/issues">&lt;- All issues</a></p>
+</body>
+</html>"#,
+            title = encode_minimal(&issue.title),
+            published_at = issue.published_at.format("%Y-%m-%d"),
+        )))
+}
+
+struct Issue {
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+    is_public: bool,
+}
+
+#[tracing::instrument(name = "Get issue", skip(pool))]
+async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<Option<Issue>, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        Issue,
+        r#"
+        SELECT
+            title,
+            html_content,
+            published_at::timestamptz AS "published_at!",
+            is_public
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND status = 'published'
+        "#,
+        issue_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the issue.")?;
+    Ok(issue)
+}
@@ -1,12 +1,18 @@
 use actix_web::http::header::ContentType;
 use actix_web::HttpResponse;
 use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
 use std::fmt::Write;
 
 pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
     let mut message_html = String::new();
     for m in flash_messages.iter() {
-        writeln!(message_html, "<p><i>{}</i></p>", m.content()).unwrap();
+        writeln!(
+            message_html,
+            "<p><i>{}</i></p>",
+            encode_minimal(m.content())
+        )
+        .unwrap();
     }
 
     HttpResponse::Ok()
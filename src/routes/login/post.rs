@@ -1,4 +1,5 @@
 use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::metrics::metrics;
 use crate::routes::error_chain_fmt;
 use crate::session_state::TypedSession;
 use actix_web::error::InternalError;
@@ -44,10 +45,10 @@ pub async fn login(
     };
 
     // Let's validate the creds
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    tracing::Span::current().record("username", tracing::field::display(&credentials.username));
     match validate_credentials(credentials, &pool).await {
         Ok(user_id) => {
-            tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
             // renew the session after login is called
             // to prevent session fixation attacks
             session.renew();
@@ -59,15 +60,17 @@ pub async fn login(
                 .insert_user_id(user_id)
                 .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
 
+            metrics().logins.with_label_values(&["success"]).inc();
             Ok(HttpResponse::SeeOther()
                 .insert_header((LOCATION, "/admin/dashboard"))
                 .finish())
         }
         Err(e) => {
-            let e = match e {
-                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
-                AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
+            let (e, outcome) = match e {
+                AuthError::InvalidCredentials(_) => (LoginError::AuthError(e.into()), "failure"),
+                AuthError::UnexpectedError(_) => (LoginError::UnexpectedError(e.into()), "error"),
             };
+            metrics().logins.with_label_values(&[outcome]).inc();
             Err(login_redirect(e))
         }
     }
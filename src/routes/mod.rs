@@ -1,13 +1,25 @@
 mod admin;
 mod health_check;
 mod home;
+mod issues;
 mod login;
+mod preferences;
+mod subscriber_data;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod tracking;
+mod webhooks;
 
 pub use admin::*;
 pub use health_check::*;
 pub use home::*;
+pub use issues::*;
 pub use login::*;
+pub use preferences::*;
+pub use subscriber_data::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_unsubscribe::*;
+pub use tracking::*;
+pub use webhooks::*;
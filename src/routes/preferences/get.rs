@@ -0,0 +1,177 @@
+use super::{get_subscriber, verify_link};
+use crate::consent::CONSENT_TEXT;
+use crate::domain::{DigestFrequency, ProfileField, ProfileFieldKind};
+use crate::lists::get_lists;
+use crate::profile::{get_profile_fields, get_profile_values};
+use crate::signed_link::SignedLink;
+use crate::startup::HmacSecret;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub async fn preferences_page(
+    link: web::Query<SignedLink>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = verify_link(&link, &hmac_secret)?;
+    let subscriber = get_subscriber(&pool, subscriber_id)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("We hold no data about you."))?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    if matches!(subscriber.status.as_str(), "bounced" | "complained") {
+        msg_html.push_str("<p>We cannot deliver emails to your address at the moment.</p>");
+    }
+
+    let values = get_profile_values(&pool, subscriber_id)
+        .await
+        .map_err(e500)?;
+    let mut fields_html = String::new();
+    for field in get_profile_fields(&pool).await.map_err(e500)? {
+        writeln!(
+            fields_html,
+            "<label>{}<br>{}</label><br>",
+            encode_minimal(&field.label),
+            field_input_html(&field, values.get(field.key.as_ref()))
+        )
+        .unwrap();
+    }
+
+    let joined = get_joined_lists(&pool, subscriber_id).await.map_err(e500)?;
+    let mut lists_html = String::new();
+    for list in get_lists(&pool).await.map_err(e500)? {
+        writeln!(
+            lists_html,
+            r#"<label>
+            <input type="checkbox" name="lists" value="{}"{}>
+            {}
+        </label><br>"#,
+            encode_attribute(&list.slug),
+            if joined.contains(&list.list_id) {
+                " checked"
+            } else {
+                ""
+            },
+            encode_minimal(&list.name),
+        )
+        .unwrap();
+    }
+
+    let mut frequencies_html = String::new();
+    for frequency in DigestFrequency::ALL {
+        writeln!(
+            frequencies_html,
+            r#"<option value="{}"{}>{}</option>"#,
+            frequency.as_str(),
+            if frequency.as_str() == subscriber.digest_frequency {
+                " selected"
+            } else {
+                ""
+            },
+            frequency.label(),
+        )
+        .unwrap();
+    }
+
+    let SignedLink {
+        subscriber_id,
+        expires_at,
+        signature,
+    } = link.into_inner();
+    let signature = encode_attribute(&signature);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Your preferences</title>
+</head>
+<body>
+    {msg_html}
+    <p>Hi {name}, tell us what you would like to receive.</p>
+    <form action="/subscriptions/preferences" method="post">
+        <input hidden type="text" name="subscriber_id" value="{subscriber_id}">
+        <input hidden type="text" name="expires_at" value="{expires_at}">
+        <input hidden type="text" name="signature" value="{signature}">
+        <h2>About you</h2>
+        {fields_html}
+        <h2>Lists</h2>
+        {lists_html}
+        <p>{consent_text}</p>
+        <h2>Frequency</h2>
+        <select name="digest_frequency">
+            {frequencies_html}
+        </select>
+        <br>
+        <button type="submit">Save my preferences</button>
+    </form>
+</body>
+</html>"#,
+            name = encode_minimal(&subscriber.name),
+            consent_text = encode_minimal(CONSENT_TEXT),
+        )))
+}
+
+/// Profile inputs are named after their field, prefixed with `profile.`.
+fn field_input_html(field: &ProfileField, value: Option<&String>) -> String {
+    // Keys are identifiers, they need no more than minimal escaping
+    let name = encode_minimal(&format!("profile.{}", field.key.as_ref()));
+    let value = value.map(String::as_str).unwrap_or_default();
+    match &field.kind {
+        ProfileFieldKind::Text => format!(
+            r#"<input type="text" name="{}" value="{}">"#,
+            name,
+            encode_attribute(value)
+        ),
+        ProfileFieldKind::Enum(options) => {
+            let mut options_html = String::from(r#"<option value="">-</option>"#);
+            for option in options {
+                write!(
+                    options_html,
+                    r#"<option value="{}"{}>{}</option>"#,
+                    encode_attribute(option),
+                    if option == value { " selected" } else { "" },
+                    encode_minimal(option),
+                )
+                .unwrap();
+            }
+            format!(r#"<select name="{}">{}</select>"#, name, options_html)
+        }
+        ProfileFieldKind::Boolean => format!(
+            r#"<input type="checkbox" name="{}" value="true"{}>"#,
+            name,
+            if value == "true" { " checked" } else { "" }
+        ),
+    }
+}
+
+/// The lists the subscriber joined or asked to join.
+#[tracing::instrument(name = "Get joined lists", skip(pool))]
+async fn get_joined_lists(pool: &PgPool, subscriber_id: Uuid) -> Result<Vec<Uuid>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT list_id FROM list_subscriptions
+        WHERE subscriber_id = $1
+        AND status IN ('pending_confirmation', 'confirmed')
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the lists of the subscriber.")?;
+    Ok(rows.into_iter().map(|r| r.list_id).collect())
+}
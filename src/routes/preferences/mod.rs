@@ -0,0 +1,63 @@
+mod get;
+mod post;
+
+pub use get::preferences_page;
+pub use post::save_preferences;
+
+use crate::signed_link::{LinkPurpose, SignedLink};
+use crate::startup::HmacSecret;
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Preference links go out with every issue: they stay valid for
+/// longer than data links, but only give access to preferences.
+const LINK_VALIDITY_DAYS: i64 = 30;
+
+/// The link to the preference centre of a subscriber.
+pub fn preferences_url(base_url: &str, subscriber_id: Uuid, secret: &Secret<String>) -> String {
+    let link = SignedLink::new(
+        LinkPurpose::Preferences,
+        subscriber_id,
+        chrono::Duration::days(LINK_VALIDITY_DAYS),
+        secret,
+    );
+    format!(
+        "{}/subscriptions/preferences?{}",
+        base_url,
+        link.query_string()
+    )
+}
+
+fn verify_link(link: &SignedLink, secret: &HmacSecret) -> Result<Uuid, actix_web::Error> {
+    link.verify(LinkPurpose::Preferences, &secret.0)
+        .map_err(actix_web::error::ErrorUnauthorized)
+}
+
+struct Subscriber {
+    name: String,
+    status: String,
+    digest_frequency: String,
+}
+
+/// Erased subscribers have no preferences left to manage.
+#[tracing::instrument(name = "Get subscriber preferences", skip(pool))]
+async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Subscriber>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT name, status, digest_frequency
+        FROM subscriptions
+        WHERE id = $1 AND status != 'erased'
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber.")?;
+    Ok(subscriber)
+}
@@ -0,0 +1,251 @@
+use super::{get_subscriber, verify_link};
+use crate::consent::{record_consent_event, ConsentEventKind, ConsentEvidence, CONSENT_TEXT};
+use crate::domain::{DigestFrequency, ListSlug};
+use crate::lists::get_lists_by_slug;
+use crate::profile::{get_profile_fields, save_profile_value};
+use crate::routes::{
+    generate_subscription_token, mark_subscriber_as_unsubscribed, TokenSubscription,
+};
+use crate::signed_link::SignedLink;
+use crate::startup::HmacSecret;
+use crate::utils::{e400, e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+struct FormData {
+    subscriber_id: Uuid,
+    expires_at: i64,
+    signature: String,
+    // Unchecked checkboxes are not submitted at all
+    #[serde(default)]
+    lists: Vec<String>,
+    digest_frequency: String,
+}
+
+#[tracing::instrument(name = "Save subscriber preferences", skip_all)]
+pub async fn save_preferences(
+    body: web::Bytes,
+    evidence: ConsentEvidence,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // `web::Form` cannot deserialize the repeated `lists` checkboxes,
+    // nor the profile inputs, whose names depend on the fields defined.
+    let form: FormData = serde_html_form::from_bytes(&body).map_err(e400)?;
+    let inputs: Vec<(String, String)> = serde_html_form::from_bytes(&body).map_err(e400)?;
+    let link = SignedLink {
+        subscriber_id: form.subscriber_id,
+        expires_at: form.expires_at,
+        signature: form.signature,
+    };
+    let subscriber_id = verify_link(&link, &hmac_secret)?;
+    let preferences_page = format!("/subscriptions/preferences?{}", link.query_string());
+    if get_subscriber(&pool, subscriber_id)
+        .await
+        .map_err(e500)?
+        .is_none()
+    {
+        return Err(actix_web::error::ErrorNotFound(
+            "We hold no data about you.",
+        ));
+    }
+
+    let mut profile = Vec::new();
+    for field in get_profile_fields(&pool).await.map_err(e500)? {
+        let name = format!("profile.{}", field.key.as_ref());
+        let input = inputs
+            .iter()
+            .find(|(k, _)| *k == name)
+            .map(|(_, v)| v.as_str());
+        match field.parse_value(input) {
+            Ok(value) => profile.push((field.key, value)),
+            Err(e) => {
+                FlashMessage::error(e).send();
+                return Ok(see_other(&preferences_page));
+            }
+        }
+    }
+    let digest_frequency = match DigestFrequency::parse(&form.digest_frequency) {
+        Ok(frequency) => frequency,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&preferences_page));
+        }
+    };
+    let slugs = match form
+        .lists
+        .into_iter()
+        .map(ListSlug::parse)
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(slugs) => slugs,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&preferences_page));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let lists = get_lists_by_slug(&mut transaction, &slugs)
+        .await
+        .context("Failed to retrieve the lists to join")
+        .map_err(e500)?;
+    if let Some(unknown) = slugs
+        .iter()
+        .find(|slug| !lists.iter().any(|l| l.slug == slug.as_ref()))
+    {
+        FlashMessage::error(format!("There is no list called {}.", unknown.as_ref())).send();
+        return Ok(see_other(&preferences_page));
+    }
+    for (key, value) in &profile {
+        save_profile_value(&mut transaction, subscriber_id, key, value.as_ref())
+            .await
+            .context("Failed to save the profile of the subscriber")
+            .map_err(e500)?;
+    }
+    save_digest_frequency(&mut transaction, subscriber_id, digest_frequency)
+        .await
+        .context("Failed to save the digest frequency of the subscriber")
+        .map_err(e500)?;
+    let list_ids: Vec<_> = lists.iter().map(|l| l.list_id).collect();
+    switch_lists(&mut transaction, subscriber_id, &list_ids, &evidence)
+        .await
+        .context("Failed to update the lists of the subscriber")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to save the preferences of a subscriber.")
+        .map_err(e500)?;
+
+    FlashMessage::info("Your preferences have been saved.").send();
+    Ok(see_other(&preferences_page))
+}
+
+#[tracing::instrument(name = "Save digest frequency", skip(transaction))]
+async fn save_digest_frequency(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    digest_frequency: DigestFrequency,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE subscriptions SET digest_frequency = $2 WHERE id = $1",
+        subscriber_id,
+        digest_frequency.as_str(),
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Join the given lists and leave the others.
+///
+/// The signed link proves that the subscriber owns the address:
+/// joining a list from the preference centre needs no confirmation.
+/// Addresses we cannot deliver to are left as they are.
+#[tracing::instrument(name = "Switch lists", skip(transaction, evidence))]
+async fn switch_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+    evidence: &ConsentEvidence,
+) -> Result<(), anyhow::Error> {
+    let current = sqlx::query!(
+        "SELECT list_id, status FROM list_subscriptions WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+
+    for &list_id in list_ids {
+        let is_confirmed = current
+            .iter()
+            .any(|s| s.list_id == list_id && s.status == "confirmed");
+        if !is_confirmed {
+            join_list(transaction, subscriber_id, list_id).await?;
+            record_consent_event(
+                transaction,
+                subscriber_id,
+                Some(list_id),
+                ConsentEventKind::SubscriptionConfirmed,
+                evidence,
+                Some(CONSENT_TEXT),
+            )
+            .await?;
+        }
+    }
+    if !list_ids.is_empty() {
+        sqlx::query!(
+            r#"
+            UPDATE subscriptions SET status = 'confirmed'
+            WHERE id = $1 AND status IN ('pending_confirmation', 'unsubscribed')
+            "#,
+            subscriber_id,
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    for s in current {
+        if s.status != "unsubscribed" && !list_ids.contains(&s.list_id) {
+            let subscription = TokenSubscription {
+                subscriber_id,
+                list_id: s.list_id,
+            };
+            mark_subscriber_as_unsubscribed(transaction, &subscription).await?;
+            record_consent_event(
+                transaction,
+                subscriber_id,
+                Some(s.list_id),
+                ConsentEventKind::Unsubscribed,
+                evidence,
+                None,
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Issues carry an unsubscribe link for one of their lists:
+/// every list subscription needs a token.
+async fn join_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+        VALUES ($1, $2, 'confirmed', now())
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE SET status = 'confirmed'
+        "#,
+        list_id,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id)
+        SELECT $1, $2, $3
+        WHERE NOT EXISTS (
+            SELECT 1 FROM subscription_tokens WHERE subscriber_id = $2 AND list_id = $3
+        )
+        "#,
+        generate_subscription_token(),
+        subscriber_id,
+        list_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use super::verify_link;
+use crate::consent::{record_consent_event, ConsentEventKind, ConsentEvidence};
+use crate::signed_link::SignedLink;
+use crate::startup::HmacSecret;
+use crate::utils::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[tracing::instrument(name = "Erase subscriber data", skip(form, pool, hmac_secret))]
+pub async fn erase_subscriber_data(
+    form: web::Form<SignedLink>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = verify_link(&form, &hmac_secret)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    erase_subscriber(&mut transaction, subscriber_id)
+        .await
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a subscriber.")
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Your data</title>
+</head>
+<body>
+    <p>Your data has been erased.</p>
+</body>
+</html>"#,
+    ))
+}
+
+/// Remove or anonymise every piece of personal data we hold
+/// about a subscriber.
+///
+/// The `subscriptions` row itself is kept, anonymised, so that the
+/// consent history and the delivery statistics stay consistent.
+///
+/// Suppressions are kept as they are: they are how we honour a bounce or
+/// a spam complaint, and the address would be mailed again if it ever
+/// came back through the subscription form.
+#[tracing::instrument(name = "Erase a subscriber", skip(transaction))]
+async fn erase_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let email = match sqlx::query!(
+        r#"
+        SELECT email
+        FROM subscriptions
+        WHERE id = $1 AND status != 'erased'
+        FOR UPDATE
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve the subscriber to erase.")?
+    {
+        Some(r) => r.email,
+        // Already erased, nothing left to do
+        None => return Ok(()),
+    };
+    let erased_email = format!("{}@erased.invalid", subscriber_id);
+
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the subscription tokens.")?;
+    sqlx::query!(
+        "DELETE FROM list_subscriptions WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the list subscriptions.")?;
+    sqlx::query!(
+        "DELETE FROM subscriber_tags WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the subscriber tags.")?;
+    sqlx::query!(
+        "DELETE FROM subscriber_profile_values WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the subscriber profile.")?;
+    sqlx::query!(
+        "DELETE FROM issue_delivery_queue WHERE subscriber_id = $1 OR recipient = $2",
+        subscriber_id,
+        email,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the pending deliveries.")?;
+    sqlx::query!(
+        "DELETE FROM digest_queue WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the issues held for a digest.")?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_log SET subscriber_email = $2
+        WHERE lower(subscriber_email) = lower($1)
+        "#,
+        email,
+        erased_email,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to anonymise the delivery history.")?;
+    sqlx::query!(
+        "DELETE FROM issue_engagement_events WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the engagement history.")?;
+    sqlx::query!(
+        r#"
+        UPDATE consent_events
+        SET ip_address = NULL, user_agent = NULL
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to anonymise the consent history.")?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET email = $2, name = 'erased', status = 'erased'
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        erased_email,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to anonymise the subscription.")?;
+
+    // Keep track of the fact that we honoured the request,
+    // without any evidence that could identify the subscriber.
+    record_consent_event(
+        transaction,
+        subscriber_id,
+        None,
+        ConsentEventKind::DataErased,
+        &ConsentEvidence::default(),
+        None,
+    )
+    .await
+    .context("Failed to record the erasure.")?;
+    Ok(())
+}
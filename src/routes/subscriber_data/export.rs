@@ -0,0 +1,239 @@
+use super::verify_link;
+use crate::consent::{get_consent_history, ConsentRecord};
+use crate::signed_link::SignedLink;
+use crate::startup::HmacSecret;
+use crate::utils::e500;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Everything we hold about a subscriber.
+#[derive(serde::Serialize)]
+struct SubscriberDataBundle {
+    subscription: Subscription,
+    lists: Vec<ListSubscription>,
+    tags: Vec<String>,
+    profile: Vec<ProfileValue>,
+    subscription_tokens: Vec<String>,
+    consent_events: Vec<ConsentRecord>,
+    deliveries: Vec<Delivery>,
+    pending_deliveries: Vec<PendingDelivery>,
+    engagement_events: Vec<EngagementEvent>,
+}
+
+#[derive(serde::Serialize)]
+struct Subscription {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    digest_frequency: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct ProfileValue {
+    field: String,
+    value: String,
+}
+
+#[derive(serde::Serialize)]
+struct ListSubscription {
+    slug: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct Delivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+    outcome: String,
+    recorded_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+struct PendingDelivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+}
+
+#[derive(serde::Serialize)]
+struct EngagementEvent {
+    newsletter_issue_id: Uuid,
+    event_type: String,
+    url: Option<String>,
+    occurred_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Export subscriber data", skip(link, pool, hmac_secret))]
+pub async fn export_subscriber_data(
+    link: web::Query<SignedLink>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = verify_link(&link, &hmac_secret)?;
+    let bundle = match get_subscriber_data(&pool, subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(bundle) => bundle,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "subscriber-data-{}.json",
+                subscriber_id
+            ))],
+        })
+        .json(bundle))
+}
+
+#[tracing::instrument(name = "Get subscriber data", skip(pool))]
+async fn get_subscriber_data(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberDataBundle>, anyhow::Error> {
+    let subscription = match sqlx::query_as!(
+        Subscription,
+        r#"
+        SELECT id, email, name, status, digest_frequency, subscribed_at
+        FROM subscriptions
+        WHERE id = $1 AND status != 'erased'
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscription.")?
+    {
+        Some(subscription) => subscription,
+        None => return Ok(None),
+    };
+
+    let lists = sqlx::query_as!(
+        ListSubscription,
+        r#"
+        SELECT l.slug, l.name, ls.status, ls.subscribed_at
+        FROM list_subscriptions ls
+        JOIN lists l USING (list_id)
+        WHERE ls.subscriber_id = $1
+        ORDER BY ls.subscribed_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the list subscriptions.")?;
+
+    let tags = sqlx::query!(
+        "SELECT tag FROM subscriber_tags WHERE subscriber_id = $1 ORDER BY tag",
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the tags.")?
+    .into_iter()
+    .map(|r| r.tag)
+    .collect();
+
+    let profile = sqlx::query_as!(
+        ProfileValue,
+        r#"
+        SELECT field_key AS field, value
+        FROM subscriber_profile_values
+        WHERE subscriber_id = $1
+        ORDER BY field_key
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the profile.")?;
+
+    let subscription_tokens = sqlx::query!(
+        r#"
+        SELECT subscription_token
+        FROM subscription_tokens
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscription tokens.")?
+    .into_iter()
+    .map(|r| r.subscription_token)
+    .collect();
+
+    let consent_events = get_consent_history(pool, subscriber_id)
+        .await
+        .context("Failed to retrieve the consent history.")?;
+
+    let deliveries = sqlx::query_as!(
+        Delivery,
+        r#"
+        SELECT l.newsletter_issue_id, i.title, l.outcome, l.recorded_at
+        FROM issue_delivery_log l
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE l.subscriber_email = $1
+        ORDER BY l.recorded_at
+        "#,
+        subscription.email,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the delivery history.")?;
+
+    let pending_deliveries = sqlx::query_as!(
+        PendingDelivery,
+        r#"
+        SELECT q.newsletter_issue_id AS "newsletter_issue_id!", i.title AS "title!"
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE q.subscriber_id = $1
+        -- Issues held for the next digest
+        UNION ALL
+        SELECT d.newsletter_issue_id, i.title
+        FROM digest_queue d
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE d.subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the pending deliveries.")?;
+
+    let engagement_events = sqlx::query_as!(
+        EngagementEvent,
+        r#"
+        SELECT newsletter_issue_id, event_type, url, occurred_at
+        FROM issue_engagement_events
+        WHERE subscriber_id = $1
+        ORDER BY occurred_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the engagement history.")?;
+
+    Ok(Some(SubscriberDataBundle {
+        subscription,
+        lists,
+        tags,
+        profile,
+        subscription_tokens,
+        consent_events,
+        deliveries,
+        pending_deliveries,
+        engagement_events,
+    }))
+}
@@ -0,0 +1,49 @@
+use super::verify_link;
+use crate::routes::preferences_url;
+use crate::signed_link::SignedLink;
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+
+pub async fn subscriber_data_page(
+    link: web::Query<SignedLink>,
+    hmac_secret: web::Data<HmacSecret>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    verify_link(&link, &hmac_secret)?;
+
+    let preferences_url = htmlescape::encode_attribute(&preferences_url(
+        &base_url.0,
+        link.subscriber_id,
+        &hmac_secret.0,
+    ));
+    let query_string = htmlescape::encode_attribute(&link.query_string());
+    let SignedLink {
+        subscriber_id,
+        expires_at,
+        signature,
+    } = link.into_inner();
+    let signature = htmlescape::encode_attribute(&signature);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Your data</title>
+</head>
+<body>
+    <p><a href="{preferences_url}">Manage my preferences</a></p>
+    <p><a href="/subscriptions/data/export?{query_string}">Download my data</a></p>
+    <form action="/subscriptions/data/erase" method="post">
+        <input hidden type="text" name="subscriber_id" value="{subscriber_id}">
+        <input hidden type="text" name="expires_at" value="{expires_at}">
+        <input hidden type="text" name="signature" value="{signature}">
+        <p>Erasing your data unsubscribes you and cannot be undone.</p>
+        <button type="submit">Erase my data</button>
+    </form>
+</body>
+</html>"#,
+        )))
+}
@@ -0,0 +1,22 @@
+mod erase;
+mod export;
+mod manage;
+mod request;
+
+pub use erase::erase_subscriber_data;
+pub use export::export_subscriber_data;
+pub use manage::subscriber_data_page;
+pub use request::{request_subscriber_data, subscriber_data_request_form};
+
+use crate::signed_link::{LinkPurpose, SignedLink};
+use crate::startup::HmacSecret;
+use uuid::Uuid;
+
+/// Links are only valid for a day: they grant access
+/// to everything we hold about a subscriber.
+const LINK_VALIDITY_HOURS: i64 = 24;
+
+fn verify_link(link: &SignedLink, secret: &HmacSecret) -> Result<Uuid, actix_web::Error> {
+    link.verify(LinkPurpose::SubscriberData, &secret.0)
+        .map_err(actix_web::error::ErrorUnauthorized)
+}
@@ -0,0 +1,139 @@
+use super::LINK_VALIDITY_HOURS;
+use crate::domain::SubscriberEmail;
+use crate::issue_delivery_worker::enqueue_transactional_email;
+use crate::signed_link::{LinkPurpose, SignedLink};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::utils::{e400, e500};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+pub async fn subscriber_data_request_form() -> HttpResponse {
+    HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Your data</title>
+</head>
+<body>
+    <p>Enter the address you subscribed with: we will email you a link
+    to download or erase the data we hold about you.</p>
+    <form action="/subscriptions/data" method="post">
+        <label>Email
+            <input type="email" placeholder="Enter your email" name="email">
+        </label>
+        <button type="submit">Send me a link</button>
+    </form>
+</body>
+</html>"#,
+    )
+}
+
+#[tracing::instrument(
+    name = "Request access to subscriber data",
+    skip(form, pool, base_url, hmac_secret)
+)]
+pub async fn request_subscriber_data(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = SubscriberEmail::parse(form.0.email).map_err(e400)?;
+
+    // We answer in the same way whether we know the address or not,
+    // to avoid disclosing who is subscribed.
+    if let Some(subscriber_id) = get_subscriber_id_by_email(&pool, &email)
+        .await
+        .map_err(e500)?
+    {
+        let link = SignedLink::new(
+            LinkPurpose::SubscriberData,
+            subscriber_id,
+            chrono::Duration::hours(LINK_VALIDITY_HOURS),
+            &hmac_secret.0,
+        );
+        queue_subscriber_data_email(&pool, &email, &base_url.0, &link)
+            .await
+            .context("Failed to queue the subscriber data email.")
+            .map_err(e500)?;
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Your data</title>
+</head>
+<body>
+    <p>If we hold any data about this address, you will shortly receive an email with a link to manage it.</p>
+</body>
+</html>"#,
+        ))
+}
+
+#[tracing::instrument(name = "Get subscriber_id from email", skip(pool, email))]
+async fn get_subscriber_id_by_email(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT id
+        FROM subscriptions
+        WHERE lower(email) = lower($1) AND status != 'erased'
+        "#,
+        email.as_ref(),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve a subscriber.")?;
+    Ok(row.map(|r| r.id))
+}
+
+#[tracing::instrument(name = "Send a link to manage their data to a subscriber", skip_all)]
+async fn queue_subscriber_data_email(
+    pool: &PgPool,
+    recipient: &SubscriberEmail,
+    base_url: &str,
+    link: &SignedLink,
+) -> Result<(), sqlx::Error> {
+    let manage_link = format!(
+        "{}/subscriptions/data/manage?{}",
+        base_url,
+        link.query_string()
+    );
+    let plain_body = format!(
+        "You asked to access the data we hold about you.\n\
+        Visit {} to download or erase it. The link is valid for {} hours.",
+        manage_link, LINK_VALIDITY_HOURS,
+    );
+    let html_body = format!(
+        "You asked to access the data we hold about you.<br />\
+        Click <a href=\"{}\">here</a> to download or erase it. \
+        The link is valid for {} hours.",
+        manage_link, LINK_VALIDITY_HOURS,
+    );
+    let mut transaction = pool.begin().await?;
+    enqueue_transactional_email(
+        &mut transaction,
+        recipient,
+        "Your data",
+        &html_body,
+        &plain_body,
+        0,
+    )
+    .await?;
+    transaction.commit().await
+}
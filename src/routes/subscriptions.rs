@@ -1,4 +1,9 @@
-use crate::email_client::EmailClient;
+use crate::consent::{record_consent_event, ConsentEventKind, ConsentEvidence, CONSENT_TEXT};
+use crate::email_templates::{EmailTemplate, EmailTemplates, RenderedEmail};
+use crate::idempotency::IdempotentTransaction;
+use crate::issue_delivery_worker::enqueue_transactional_email;
+use crate::lists::{get_list, MailingList, DEFAULT_LIST};
+use crate::metrics::metrics;
 use crate::startup::ApplicationBaseUrl;
 use actix_web::http::StatusCode;
 use actix_web::{web, HttpResponse, ResponseError};
@@ -10,73 +15,180 @@ use sqlx::PgPool;
 use sqlx::{Postgres, Transaction};
 use uuid::Uuid;
 
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::domain::{ListSlug, NewSubscriber, SubscriberEmail, SubscriberName};
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     email: String,
     name: String,
+    // The slug of the list to join, the default list if missing
+    #[serde(default)]
+    list: Option<String>,
 }
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip(form, evidence, transaction, pool, templates, base_url),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name,
+        list = ?form.list,
         )
     )]
 pub async fn subscribe(
     form: web::Form<FormData>,
+    evidence: ConsentEvidence,
+    // Only present if the form carried an idempotency key
+    transaction: Option<IdempotentTransaction>,
     // Retrieving a pool from the application state
     pool: web::Data<PgPool>,
-    // Get the email client from the app context
-    email_client: web::Data<EmailClient>,
+    templates: web::Data<EmailTemplates>,
     base_url: web::Data<ApplicationBaseUrl>,
 ) -> Result<HttpResponse, SubscribeError> {
-    // Get the subscriber details from the incoming request
     // `web::Form` is a wrapper around `FormData`
     // `form.0` gives us access to the underlying `FormData`
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let outcome = try_subscribe(
+        form.0,
+        evidence,
+        transaction,
+        &pool,
+        &templates,
+        &base_url.0,
+    )
+    .await;
+    let label = match &outcome {
+        Ok(_) => "subscribed",
+        Err(SubscribeError::ValidationError(_)) => "invalid",
+        Err(SubscribeError::UnexpectedError(_)) => "error",
+    };
+    metrics().subscriptions.with_label_values(&[label]).inc();
+    outcome
+}
 
-    // Start the transaction for db operations
-    let mut transaction = pool
-        .begin()
+async fn try_subscribe(
+    mut form: FormData,
+    evidence: ConsentEvidence,
+    transaction: Option<IdempotentTransaction>,
+    pool: &PgPool,
+    templates: &EmailTemplates,
+    base_url: &str,
+) -> Result<HttpResponse, SubscribeError> {
+    let list_slug = ListSlug::parse(form.list.take().unwrap_or_else(|| DEFAULT_LIST.into()))
+        .map_err(SubscribeError::ValidationError)?;
+    let new_subscriber = form.try_into().map_err(SubscribeError::ValidationError)?;
+    let list = get_list(pool, &list_slug)
         .await
-        .context("Failed to acquire a Postgres connection from the pool")?;
+        .context("Failed to retrieve the list to subscribe to.")?
+        .ok_or_else(|| {
+            SubscribeError::ValidationError(format!(
+                "There is no list called {}.",
+                list_slug.as_ref()
+            ))
+        })?;
+
+    let subscription_token = generate_subscription_token();
+    let confirmation_email = render_confirmation_email(
+        pool,
+        templates,
+        &new_subscriber,
+        &list,
+        base_url,
+        &subscription_token,
+    )
+    .await
+    .context("Failed to render a confirmation email.")?;
+
+    match transaction {
+        // Double submissions are answered by the idempotency middleware:
+        // we write through its transaction so that the subscriber is only
+        // stored if the response is saved as well.
+        Some(transaction) => {
+            let mut transaction = transaction.lock().await?;
+            store_new_subscriber(
+                &mut transaction,
+                &new_subscriber,
+                &list,
+                &evidence,
+                &subscription_token,
+                &confirmation_email,
+            )
+            .await?;
+        }
+        None => {
+            // Start the transaction for db operations
+            let mut transaction = pool
+                .begin()
+                .await
+                .context("Failed to acquire a Postgres connection from the pool")?;
+            store_new_subscriber(
+                &mut transaction,
+                &new_subscriber,
+                &list,
+                &evidence,
+                &subscription_token,
+                &confirmation_email,
+            )
+            .await?;
+            // Commit the transaction
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to store a new subscriber.")?;
+        }
+    }
+
+    Ok(HttpResponse::Ok().finish())
+}
 
+/// Insert the subscriber - unless they already joined another list -,
+/// their pending subscription to the list, their confirmation token and
+/// the evidence of their consent, and queue their confirmation email.
+async fn store_new_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+    list: &MailingList,
+    evidence: &ConsentEvidence,
+    subscription_token: &str,
+    confirmation_email: &RenderedEmail,
+) -> Result<(), anyhow::Error> {
     // Insert the subscriber in the db
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    let subscriber_id = insert_subscriber(transaction, new_subscriber)
         .await
         .context("Failed to insert new subscriber in the database.")?;
+    insert_list_subscription(transaction, subscriber_id, list.list_id)
+        .await
+        .context("Failed to subscribe the subscriber to the list.")?;
 
-    // Generate and store token in db
-    let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+    // Store token in db
+    store_token(transaction, subscriber_id, list.list_id, subscription_token)
         .await
         .context(
             "Failed to store the confirmation token for a new \
             subscriber.",
         )?;
 
-    // Commit the transaction
-    transaction
-        .commit()
-        .await
-        .context("Failed to commit SQL transaction to store a new subscriber.")?;
-
-    // Send a (useless) email to the new subscriber.
-    // We are ignoring email delivery errors for now.
-    send_confirmation_email(
-        &email_client,
-        new_subscriber,
-        &base_url.0,
-        &subscription_token,
+    record_consent_event(
+        transaction,
+        subscriber_id,
+        Some(list.list_id),
+        ConsentEventKind::SubscriptionRequested,
+        evidence,
+        Some(CONSENT_TEXT),
     )
     .await
-    .context("Failed to send a confirmation email.")?;
+    .context("Failed to record the consent of a new subscriber.")?;
 
-    Ok(HttpResponse::Ok().finish())
+    enqueue_transactional_email(
+        transaction,
+        &new_subscriber.email,
+        &confirmation_email.subject,
+        &confirmation_email.html,
+        &confirmation_email.text,
+        0,
+    )
+    .await
+    .context("Failed to queue a confirmation email.")?;
+    Ok(())
 }
 
 #[derive(thiserror::Error)]
@@ -109,13 +221,15 @@ impl ResponseError for SubscribeError {
 pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
+    list_id: Uuid,
     subscription_token: &str,
 ) -> Result<(), StoreTokenError> {
     sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-        VALUES ($1, $2)"#,
+        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id)
+        VALUES ($1, $2, $3)"#,
         subscription_token,
         subscriber_id,
+        list_id,
     )
     .execute(transaction)
     .await
@@ -171,30 +285,31 @@ pub fn error_chain_fmt(
 }
 
 #[tracing::instrument(
-    name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber, base_url, subscription_token)
+    name = "Render the confirmation email of a new subscriber",
+    skip(pool, templates, new_subscriber, list, base_url, subscription_token)
 )]
-pub async fn send_confirmation_email(
-    email_client: &EmailClient,
-    new_subscriber: NewSubscriber,
+pub async fn render_confirmation_email(
+    pool: &PgPool,
+    templates: &EmailTemplates,
+    new_subscriber: &NewSubscriber,
+    list: &MailingList,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<RenderedEmail, anyhow::Error> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         base_url, subscription_token,
     );
-    let plain_body = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link,
-    );
-    let html_body = format!(
-        "Welcome to our newsletter!<br />\
-Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
-    );
-    email_client
-        .send_email(&new_subscriber.email, "Welcome!", &html_body, &plain_body)
+    templates
+        .render(
+            pool,
+            EmailTemplate::Confirmation,
+            &serde_json::json!({
+                "name": new_subscriber.name.as_ref(),
+                "list": list.name,
+                "confirmation_link": confirmation_link,
+            }),
+        )
         .await
 }
 
@@ -210,6 +325,8 @@ impl TryFrom<FormData> for NewSubscriber {
     }
 }
 
+/// Returns the id of the existing subscriber if the address
+/// already subscribed to another list.
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
     skip(new_subscriber, transaction)
@@ -218,24 +335,50 @@ pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
 ) -> Result<Uuid, sqlx::Error> {
-    let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
+    // The no-op update makes `RETURNING` yield the existing row
+    let subscriber = sqlx::query!(
         r#"
         INSERT INTO subscriptions (id, email, name, subscribed_at, status)
         VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        ON CONFLICT (email) DO UPDATE SET email = EXCLUDED.email
+        RETURNING id
         "#,
-        subscriber_id,
+        Uuid::new_v4(),
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
         Utc::now(),
     )
+    .fetch_one(transaction)
+    .await?;
+    Ok(subscriber.id)
+}
+
+/// Subscribing again to a list is a no-op, unless the subscriber
+/// left it: they have to confirm again.
+#[tracing::instrument(name = "Saving a new list subscription", skip(transaction))]
+async fn insert_list_subscription(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, status, subscribed_at)
+        VALUES ($1, $2, 'pending_confirmation', now())
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET status = 'pending_confirmation', subscribed_at = EXCLUDED.subscribed_at
+        WHERE list_subscriptions.status = 'unsubscribed'
+        "#,
+        list_id,
+        subscriber_id,
+    )
     .execute(transaction)
     .await?;
-    Ok(subscriber_id)
+    Ok(())
 }
 
 /// Generate a random 25-characters-long case-sensitive subscription token
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
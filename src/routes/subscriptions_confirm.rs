@@ -1,7 +1,9 @@
+use crate::consent::{record_consent_event, ConsentEventKind, ConsentEvidence};
+use crate::metrics::metrics;
 use crate::routes::error_chain_fmt;
 use actix_web::http::StatusCode;
 use actix_web::{web, HttpResponse, ResponseError};
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
@@ -32,48 +34,112 @@ impl ResponseError for ConfirmationError {
     }
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
-pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>) -> HttpResponse {
-    let id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
-        Ok(id) => id,
-        Err(_) => return HttpResponse::InternalServerError().finish(),
-    };
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(parameters, evidence, pool)
+)]
+pub async fn confirm(
+    parameters: web::Query<Parameters>,
+    evidence: ConsentEvidence,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    let confirmations = &metrics().confirmations;
+    let subscription =
+        match get_subscription_from_token(&pool, &parameters.subscription_token).await {
+            Ok(subscription) => subscription,
+            Err(_) => {
+                confirmations.with_label_values(&["error"]).inc();
+                return HttpResponse::InternalServerError().finish();
+            }
+        };
 
-    match id {
+    match subscription {
         // Non-existing token!
-        None => HttpResponse::Unauthorized().finish(),
-        Some(subscriber_id) => {
-            if confirm_subscriber(&pool, subscriber_id).await.is_err() {
+        None => {
+            confirmations.with_label_values(&["unknown_token"]).inc();
+            HttpResponse::Unauthorized().finish()
+        }
+        Some(subscription) => {
+            if confirm_and_record_consent(&pool, &subscription, &evidence)
+                .await
+                .is_err()
+            {
+                confirmations.with_label_values(&["error"]).inc();
                 return HttpResponse::InternalServerError().finish();
             }
 
+            confirmations.with_label_values(&["confirmed"]).inc();
             HttpResponse::Ok().finish()
         }
     }
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+async fn confirm_and_record_consent(
+    pool: &PgPool,
+    subscription: &TokenSubscription,
+    evidence: &ConsentEvidence,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    confirm_subscriber(&mut transaction, subscription).await?;
+    record_consent_event(
+        &mut transaction,
+        subscription.subscriber_id,
+        Some(subscription.list_id),
+        ConsentEventKind::SubscriptionConfirmed,
+        evidence,
+        None,
+    )
+    .await?;
+    transaction.commit().await
+}
+
+/// Confirming a list subscription confirms the address as well, unless
+/// it bounced or complained: it stays suppressed.
+#[tracing::instrument(name = "Mark subscriber as confirmed", skip(transaction))]
+pub async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription: &TokenSubscription,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE list_subscriptions SET status = 'confirmed'
+        WHERE subscriber_id = $1 AND list_id = $2
+        "#,
+        subscription.subscriber_id,
+        subscription.list_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
     sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
-        subscriber_id,
+        r#"
+        UPDATE subscriptions SET status = 'confirmed'
+        WHERE id = $1 AND status IN ('pending_confirmation', 'unsubscribed')
+        "#,
+        subscription.subscriber_id,
     )
-    .execute(pool)
+    .execute(transaction)
     .await?;
     Ok(())
 }
 
-#[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
-pub async fn get_subscriber_id_from_token(
+/// The list subscription a confirmation or unsubscribe link acts on.
+#[derive(Debug)]
+pub struct TokenSubscription {
+    pub subscriber_id: Uuid,
+    pub list_id: Uuid,
+}
+
+#[tracing::instrument(name = "Get subscription from token", skip(subscription_token, pool))]
+pub async fn get_subscription_from_token(
     pool: &PgPool,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
-        "SELECT subscriber_id FROM subscription_tokens \
+) -> Result<Option<TokenSubscription>, sqlx::Error> {
+    sqlx::query_as!(
+        TokenSubscription,
+        "SELECT subscriber_id, list_id FROM subscription_tokens \
         WHERE subscription_token = $1",
         subscription_token,
     )
     .fetch_optional(pool)
-    .await?;
-    Ok(result.map(|r| r.subscriber_id))
+    .await
 }
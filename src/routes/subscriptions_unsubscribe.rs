@@ -0,0 +1,208 @@
+use crate::consent::{record_consent_event, ConsentEventKind, ConsentEvidence};
+use crate::domain::SubscriberEmail;
+use crate::email_templates::{EmailTemplate, EmailTemplates, RenderedEmail};
+use crate::issue_delivery_worker::enqueue_transactional_email;
+use crate::routes::{error_chain_fmt, get_subscription_from_token, TokenSubscription};
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    subscription_token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::UnknownToken => StatusCode::UNAUTHORIZED,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Ask for a confirmation before unsubscribing: email clients and
+/// link scanners happily follow links, they do not submit forms.
+pub async fn unsubscribe_form(parameters: web::Query<UnsubscribeParameters>) -> HttpResponse {
+    let subscription_token = htmlescape::encode_attribute(&parameters.subscription_token);
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <form action="/subscriptions/unsubscribe" method="post">
+        <input hidden type="text" name="subscription_token" value="{subscription_token}">
+        <button type="submit">Unsubscribe from the newsletter</button>
+    </form>
+</body>
+</html>"#,
+        ))
+}
+
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(form, evidence, pool, templates)
+)]
+pub async fn unsubscribe(
+    form: web::Form<UnsubscribeParameters>,
+    evidence: ConsentEvidence,
+    pool: web::Data<PgPool>,
+    templates: web::Data<EmailTemplates>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let subscription = get_subscription_from_token(&pool, &form.subscription_token)
+        .await
+        .context("Failed to retrieve the subscription associated with the provided token.")?
+        .ok_or(UnsubscribeError::UnknownToken)?;
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let left = mark_subscriber_as_unsubscribed(&mut transaction, &subscription)
+        .await
+        .context("Failed to unsubscribe the subscriber.")?;
+    // A resubmitted form: the subscriber already left, and got a receipt
+    if !left {
+        return Ok(unsubscribed_page());
+    }
+    record_consent_event(
+        &mut transaction,
+        subscription.subscriber_id,
+        Some(subscription.list_id),
+        ConsentEventKind::Unsubscribed,
+        &evidence,
+        None,
+    )
+    .await
+    .context("Failed to record the withdrawal of consent.")?;
+    // The subscriber is gone either way: a lost receipt is not worth an error page
+    let receipt = match render_unsubscribe_receipt(&pool, &templates, &subscription).await {
+        Ok(receipt) => Some(receipt),
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to render an unsubscribe receipt."
+            );
+            None
+        }
+    };
+    if let Some((recipient, email)) = receipt {
+        enqueue_transactional_email(
+            &mut transaction,
+            &recipient,
+            &email.subject,
+            &email.html,
+            &email.text,
+            0,
+        )
+        .await
+        .context("Failed to queue an unsubscribe receipt.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber.")?;
+
+    Ok(unsubscribed_page())
+}
+
+fn unsubscribed_page() -> HttpResponse {
+    HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribed</title>
+</head>
+<body>
+    <p>You have been unsubscribed. You will not receive any further emails from us.</p>
+</body>
+</html>"#,
+    )
+}
+
+#[tracing::instrument(name = "Render an unsubscribe receipt", skip(pool, templates))]
+async fn render_unsubscribe_receipt(
+    pool: &PgPool,
+    templates: &EmailTemplates,
+    subscription: &TokenSubscription,
+) -> Result<(SubscriberEmail, RenderedEmail), anyhow::Error> {
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT s.email, s.name, l.name AS list
+        FROM subscriptions s, lists l
+        WHERE s.id = $1 AND l.list_id = $2
+        "#,
+        subscription.subscriber_id,
+        subscription.list_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the unsubscribed subscriber.")?;
+    let recipient = SubscriberEmail::parse(subscriber.email).map_err(anyhow::Error::msg)?;
+    let email = templates
+        .render(
+            pool,
+            EmailTemplate::UnsubscribeReceipt,
+            &serde_json::json!({ "name": subscriber.name, "list": subscriber.list }),
+        )
+        .await?;
+    Ok((recipient, email))
+}
+
+/// The address itself is marked as unsubscribed once it left every list.
+/// Returns whether the subscriber was still on the list.
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(transaction))]
+pub async fn mark_subscriber_as_unsubscribed(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription: &TokenSubscription,
+) -> Result<bool, sqlx::Error> {
+    let left = sqlx::query!(
+        r#"
+        UPDATE list_subscriptions SET status = 'unsubscribed'
+        WHERE subscriber_id = $1 AND list_id = $2 AND status != 'unsubscribed'
+        "#,
+        subscription.subscriber_id,
+        subscription.list_id,
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected()
+        == 1;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'unsubscribed'
+        WHERE id = $1 AND status IN ('pending_confirmation', 'confirmed')
+        AND NOT EXISTS (
+            SELECT 1 FROM list_subscriptions
+            WHERE subscriber_id = $1 AND status != 'unsubscribed'
+        )
+        "#,
+        subscription.subscriber_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(left)
+}
@@ -0,0 +1,101 @@
+use crate::tracking::{EngagementKind, Tracker, TrackingParameters};
+use crate::utils::see_other;
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// A transparent 1x1 GIF.
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+#[tracing::instrument(name = "Track an issue open", skip(parameters, pool, tracker))]
+pub async fn track_open(
+    issue_id: web::Path<Uuid>,
+    parameters: web::Query<TrackingParameters>,
+    pool: web::Data<PgPool>,
+    tracker: web::Data<Tracker>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let subscriber_id = tracker
+        .verify(EngagementKind::Open, issue_id, &parameters)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+    record_engagement(&pool, issue_id, subscriber_id, EngagementKind::Open, None).await;
+
+    Ok(HttpResponse::Ok()
+        .content_type("image/gif")
+        // Every open must reach us
+        .insert_header(CacheControl(vec![
+            CacheDirective::NoCache,
+            CacheDirective::NoStore,
+        ]))
+        .body(PIXEL))
+}
+
+#[tracing::instrument(name = "Track an issue click", skip(parameters, pool, tracker))]
+pub async fn track_click(
+    issue_id: web::Path<Uuid>,
+    parameters: web::Query<TrackingParameters>,
+    pool: web::Data<PgPool>,
+    tracker: web::Data<Tracker>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let subscriber_id = tracker
+        .verify(EngagementKind::Click, issue_id, &parameters)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+    let target = parameters
+        .url
+        .as_deref()
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("The link has no target."))?;
+    record_engagement(
+        &pool,
+        issue_id,
+        subscriber_id,
+        EngagementKind::Click,
+        Some(target),
+    )
+    .await;
+
+    Ok(see_other(target))
+}
+
+/// Statistics are best-effort: failing to record an event
+/// must not break the reader's experience.
+async fn record_engagement(
+    pool: &PgPool,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    kind: EngagementKind,
+    url: Option<&str>,
+) {
+    if let Err(e) = sqlx::query!(
+        r#"
+        INSERT INTO issue_engagement_events (
+            engagement_event_id,
+            newsletter_issue_id,
+            subscriber_id,
+            event_type,
+            url,
+            occurred_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        issue_id,
+        subscriber_id,
+        kind.as_str(),
+        url,
+    )
+    .execute(pool)
+    .await
+    {
+        tracing::warn!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to record an engagement event."
+        );
+    }
+}
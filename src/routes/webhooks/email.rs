@@ -0,0 +1,303 @@
+use crate::routes::error_chain_fmt;
+use crate::startup::EmailWebhookSecret;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use sha2::Sha256;
+use sqlx::{PgPool, Postgres, Transaction};
+
+const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+/// A provider-agnostic view of the notifications we act upon.
+#[derive(Debug, PartialEq)]
+pub enum EmailEvent {
+    HardBounce {
+        email: String,
+        details: String,
+    },
+    SpamComplaint {
+        email: String,
+        details: String,
+    },
+    /// Deliveries, soft bounces, opens... we do not act on those.
+    Ignored,
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("The webhook could not be authenticated.")]
+    Unauthorized,
+    #[error("Unknown email provider: {0}.")]
+    UnknownProvider(String),
+    #[error("Invalid webhook payload.")]
+    InvalidPayload(#[source] serde_json::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            WebhookError::Unauthorized => StatusCode::UNAUTHORIZED,
+            WebhookError::UnknownProvider(_) => StatusCode::NOT_FOUND,
+            WebhookError::InvalidPayload(_) => StatusCode::BAD_REQUEST,
+            WebhookError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Process an email provider webhook",
+    skip(request, body, pool, secret),
+    fields(provider = %provider)
+)]
+pub async fn email_webhook(
+    request: HttpRequest,
+    provider: web::Path<String>,
+    // The raw body: the signature is computed over the exact bytes we received
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    secret: web::Data<EmailWebhookSecret>,
+) -> Result<HttpResponse, WebhookError> {
+    if !is_authenticated(&request, &body, &secret) {
+        return Err(WebhookError::Unauthorized);
+    }
+    let event = match provider.as_str() {
+        "postmark" => parse_postmark_event(&body).map_err(WebhookError::InvalidPayload)?,
+        other => return Err(WebhookError::UnknownProvider(other.into())),
+    };
+
+    let (email, status, details) = match event {
+        EmailEvent::HardBounce { email, details } => (email, "bounced", details),
+        EmailEvent::SpamComplaint { email, details } => (email, "complained", details),
+        EmailEvent::Ignored => return Ok(HttpResponse::Ok().finish()),
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    suppress_address(&mut transaction, &email, status, &provider, &details)
+        .await
+        .context("Failed to suppress an email address.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to suppress an email address.")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Webhooks are accepted if they either carry an HMAC-SHA256 signature
+/// of their body, hex-encoded in `X-Webhook-Signature`, or the shared
+/// secret as the password of their basic auth credentials (this is what
+/// Postmark supports out of the box).
+fn is_authenticated(request: &HttpRequest, body: &[u8], secret: &EmailWebhookSecret) -> bool {
+    let secret = secret.0.expose_secret().as_bytes();
+
+    if let Some(signature) = request
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| hex::decode(h).ok())
+    {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+        mac.update(body);
+        // `verify_slice` compares in constant time
+        return mac.verify_slice(&signature).is_ok();
+    }
+
+    let password = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Basic "))
+        .and_then(|credentials| {
+            base64::engine::general_purpose::STANDARD
+                .decode(credentials)
+                .ok()
+        })
+        .and_then(|credentials| String::from_utf8(credentials).ok())
+        .and_then(|credentials| {
+            credentials
+                .split_once(':')
+                .map(|(_, password)| password.to_owned())
+        });
+    match password {
+        Some(password) => constant_time_eq(password.as_bytes(), secret),
+        None => false,
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkPayload {
+    record_type: String,
+    #[serde(rename = "Type")]
+    kind: Option<String>,
+    email: Option<String>,
+    description: Option<String>,
+}
+
+/// See https://postmarkapp.com/developer/webhooks/bounce-webhook
+/// and https://postmarkapp.com/developer/webhooks/spam-complaint-webhook
+pub fn parse_postmark_event(body: &[u8]) -> Result<EmailEvent, serde_json::Error> {
+    let payload: PostmarkPayload = serde_json::from_slice(body)?;
+    let email = match payload.email {
+        Some(email) => email,
+        None => return Ok(EmailEvent::Ignored),
+    };
+    let details = payload
+        .description
+        .or_else(|| payload.kind.clone())
+        .unwrap_or_else(|| payload.record_type.clone());
+    // Only bounces need a type, to tell hard bounces apart
+    let kind = payload.kind.unwrap_or_default();
+    let event = match (payload.record_type.as_str(), kind.as_str()) {
+        ("Bounce", "HardBounce" | "BadEmailAddress" | "ManuallyDeactivated") => {
+            EmailEvent::HardBounce { email, details }
+        }
+        ("SpamComplaint", _) => EmailEvent::SpamComplaint { email, details },
+        _ => EmailEvent::Ignored,
+    };
+    Ok(event)
+}
+
+/// Add the address to the suppression list and flag the subscriber,
+/// so that no further issue is sent their way.
+///
+/// Addresses are compared case-insensitively: the provider may not spell
+/// them the way the subscriber did.
+#[tracing::instrument(name = "Suppress an email address", skip(transaction, email, details))]
+async fn suppress_address(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+    reason: &str,
+    provider: &str,
+    details: &str,
+) -> Result<(), sqlx::Error> {
+    let email = email.trim().to_lowercase();
+    sqlx::query!(
+        r#"
+        INSERT INTO email_suppressions (email, reason, provider, details, created_at)
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (email) DO UPDATE
+        SET reason = EXCLUDED.reason, details = EXCLUDED.details
+        "#,
+        email,
+        reason,
+        provider,
+        details,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = $2
+        WHERE lower(email) = $1 AND status != 'erased'
+        "#,
+        email,
+        reason,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    // Emails that are still waiting to be sent should not go out either
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE subscriber_id IN (SELECT id FROM subscriptions WHERE lower(email) = $1)
+        OR lower(recipient) = $1
+        "#,
+        email,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_postmark_event, EmailEvent};
+    use claim::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn a_postmark_hard_bounce_is_parsed() {
+        let body = serde_json::json!({
+            "RecordType": "Bounce",
+            "Type": "HardBounce",
+            "TypeCode": 1,
+            "Email": "john@example.com",
+            "Description": "The server was unable to deliver your message",
+        });
+        assert_ok_eq!(
+            parse_postmark_event(body.to_string().as_bytes()),
+            EmailEvent::HardBounce {
+                email: "john@example.com".into(),
+                details: "The server was unable to deliver your message".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn a_postmark_soft_bounce_is_ignored() {
+        let body = serde_json::json!({
+            "RecordType": "Bounce",
+            "Type": "SoftBounce",
+            "Email": "john@example.com",
+        });
+        assert_ok_eq!(
+            parse_postmark_event(body.to_string().as_bytes()),
+            EmailEvent::Ignored
+        );
+    }
+
+    #[test]
+    fn a_postmark_spam_complaint_is_parsed() {
+        let body = serde_json::json!({
+            "RecordType": "SpamComplaint",
+            "Type": "SpamComplaint",
+            "Email": "john@example.com",
+        });
+        assert_ok_eq!(
+            parse_postmark_event(body.to_string().as_bytes()),
+            EmailEvent::SpamComplaint {
+                email: "john@example.com".into(),
+                details: "SpamComplaint".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn a_postmark_spam_complaint_without_a_type_is_parsed() {
+        let body = serde_json::json!({
+            "RecordType": "SpamComplaint",
+            "Email": "john@example.com",
+        });
+        assert_ok_eq!(
+            parse_postmark_event(body.to_string().as_bytes()),
+            EmailEvent::SpamComplaint {
+                email: "john@example.com".into(),
+                details: "SpamComplaint".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn a_malformed_payload_is_rejected() {
+        assert_err!(parse_postmark_event(b"not json"));
+    }
+}
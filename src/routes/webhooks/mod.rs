@@ -0,0 +1,3 @@
+mod email;
+
+pub use email::email_webhook;
@@ -0,0 +1,384 @@
+use std::collections::{HashMap, HashSet};
+
+/// The elements email clients render reliably.
+const ALLOWED_TAGS: &[&str] = &[
+    "a",
+    "abbr",
+    "b",
+    "blockquote",
+    "br",
+    "caption",
+    "center",
+    "code",
+    "col",
+    "colgroup",
+    "dd",
+    "del",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "font",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "s",
+    "small",
+    "span",
+    "strike",
+    "strong",
+    "sub",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "u",
+    "ul",
+];
+const GENERIC_ATTRIBUTES: &[&str] = &["align", "dir", "lang", "style", "title"];
+const TAG_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("a", &["href", "name", "target"]),
+    ("col", &["span", "width"]),
+    ("font", &["color", "face", "size"]),
+    ("img", &["alt", "border", "height", "src", "width"]),
+    ("ol", &["start", "type"]),
+    (
+        "table",
+        &["bgcolor", "border", "cellpadding", "cellspacing", "width"],
+    ),
+    (
+        "td",
+        &["bgcolor", "colspan", "height", "rowspan", "valign", "width"],
+    ),
+    (
+        "th",
+        &["bgcolor", "colspan", "height", "rowspan", "valign", "width"],
+    ),
+];
+/// The document structure is dropped and `<style>` blocks are inlined:
+/// there is nothing worth warning about.
+const SILENTLY_DROPPED_TAGS: &[&str] = &["body", "head", "html", "meta", "style", "title"];
+/// Active content: an issue containing these is rejected, not cleaned.
+const FORBIDDEN_TAGS: &[&str] = &[
+    "applet", "embed", "frame", "frameset", "iframe", "object", "script",
+];
+const URL_ATTRIBUTES: &[&str] = &["action", "background", "formaction", "href", "src"];
+
+/// Issue HTML, ready to be sent.
+#[derive(Debug)]
+pub struct SanitizedHtml {
+    pub html: String,
+    /// What was removed to make the HTML safe
+    pub warnings: Vec<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SanitizationError {
+    #[error("The HTML content is not allowed: {}", .0.join(" "))]
+    Disallowed(Vec<String>),
+    #[error("Failed to inline the CSS of the HTML content.")]
+    CssInliningFailed(#[source] css_inline::InlineError),
+}
+
+/// Inline the CSS of admin-provided HTML and run it through an allow-list.
+///
+/// Active content (scripts, forms submitting to other sites, event handlers...)
+/// is rejected. Anything else outside of the allow-list is removed, with a
+/// warning for each kind of construct that goes away.
+pub fn sanitize(html: &str) -> Result<SanitizedHtml, SanitizationError> {
+    let (errors, warnings) = review(html);
+    if !errors.is_empty() {
+        return Err(SanitizationError::Disallowed(errors));
+    }
+
+    // Many email clients ignore `<style>` blocks
+    let inlined = css_inline::CSSInliner::options()
+        .load_remote_stylesheets(false)
+        .build()
+        .inline(html)
+        .map_err(SanitizationError::CssInliningFailed)?;
+    let html = ammonia::Builder::default()
+        .tags(ALLOWED_TAGS.iter().copied().collect())
+        .generic_attributes(GENERIC_ATTRIBUTES.iter().copied().collect())
+        .tag_attributes(
+            TAG_ATTRIBUTES
+                .iter()
+                .map(|(tag, attributes)| (*tag, attributes.iter().copied().collect()))
+                .collect::<HashMap<_, HashSet<_>>>(),
+        )
+        .clean(&inlined)
+        .to_string();
+    Ok(SanitizedHtml { html, warnings })
+}
+
+/// Returns the errors and the warnings for the given HTML.
+fn review(html: &str) -> (Vec<String>, Vec<String>) {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    for element in elements(html) {
+        let tag = element.name.as_str();
+        if FORBIDDEN_TAGS.contains(&tag) {
+            report(
+                &mut errors,
+                format!("`<{}>` elements are not allowed.", tag),
+            );
+            continue;
+        }
+        if tag == "form" {
+            let action = element.attribute("action").unwrap_or_default();
+            if is_external(action) {
+                report(
+                    &mut errors,
+                    "Forms submitting to external sites are not allowed.".into(),
+                );
+                continue;
+            }
+        }
+        if tag == "link" {
+            report(
+                &mut warnings,
+                "External stylesheets are not loaded, add the styles to a `<style>` element."
+                    .into(),
+            );
+            continue;
+        }
+
+        let allowed_tag = ALLOWED_TAGS.contains(&tag);
+        if !allowed_tag && !SILENTLY_DROPPED_TAGS.contains(&tag) {
+            report(
+                &mut warnings,
+                format!("`<{}>` elements will be removed.", tag),
+            );
+        }
+        for (name, value) in &element.attributes {
+            if name.starts_with("on") {
+                report(
+                    &mut errors,
+                    format!(
+                        "Event handler attributes such as `{}` are not allowed.",
+                        name
+                    ),
+                );
+            } else if URL_ATTRIBUTES.contains(&name.as_str()) && is_script_url(value) {
+                report(&mut errors, "Script URLs are not allowed.".into());
+            } else if allowed_tag && !is_allowed_attribute(tag, name) {
+                report(
+                    &mut warnings,
+                    format!("The `{}` attribute of `<{}>` will be removed.", name, tag),
+                );
+            }
+        }
+    }
+    (errors, warnings)
+}
+
+/// Each kind of problem is reported once.
+fn report(messages: &mut Vec<String>, message: String) {
+    if !messages.contains(&message) {
+        messages.push(message);
+    }
+}
+
+fn is_allowed_attribute(tag: &str, attribute: &str) -> bool {
+    GENERIC_ATTRIBUTES.contains(&attribute)
+        || TAG_ATTRIBUTES
+            .iter()
+            .any(|(t, attributes)| *t == tag && attributes.contains(&attribute))
+}
+
+fn is_external(url: &str) -> bool {
+    let url = url.trim().to_ascii_lowercase();
+    url.starts_with("http://") || url.starts_with("https://") || url.starts_with("//")
+}
+
+fn is_script_url(url: &str) -> bool {
+    // Browsers ignore whitespace and control characters in schemes
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    url.starts_with("javascript:")
+        || url.starts_with("vbscript:")
+        || url.starts_with("data:text/html")
+}
+
+struct Element {
+    /// Lowercase
+    name: String,
+    /// Lowercase names, decoded values
+    attributes: Vec<(String, String)>,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A forgiving scan of the opening tags of an HTML document.
+/// It is only used to report problems: the output of `sanitize`
+/// is always produced by a proper HTML parser.
+fn elements(html: &str) -> Vec<Element> {
+    let bytes = html.as_bytes();
+    let mut elements = Vec::new();
+    let mut i = 0;
+    while let Some(offset) = html[i..].find('<') {
+        i += offset + 1;
+        let name_start = i;
+        while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'-') {
+            i += 1;
+        }
+        // Closing tags, comments, doctypes...
+        if i == name_start || !bytes[name_start].is_ascii_alphabetic() {
+            continue;
+        }
+        let mut element = Element {
+            name: html[name_start..i].to_ascii_lowercase(),
+            attributes: Vec::new(),
+        };
+
+        loop {
+            while i < bytes.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+                i += 1;
+            }
+            if i >= bytes.len() || bytes[i] == b'>' {
+                break;
+            }
+            let attribute_start = i;
+            while i < bytes.len()
+                && !bytes[i].is_ascii_whitespace()
+                && !matches!(bytes[i], b'=' | b'>' | b'/')
+            {
+                i += 1;
+            }
+            let name = html[attribute_start..i].to_ascii_lowercase();
+            let mut value = String::new();
+            if i < bytes.len() && bytes[i] == b'=' {
+                i += 1;
+                let (value_start, value_end) = match bytes.get(i) {
+                    Some(&quote @ (b'"' | b'\'')) => {
+                        let start = i + 1;
+                        let end = html[start..]
+                            .find(quote as char)
+                            .map(|length| start + length)
+                            .unwrap_or(bytes.len());
+                        i = (end + 1).min(bytes.len());
+                        (start, end)
+                    }
+                    _ => {
+                        let start = i;
+                        while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>'
+                        {
+                            i += 1;
+                        }
+                        (start, i)
+                    }
+                };
+                let raw_value = &html[value_start..value_end];
+                value = htmlescape::decode_html(raw_value).unwrap_or_else(|_| raw_value.into());
+            }
+            element.attributes.push((name, value));
+        }
+        elements.push(element);
+    }
+    elements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sanitize;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn plain_formatting_goes_through_untouched() {
+        let sanitized = assert_ok!(sanitize(
+            r#"<p>Hello <b>there</b>, read <a href="https://example.com">this</a></p>"#
+        ));
+
+        assert!(sanitized.warnings.is_empty());
+        assert!(sanitized
+            .html
+            .contains(r#"<p>Hello <b>there</b>, read <a href="https://example.com""#));
+    }
+
+    #[test]
+    fn scripts_are_rejected() {
+        assert_err!(sanitize("<p>Hi</p><script>alert('boo')</script>"));
+    }
+
+    #[test]
+    fn event_handlers_are_rejected() {
+        assert_err!(sanitize(r#"<img src="cat.png" onerror="alert('boo')">"#));
+    }
+
+    #[test]
+    fn script_urls_are_rejected() {
+        assert_err!(sanitize(r#"<a href=" JavaScript:alert('boo')">click</a>"#));
+    }
+
+    #[test]
+    fn external_forms_are_rejected() {
+        assert_err!(sanitize(
+            r#"<form action="https://evil.example.com/login"><input name="password"></form>"#
+        ));
+    }
+
+    #[test]
+    fn disallowed_constructs_are_removed_with_a_warning() {
+        let sanitized = assert_ok!(sanitize(
+            r#"<p class="intro">Hi</p><video src="cat.mp4"></video>"#
+        ));
+
+        assert_eq!(
+            sanitized.warnings,
+            vec![
+                "The `class` attribute of `<p>` will be removed.".to_string(),
+                "`<video>` elements will be removed.".to_string(),
+            ]
+        );
+        assert!(!sanitized.html.contains("class="));
+        assert!(!sanitized.html.contains("<video"));
+    }
+
+    #[test]
+    fn css_is_inlined() {
+        let sanitized = assert_ok!(sanitize(
+            "<html><head><style>p { color: red; }</style></head><body><p>Hi</p></body></html>"
+        ));
+
+        assert!(sanitized.warnings.is_empty());
+        assert!(sanitized.html.contains(r#"<p style="color: red"#));
+        assert!(!sanitized.html.contains("<style"));
+    }
+
+    #[test]
+    fn template_placeholders_survive_sanitization() {
+        let sanitized = assert_ok!(sanitize(
+            r#"<p>Hi {{name}}, <a href="{{unsubscribe_url}}">unsubscribe</a></p>"#
+        ));
+
+        assert!(sanitized.html.contains("Hi {{name}}"));
+        assert!(sanitized.html.contains(r#"href="{{unsubscribe_url}}""#));
+    }
+}
@@ -0,0 +1,361 @@
+use crate::domain::{DigestFrequency, SubscriberTag};
+use chrono::NaiveDate;
+use sqlx::{Postgres, QueryBuilder};
+use std::fmt;
+
+/// The address statuses a segment can filter on.
+const STATUSES: [&str; 5] = [
+    "pending_confirmation",
+    "confirmed",
+    "unsubscribed",
+    "bounced",
+    "complained",
+];
+
+/// Parentheses and `not` nest: bound the recursion.
+const MAX_DEPTH: usize = 16;
+
+/// A set of subscribers, described by a filter expression over their
+/// tags, status, digest frequency and subscription date, e.g.
+/// `tag:beta and not tag:paid and subscribed_after:2024-01-01`.
+///
+/// `not` binds tighter than `and`, which binds tighter than `or`;
+/// parentheses group. Both date filters exclude the given day.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Tag(SubscriberTag),
+    Status(String),
+    Digest(DigestFrequency),
+    SubscribedBefore(NaiveDate),
+    SubscribedAfter(NaiveDate),
+    Not(Box<Segment>),
+    And(Box<Segment>, Box<Segment>),
+    Or(Box<Segment>, Box<Segment>),
+}
+
+impl Segment {
+    pub fn parse(s: &str) -> Result<Segment, String> {
+        let mut parser = Parser {
+            tokens: tokenize(s),
+            position: 0,
+            depth: 0,
+        };
+        if parser.tokens.is_empty() {
+            return Err("The segment is empty.".into());
+        }
+        let segment = parser.or()?;
+        match parser.next() {
+            Some(token) => Err(format!("Unexpected {} in the segment.", token)),
+            None => Ok(segment),
+        }
+    }
+
+    /// Push the segment as a SQL condition on `s`, an alias of the
+    /// `subscriptions` table. Values are bound, never interpolated.
+    pub fn push_sql(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        match self {
+            Segment::Tag(tag) => {
+                builder
+                    .push("EXISTS (SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = ")
+                    .push_bind(tag.as_ref().to_owned())
+                    .push(")");
+            }
+            Segment::Status(status) => {
+                builder.push("s.status = ").push_bind(status.clone());
+            }
+            Segment::Digest(frequency) => {
+                builder
+                    .push("s.digest_frequency = ")
+                    .push_bind(frequency.as_str());
+            }
+            Segment::SubscribedBefore(date) => {
+                builder.push("s.subscribed_at < ").push_bind(*date);
+            }
+            Segment::SubscribedAfter(date) => {
+                builder
+                    .push("s.subscribed_at >= ")
+                    .push_bind(*date)
+                    .push("::date + 1");
+            }
+            Segment::Not(segment) => {
+                builder.push("NOT (");
+                segment.push_sql(builder);
+                builder.push(")");
+            }
+            Segment::And(left, right) | Segment::Or(left, right) => {
+                let operator = if matches!(self, Segment::And(..)) {
+                    " AND "
+                } else {
+                    " OR "
+                };
+                builder.push("(");
+                left.push_sql(builder);
+                builder.push(operator);
+                right.push_sql(builder);
+                builder.push(")");
+            }
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Segment::Or(..) => 1,
+            Segment::And(..) => 2,
+            _ => 3,
+        }
+    }
+
+    fn fmt_within(&self, f: &mut fmt::Formatter<'_>, parent_precedence: u8) -> fmt::Result {
+        let parenthesize = self.precedence() < parent_precedence;
+        if parenthesize {
+            write!(f, "(")?;
+        }
+        match self {
+            Segment::Tag(tag) => write!(f, "tag:{}", tag.as_ref())?,
+            Segment::Status(status) => write!(f, "status:{}", status)?,
+            Segment::Digest(frequency) => write!(f, "digest:{}", frequency.as_str())?,
+            Segment::SubscribedBefore(date) => {
+                write!(f, "subscribed_before:{}", date.format("%Y-%m-%d"))?
+            }
+            Segment::SubscribedAfter(date) => {
+                write!(f, "subscribed_after:{}", date.format("%Y-%m-%d"))?
+            }
+            Segment::Not(segment) => {
+                write!(f, "not ")?;
+                segment.fmt_within(f, 3)?;
+            }
+            Segment::And(left, right) | Segment::Or(left, right) => {
+                let operator = if matches!(self, Segment::And(..)) {
+                    "and"
+                } else {
+                    "or"
+                };
+                left.fmt_within(f, self.precedence())?;
+                write!(f, " {} ", operator)?;
+                right.fmt_within(f, self.precedence())?;
+            }
+        }
+        if parenthesize {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+/// The normalised expression, stored alongside the issue.
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_within(f, 0)
+    }
+}
+
+/// Words are separated by whitespace, parentheses stand on their own.
+fn tokenize(s: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    for c in s.chars() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+            if !c.is_whitespace() {
+                tokens.push(c.to_string());
+            }
+        } else {
+            word.push(c);
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn next_is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(token) if token.eq_ignore_ascii_case(keyword))
+    }
+
+    fn or(&mut self) -> Result<Segment, String> {
+        let mut segment = self.and()?;
+        while self.next_is_keyword("or") {
+            self.position += 1;
+            segment = Segment::Or(Box::new(segment), Box::new(self.and()?));
+        }
+        Ok(segment)
+    }
+
+    fn and(&mut self) -> Result<Segment, String> {
+        let mut segment = self.not()?;
+        while self.next_is_keyword("and") {
+            self.position += 1;
+            segment = Segment::And(Box::new(segment), Box::new(self.not()?));
+        }
+        Ok(segment)
+    }
+
+    fn not(&mut self) -> Result<Segment, String> {
+        if self.next_is_keyword("not") {
+            self.position += 1;
+            self.nested(|parser| Ok(Segment::Not(Box::new(parser.not()?))))
+        } else {
+            self.atom()
+        }
+    }
+
+    fn nested(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<Segment, String>,
+    ) -> Result<Segment, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("The segment is nested too deeply.".into());
+        }
+        let segment = parse(self)?;
+        self.depth -= 1;
+        Ok(segment)
+    }
+
+    fn atom(&mut self) -> Result<Segment, String> {
+        let token = self
+            .next()
+            .ok_or_else(|| "The segment ends unexpectedly.".to_string())?;
+        if token == "(" {
+            return self.nested(|parser| {
+                let segment = parser.or()?;
+                match parser.next().as_deref() {
+                    Some(")") => Ok(segment),
+                    _ => Err("A parenthesis is not closed in the segment.".into()),
+                }
+            });
+        }
+        let (key, value) = token
+            .split_once(':')
+            .ok_or_else(|| format!("Unexpected {} in the segment.", token))?;
+        let parse_date = |value: &str| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map_err(|_| format!("{} is not a date, use YYYY-MM-DD.", value))
+        };
+        match key.to_lowercase().as_str() {
+            "tag" => Ok(Segment::Tag(SubscriberTag::parse(value.into())?)),
+            "status" => {
+                let status = value.to_lowercase();
+                if STATUSES.contains(&status.as_str()) {
+                    Ok(Segment::Status(status))
+                } else {
+                    Err(format!(
+                        "{} is not a status, use one of {}.",
+                        value,
+                        STATUSES.join(", ")
+                    ))
+                }
+            }
+            "digest" => Ok(Segment::Digest(DigestFrequency::parse(
+                &value.to_lowercase(),
+            )?)),
+            "subscribed_before" => Ok(Segment::SubscribedBefore(parse_date(value)?)),
+            "subscribed_after" => Ok(Segment::SubscribedAfter(parse_date(value)?)),
+            _ => Err(format!(
+                "Unknown filter {}, use tag:, status:, digest:, subscribed_before: or subscribed_after:.",
+                key
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Segment;
+    use claim::{assert_err, assert_ok};
+    use sqlx::QueryBuilder;
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let segment = assert_ok!(Segment::parse("tag:a or tag:b and tag:c"));
+        assert!(matches!(segment, Segment::Or(..)));
+        assert_eq!(segment.to_string(), "tag:a or tag:b and tag:c");
+    }
+
+    #[test]
+    fn parentheses_group() {
+        let segment = assert_ok!(Segment::parse("(tag:a or tag:b)and tag:c"));
+        assert!(matches!(segment, Segment::And(..)));
+        assert_eq!(segment.to_string(), "(tag:a or tag:b) and tag:c");
+    }
+
+    #[test]
+    fn keywords_and_values_are_case_insensitive() {
+        let segment = assert_ok!(Segment::parse(
+            "NOT Tag:Beta AND status:CONFIRMED and digest:Weekly and subscribed_after:2024-01-31"
+        ));
+        assert_eq!(
+            segment.to_string(),
+            "not tag:beta and status:confirmed and digest:weekly and subscribed_after:2024-01-31"
+        );
+    }
+
+    #[test]
+    fn the_sql_binds_every_value() {
+        let segment =
+            Segment::parse("tag:beta and not (status:bounced or subscribed_before:2024-01-01)")
+                .unwrap();
+        let mut builder = QueryBuilder::new("");
+        segment.push_sql(&mut builder);
+        assert_eq!(
+            builder.sql(),
+            "(EXISTS (SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = $1) \
+            AND NOT ((s.status = $2 OR s.subscribed_at < $3)))"
+        );
+    }
+
+    #[test]
+    fn empty_segments_are_rejected() {
+        assert_err!(Segment::parse(""));
+        assert_err!(Segment::parse("  "));
+    }
+
+    #[test]
+    fn dangling_operators_and_parentheses_are_rejected() {
+        assert_err!(Segment::parse("tag:a and"));
+        assert_err!(Segment::parse("or tag:a"));
+        assert_err!(Segment::parse("(tag:a"));
+        assert_err!(Segment::parse("tag:a)"));
+        assert_err!(Segment::parse("tag:a tag:b"));
+    }
+
+    #[test]
+    fn unknown_filters_and_invalid_values_are_rejected() {
+        assert_err!(Segment::parse("country:fr"));
+        assert_err!(Segment::parse("status:sleeping"));
+        assert_err!(Segment::parse("digest:hourly"));
+        assert_err!(Segment::parse("subscribed_after:yesterday"));
+        assert_err!(Segment::parse("tag:paid;drop"));
+        assert_err!(Segment::parse("beta"));
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        assert_err!(Segment::parse(&format!("{}tag:a", "not ".repeat(100))));
+        assert_err!(Segment::parse(&format!(
+            "{}tag:a{}",
+            "(".repeat(100),
+            ")".repeat(100)
+        )));
+    }
+}
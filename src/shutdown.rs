@@ -0,0 +1,84 @@
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Tells the long-running tasks of the process to wrap up: the API stops
+/// accepting requests, workers finish their current task and stop dequeuing.
+#[derive(Clone)]
+pub struct Shutdown {
+    // Kept alive by every clone, so that `triggered` never returns early
+    sender: Arc<watch::Sender<bool>>,
+    receiver: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (sender, receiver) = watch::channel(false);
+        Self {
+            sender: Arc::new(sender),
+            receiver,
+        }
+    }
+
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    /// Resolves once shutdown has been triggered.
+    pub async fn triggered(&self) {
+        let mut receiver = self.receiver.clone();
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Resolves on SIGTERM, sent by orchestrators during deploys, or SIGINT.
+pub async fn wait_for_signal() -> Result<(), std::io::Error> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            _ = terminate.recv() => tracing::info!("Received SIGTERM."),
+            outcome = tokio::signal::ctrl_c() => {
+                outcome?;
+                tracing::info!("Received SIGINT.");
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await?;
+        tracing::info!("Received Ctrl+C.");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Shutdown;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn every_clone_sees_the_shutdown() {
+        let shutdown = Shutdown::new();
+        let clone = shutdown.clone();
+        assert!(!clone.is_triggered());
+
+        shutdown.trigger();
+
+        assert!(clone.is_triggered());
+        tokio::time::timeout(Duration::from_millis(100), clone.triggered())
+            .await
+            .expect("Shutdown was not seen.");
+    }
+}
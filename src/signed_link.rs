@@ -0,0 +1,166 @@
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// What a signed link allows its bearer to do.
+/// It is part of the signed payload: a link issued for one
+/// purpose cannot be replayed against another endpoint.
+#[derive(Debug, Clone, Copy)]
+pub enum LinkPurpose {
+    SubscriberData,
+    Preferences,
+}
+
+impl LinkPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkPurpose::SubscriberData => "subscriber_data",
+            LinkPurpose::Preferences => "preferences",
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum LinkError {
+    #[error("The link has expired.")]
+    Expired,
+    #[error("The link signature is invalid.")]
+    InvalidSignature,
+}
+
+/// The query parameters of a link authenticating a subscriber
+/// without them having to log in.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct SignedLink {
+    pub subscriber_id: Uuid,
+    /// Unix timestamp, in seconds
+    pub expires_at: i64,
+    pub signature: String,
+}
+
+impl SignedLink {
+    pub fn new(
+        purpose: LinkPurpose,
+        subscriber_id: Uuid,
+        valid_for: Duration,
+        secret: &Secret<String>,
+    ) -> Self {
+        let expires_at = (Utc::now() + valid_for).timestamp();
+        let signature = hex::encode(
+            mac(purpose, subscriber_id, expires_at, secret)
+                .finalize()
+                .into_bytes(),
+        );
+        Self {
+            subscriber_id,
+            expires_at,
+            signature,
+        }
+    }
+
+    /// Returns the id of the subscriber the link was issued to.
+    pub fn verify(&self, purpose: LinkPurpose, secret: &Secret<String>) -> Result<Uuid, LinkError> {
+        let signature = hex::decode(&self.signature).map_err(|_| LinkError::InvalidSignature)?;
+        // `verify_slice` compares in constant time
+        mac(purpose, self.subscriber_id, self.expires_at, secret)
+            .verify_slice(&signature)
+            .map_err(|_| LinkError::InvalidSignature)?;
+        if self.expires_at < Utc::now().timestamp() {
+            return Err(LinkError::Expired);
+        }
+        Ok(self.subscriber_id)
+    }
+
+    pub fn query_string(&self) -> String {
+        format!(
+            "subscriber_id={}&expires_at={}&signature={}",
+            self.subscriber_id, self.expires_at, self.signature
+        )
+    }
+}
+
+fn mac(
+    purpose: LinkPurpose,
+    subscriber_id: Uuid,
+    expires_at: i64,
+    secret: &Secret<String>,
+) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes()).unwrap();
+    mac.update(format!("{}:{}:{}", purpose.as_str(), subscriber_id, expires_at).as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LinkPurpose, SignedLink};
+    use chrono::Duration;
+    use claim::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-very-secret-key".to_string())
+    }
+
+    #[test]
+    fn a_freshly_signed_link_is_valid() {
+        let subscriber_id = Uuid::new_v4();
+        let link = SignedLink::new(
+            LinkPurpose::SubscriberData,
+            subscriber_id,
+            Duration::hours(1),
+            &secret(),
+        );
+        assert_ok_eq!(
+            link.verify(LinkPurpose::SubscriberData, &secret()),
+            subscriber_id
+        );
+    }
+
+    #[test]
+    fn an_expired_link_is_rejected() {
+        let link = SignedLink::new(
+            LinkPurpose::SubscriberData,
+            Uuid::new_v4(),
+            Duration::hours(-1),
+            &secret(),
+        );
+        assert_err!(link.verify(LinkPurpose::SubscriberData, &secret()));
+    }
+
+    #[test]
+    fn a_link_for_another_subscriber_is_rejected() {
+        let mut link = SignedLink::new(
+            LinkPurpose::SubscriberData,
+            Uuid::new_v4(),
+            Duration::hours(1),
+            &secret(),
+        );
+        link.subscriber_id = Uuid::new_v4();
+        assert_err!(link.verify(LinkPurpose::SubscriberData, &secret()));
+    }
+
+    #[test]
+    fn a_link_issued_for_another_purpose_is_rejected() {
+        let link = SignedLink::new(
+            LinkPurpose::Preferences,
+            Uuid::new_v4(),
+            Duration::hours(1),
+            &secret(),
+        );
+        assert_err!(link.verify(LinkPurpose::SubscriberData, &secret()));
+    }
+
+    #[test]
+    fn a_link_signed_with_another_secret_is_rejected() {
+        let link = SignedLink::new(
+            LinkPurpose::SubscriberData,
+            Uuid::new_v4(),
+            Duration::hours(1),
+            &Secret::new("another-secret".to_string()),
+        );
+        assert_err!(link.verify(LinkPurpose::SubscriberData, &secret()));
+    }
+}
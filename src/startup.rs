@@ -1,18 +1,45 @@
 use crate::authentication::reject_anonymous_users;
+use crate::configuration::ApplicationSettings;
 use crate::configuration::DatabaseSettings;
 use crate::configuration::Settings;
 use crate::email_client::EmailClient;
-use crate::routes::publish_newsletter_form;
+use crate::email_templates::EmailTemplates;
+use crate::idempotency::idempotent_requests;
+use crate::metrics::{metrics, record_http_metrics};
+use crate::routes::{
+    add_subscriber_tag, export_subscriber_consent, remove_subscriber_tag, subscriber_consent,
+    subscribers_list,
+};
 use crate::routes::{admin_dashboard, home, log_out, login, login_form};
+use crate::routes::{
+    cancel_newsletter_delivery, newsletter_delivery_progress, newsletter_issue_page,
+    pause_newsletter_delivery, resume_newsletter_delivery,
+};
 use crate::routes::{change_password, change_password_form};
 use crate::routes::{confirm, health_check, publish_newsletter, subscribe};
+use crate::routes::{create_list, lists_page};
+use crate::routes::{create_profile_field, profile_fields_page};
+use crate::routes::{email_template_form, email_templates_list};
+use crate::routes::{email_webhook, suppression_list};
+use crate::routes::{erase_subscriber_data, export_subscriber_data, subscriber_data_page};
+use crate::routes::{issue_archive, issue_feed, issue_web_view};
+use crate::routes::{newsletter_drafts, publish_newsletter_draft};
+use crate::routes::{preferences_page, save_preferences};
+use crate::routes::{preview_email_template, reset_email_template, save_email_template};
+use crate::routes::{preview_newsletter, publish_newsletter_form};
+use crate::routes::{readiness_check, ReadinessProbe};
+use crate::routes::{request_subscriber_data, subscriber_data_request_form};
+use crate::routes::{track_click, track_open};
+use crate::routes::{unsubscribe, unsubscribe_form};
+use crate::shutdown::Shutdown;
+use crate::tracking::Tracker;
 
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
 use actix_web::cookie::Key;
 use actix_web::dev::Server;
 use actix_web::web::Data;
-use actix_web::{web, App, HttpServer};
+use actix_web::{guard, web, App, HttpServer};
 use actix_web_flash_messages::storage::CookieMessageStore;
 use actix_web_flash_messages::FlashMessagesFramework;
 use actix_web_lab::middleware::from_fn;
@@ -31,10 +58,14 @@ pub struct Application {
 #[derive(Clone)]
 pub struct HmacSecret(pub Secret<String>);
 
+#[derive(Clone)]
+pub struct EmailWebhookSecret(pub Secret<String>);
+
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
-        let email_client = configuration.email_client.client();
+        metrics().watch_pool("api", &connection_pool);
+        let email_client = configuration.email_client.clone().client();
         let address = format!(
             "{}:{}",
             configuration.application.host, configuration.application.port
@@ -45,8 +76,9 @@ impl Application {
             listener,
             connection_pool,
             email_client,
-            configuration.application.base_url,
-            configuration.application.hmac_secret,
+            configuration.email_templates.templates(),
+            configuration.application,
+            configuration.email_client.webhook_secret,
             configuration.redis_uri,
         )
         .await?;
@@ -57,7 +89,14 @@ impl Application {
         self.port
     }
 
-    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
+    /// Stop accepting requests once shutdown is triggered, and give the
+    /// in-flight ones the grace period to complete.
+    pub async fn run_until_stopped(self, shutdown: Shutdown) -> Result<(), std::io::Error> {
+        let handle = self.server.handle();
+        tokio::spawn(async move {
+            shutdown.triggered().await;
+            handle.stop(true).await;
+        });
         self.server.await
     }
 }
@@ -78,12 +117,22 @@ async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
-    base_url: String,
-    hmac_secret: Secret<String>,
+    email_templates: EmailTemplates,
+    application: ApplicationSettings,
+    webhook_secret: Secret<String>,
     redis_uri: Secret<String>,
 ) -> Result<Server, anyhow::Error> {
+    let ApplicationSettings {
+        base_url,
+        hmac_secret,
+        shutdown_grace_period_seconds,
+        readiness,
+        ..
+    } = application;
     let db_pool = Data::new(db_pool);
     let email_client = Data::new(email_client);
+    let email_templates = Data::new(email_templates);
+    let tracker = Data::new(Tracker::new(base_url.clone(), hmac_secret.clone()));
     let base_url = Data::new(ApplicationBaseUrl(base_url));
 
     let message_store =
@@ -91,38 +140,178 @@ async fn run(
     let message_framework = FlashMessagesFramework::builder(message_store).build();
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes()); // management
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    let readiness_probe = Data::new(ReadinessProbe::new(&readiness, &redis_uri)?);
 
     let server = HttpServer::new(move || {
         App::new()
-            .wrap(message_framework.clone())
             .wrap(SessionMiddleware::new(
                 redis_store.clone(),
                 secret_key.clone(),
             ))
             .wrap(TracingLogger::default())
-            .route("/", web::get().to(home))
-            .route("/login", web::get().to(login_form))
-            .route("/login", web::post().to(login))
-            .route("/health_check", web::get().to(health_check))
-            .route("/newsletters", web::post().to(publish_newsletter))
-            .route("/subscriptions", web::post().to(subscribe))
-            .route("/subscriptions/confirm", web::get().to(confirm))
+            .wrap(from_fn(record_http_metrics))
+            // Only the routes taking an `IdempotentTransaction` go through
+            // the idempotency middleware, which buffers the request body.
+            // It sits outside of the flash messages framework so that
+            // replayed responses carry the flash cookie as well.
+            .service(
+                web::resource("/newsletters")
+                    .wrap(message_framework.clone())
+                    .wrap(from_fn(idempotent_requests))
+                    .route(web::post().to(publish_newsletter)),
+            )
             .service(
-                web::scope("/admin")
+                web::resource("/subscriptions")
+                    .wrap(from_fn(idempotent_requests))
+                    .route(web::post().to(subscribe)),
+            )
+            .service(
+                // Other methods fall through to the `/admin` scope
+                web::resource("/admin/newsletters")
+                    .guard(guard::Post())
                     .wrap(from_fn(reject_anonymous_users))
-                    .route("/dashboard", web::get().to(admin_dashboard))
-                    .route("/newsletters", web::get().to(publish_newsletter_form))
-                    .route("/newsletters", web::post().to(publish_newsletter))
-                    .route("/password", web::get().to(change_password_form))
-                    .route("/password", web::post().to(change_password))
-                    .route("/logout", web::post().to(log_out)),
+                    .wrap(message_framework.clone())
+                    .wrap(from_fn(idempotent_requests))
+                    .route(web::post().to(publish_newsletter)),
+            )
+            .service(
+                web::scope("")
+                    .wrap(message_framework.clone())
+                    .route("/", web::get().to(home))
+                    .route("/login", web::get().to(login_form))
+                    .route("/login", web::post().to(login))
+                    .route("/health_check", web::get().to(health_check))
+                    .route("/health/ready", web::get().to(readiness_check))
+                    .route("/subscriptions/confirm", web::get().to(confirm))
+                    .route(
+                        "/subscriptions/unsubscribe",
+                        web::get().to(unsubscribe_form),
+                    )
+                    .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+                    .route(
+                        "/subscriptions/data",
+                        web::get().to(subscriber_data_request_form),
+                    )
+                    .route(
+                        "/subscriptions/data",
+                        web::post().to(request_subscriber_data),
+                    )
+                    .route(
+                        "/subscriptions/data/manage",
+                        web::get().to(subscriber_data_page),
+                    )
+                    .route(
+                        "/subscriptions/data/export",
+                        web::get().to(export_subscriber_data),
+                    )
+                    .route(
+                        "/subscriptions/data/erase",
+                        web::post().to(erase_subscriber_data),
+                    )
+                    .route(
+                        "/subscriptions/preferences",
+                        web::get().to(preferences_page),
+                    )
+                    .route(
+                        "/subscriptions/preferences",
+                        web::post().to(save_preferences),
+                    )
+                    .route("/issues", web::get().to(issue_archive))
+                    // Before `/issues/{issue_id}`, which would match it as well
+                    .route("/issues/feed.xml", web::get().to(issue_feed))
+                    .route("/issues/{issue_id}", web::get().to(issue_web_view))
+                    .route("/issues/{issue_id}/open", web::get().to(track_open))
+                    .route("/issues/{issue_id}/click", web::get().to(track_click))
+                    .route("/webhooks/email/{provider}", web::post().to(email_webhook))
+                    .service(
+                        web::scope("/admin")
+                            .wrap(from_fn(reject_anonymous_users))
+                            .route("/dashboard", web::get().to(admin_dashboard))
+                            .route("/newsletters", web::get().to(publish_newsletter_form))
+                            .route("/newsletters/preview", web::post().to(preview_newsletter))
+                            .route("/newsletters/drafts", web::get().to(newsletter_drafts))
+                            .route(
+                                "/newsletters/drafts/{issue_id}/publish",
+                                web::post().to(publish_newsletter_draft),
+                            )
+                            // After the other `/newsletters/...` routes
+                            .route(
+                                "/newsletters/{issue_id}",
+                                web::get().to(newsletter_issue_page),
+                            )
+                            .route(
+                                "/newsletters/{issue_id}/progress",
+                                web::get().to(newsletter_delivery_progress),
+                            )
+                            .route(
+                                "/newsletters/{issue_id}/pause",
+                                web::post().to(pause_newsletter_delivery),
+                            )
+                            .route(
+                                "/newsletters/{issue_id}/resume",
+                                web::post().to(resume_newsletter_delivery),
+                            )
+                            .route(
+                                "/newsletters/{issue_id}/cancel",
+                                web::post().to(cancel_newsletter_delivery),
+                            )
+                            .route("/subscribers", web::get().to(subscribers_list))
+                            .route("/lists", web::get().to(lists_page))
+                            .route("/lists", web::post().to(create_list))
+                            .route("/profile-fields", web::get().to(profile_fields_page))
+                            .route("/profile-fields", web::post().to(create_profile_field))
+                            .route(
+                                "/subscribers/{subscriber_id}/consent",
+                                web::get().to(subscriber_consent),
+                            )
+                            .route(
+                                "/subscribers/{subscriber_id}/consent/export",
+                                web::get().to(export_subscriber_consent),
+                            )
+                            .route(
+                                "/subscribers/{subscriber_id}/tags",
+                                web::post().to(add_subscriber_tag),
+                            )
+                            .route(
+                                "/subscribers/{subscriber_id}/tags/remove",
+                                web::post().to(remove_subscriber_tag),
+                            )
+                            .route("/suppressions", web::get().to(suppression_list))
+                            .route("/email-templates", web::get().to(email_templates_list))
+                            .route(
+                                "/email-templates/{name}",
+                                web::get().to(email_template_form),
+                            )
+                            .route(
+                                "/email-templates/{name}",
+                                web::post().to(save_email_template),
+                            )
+                            .route(
+                                "/email-templates/{name}/preview",
+                                web::post().to(preview_email_template),
+                            )
+                            .route(
+                                "/email-templates/{name}/reset",
+                                web::post().to(reset_email_template),
+                            )
+                            .route("/password", web::get().to(change_password_form))
+                            .route("/password", web::post().to(change_password))
+                            .route("/logout", web::post().to(log_out)),
+                    ),
             )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
+            .app_data(email_templates.clone())
             .app_data(base_url.clone())
+            .app_data(tracker.clone())
+            .app_data(readiness_probe.clone())
             .app_data(Data::new(HmacSecret(hmac_secret.clone())))
+            .app_data(Data::new(EmailWebhookSecret(webhook_secret.clone())))
     })
     .listen(listener)?
+    // Signals are handled by the caller, see `run_until_stopped`
+    .disable_signals()
+    .shutdown_timeout(shutdown_grace_period_seconds)
     .run();
     Ok(server)
 }
@@ -0,0 +1,210 @@
+use crate::domain::SubscriberEmail;
+use crate::email_templates::{EmailTemplate, EmailTemplates};
+use crate::issue_delivery_worker::enqueue_digest_email;
+use crate::routes::preferences_url;
+use crate::shutdown::Shutdown;
+use crate::tracking::Tracker;
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How often held issues are looked at. Digests are due a day, or a week,
+/// after the oldest issue they hold.
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Queue the digests that are due, until shutdown is triggered.
+pub async fn run_digests_until_stopped(
+    pool: PgPool,
+    templates: EmailTemplates,
+    tracker: Tracker,
+    base_url: String,
+    hmac_secret: Secret<String>,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_triggered() {
+        if let Err(e) =
+            try_send_due_digests(&pool, &templates, &tracker, &base_url, &hmac_secret).await
+        {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send the due digests",
+            );
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            _ = shutdown.triggered() => {}
+        }
+    }
+    Ok(())
+}
+
+/// Queue a digest for every subscriber whose digest is due, listing the
+/// issues held for them. Returns how many digests were queued.
+///
+/// Subscribers who went back to every issue get what was held for them
+/// right away.
+#[tracing::instrument(skip_all, err)]
+pub async fn try_send_due_digests(
+    pool: &PgPool,
+    templates: &EmailTemplates,
+    tracker: &Tracker,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+) -> Result<usize, anyhow::Error> {
+    // The issues of paused deliveries are held until they resume
+    let due = sqlx::query!(
+        r#"
+        SELECT d.subscriber_id
+        FROM digest_queue d
+        JOIN subscriptions s ON s.id = d.subscriber_id
+        JOIN newsletter_issues i USING (newsletter_issue_id)
+        WHERE i.delivery_status = 'active'
+        GROUP BY d.subscriber_id, s.digest_frequency
+        HAVING MIN(d.held_at) <= now() - CASE s.digest_frequency
+            WHEN 'daily' THEN interval '1 day'
+            WHEN 'weekly' THEN interval '7 days'
+            ELSE interval '0'
+        END
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to find the due digests.")?;
+    let mut sent = 0;
+    for r in due {
+        // One digest failing must not hold back the others
+        match send_digest(
+            pool,
+            templates,
+            tracker,
+            base_url,
+            hmac_secret,
+            r.subscriber_id,
+        )
+        .await
+        {
+            Ok(true) => sent += 1,
+            Ok(false) => {}
+            Err(e) => tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                subscriber_id = %r.subscriber_id,
+                "Failed to send a digest",
+            ),
+        }
+    }
+    Ok(sent)
+}
+
+#[derive(serde::Serialize)]
+struct DigestEntry {
+    title: String,
+    url: String,
+}
+
+/// Returns `false` if there was nothing left to send: another instance
+/// got there first, or the subscriber does not want the issues anymore.
+#[tracing::instrument(skip(pool, templates, tracker, base_url, hmac_secret))]
+async fn send_digest(
+    pool: &PgPool,
+    templates: &EmailTemplates,
+    tracker: &Tracker,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    subscriber_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let mut held = sqlx::query!(
+        r#"
+        DELETE FROM digest_queue d
+        USING newsletter_issues i
+        WHERE d.subscriber_id = $1
+        AND i.newsletter_issue_id = d.newsletter_issue_id
+        AND i.delivery_status = 'active'
+        RETURNING
+            i.newsletter_issue_id,
+            i.title,
+            d.held_at,
+            EXISTS (
+                SELECT 1 FROM list_subscriptions ls
+                JOIN newsletter_issue_lists il USING (list_id)
+                WHERE ls.subscriber_id = d.subscriber_id
+                AND ls.status = 'confirmed'
+                AND il.newsletter_issue_id = d.newsletter_issue_id
+            ) AS "wanted!"
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to take the held issues.")?;
+    held.retain(|issue| issue.wanted);
+    held.sort_by_key(|issue| issue.held_at);
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT
+            email,
+            name,
+            (
+                status = 'confirmed'
+                AND lower(email) NOT IN (SELECT lower(email) FROM email_suppressions)
+            ) AS "wants_digest!"
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to retrieve the subscriber of a digest.")?;
+    if held.is_empty() || !subscriber.wants_digest {
+        tracing::info!("The subscriber does not want the held issues anymore. Dropping them.");
+        transaction.commit().await?;
+        return Ok(false);
+    }
+    let recipient = match SubscriberEmail::parse(subscriber.email) {
+        Ok(recipient) => recipient,
+        Err(e) => {
+            tracing::error!(
+                error.message = %e,
+                "Skipping the digest of a subscriber. Their stored contact details are invalid",
+            );
+            transaction.commit().await?;
+            return Ok(false);
+        }
+    };
+
+    let issues: Vec<_> = held
+        .into_iter()
+        .map(|issue| DigestEntry {
+            title: issue.title,
+            url: tracker.view_url(issue.newsletter_issue_id, subscriber_id),
+        })
+        .collect();
+    let email = templates
+        .render(
+            pool,
+            EmailTemplate::Digest,
+            &serde_json::json!({
+                "name": subscriber.name,
+                "issues": issues,
+                "preferences_url": preferences_url(base_url, subscriber_id, hmac_secret),
+            }),
+        )
+        .await?;
+    enqueue_digest_email(
+        &mut transaction,
+        subscriber_id,
+        &recipient,
+        &email.subject,
+        &email.html,
+        &email.text,
+    )
+    .await
+    .context("Failed to queue a digest.")?;
+    transaction.commit().await?;
+    Ok(true)
+}
@@ -1,11 +1,21 @@
+use crate::configuration::OtlpSettings;
+use opentelemetry::trace::{TraceContextExt, TraceError, TracerProvider as _};
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Tracer, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use std::collections::HashMap;
 use tokio::task::JoinHandle;
-use tracing::{subscriber::set_global_default, Subscriber};
+use tracing::{subscriber::set_global_default, Span, Subscriber};
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::fmt::MakeWriter;
 use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Registry};
 
-/// Compose multiple layers into a `tracing`'s subscriber
+/// Compose multiple layers into a `tracing`'s subscriber.
+/// Spans are exported with the tracer as well, if there is one.
 ///
 /// # Implementation Notes
 ///
@@ -19,6 +29,7 @@ pub fn get_subscriber<Sink>(
     name: String,
     env_filter: String,
     sink: Sink,
+    tracer: Option<Tracer>,
 ) -> impl Subscriber + Send + Sync
 where
     // This "weird" syntax is higher-ranked trait bound (HRTB)
@@ -34,6 +45,32 @@ where
         .with(env_filter)
         .with(JsonStorageLayer)
         .with(formatting_layer)
+        .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+/// A tracer exporting spans to an OpenTelemetry collector over gRPC,
+/// in batches. It must be built within the Tokio runtime.
+pub fn otlp_tracer(name: &str, settings: &OtlpSettings) -> Result<Tracer, TraceError> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(&settings.endpoint)
+        .build()?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            name.to_string(),
+        )]))
+        .build();
+    // Registered so that `flush_spans` can reach it on exit
+    global::set_tracer_provider(provider.clone());
+    Ok(provider.tracer(name.to_string()))
+}
+
+/// Export the spans still buffered, before the process exits.
+pub async fn flush_spans() {
+    // Shutting the exporter down blocks
+    let _ = tokio::task::spawn_blocking(global::shutdown_tracer_provider).await;
 }
 
 /// Register a subsciber as global default to process span data.
@@ -41,6 +78,8 @@ where
 /// It should only be called once!
 pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     LogTracer::init().expect("Failed to set logger");
+    // W3C `traceparent` headers, on incoming and outgoing requests
+    global::set_text_map_propagator(TraceContextPropagator::new());
     set_global_default(subscriber).expect("Failed to set subscriber");
 }
 
@@ -53,3 +92,27 @@ where
     let current_span = tracing::Span::current();
     tokio::task::spawn_blocking(move || current_span.in_scope(f))
 }
+
+/// The headers carrying the context of the current span to another
+/// service. Empty unless spans are exported.
+pub fn trace_context_headers() -> HashMap<String, String> {
+    let context = Span::current().context();
+    let mut headers = HashMap::new();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut headers));
+    headers
+}
+
+/// The W3C `traceparent` of the current span, to link later work to it.
+pub fn current_traceparent() -> Option<String> {
+    trace_context_headers().remove("traceparent")
+}
+
+/// Link the current span to the one a `traceparent` was taken from.
+pub fn link_to_traceparent(traceparent: &str) {
+    let headers = HashMap::from([("traceparent".to_string(), traceparent.to_string())]);
+    let context = global::get_text_map_propagator(|propagator| propagator.extract(&headers));
+    let span_context = context.span().span_context().clone();
+    if span_context.is_valid() {
+        Span::current().add_link(span_context);
+    }
+}
@@ -0,0 +1,322 @@
+use hmac::{Hmac, Mac};
+use htmlescape::{decode_html, encode_minimal};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// What a recipient did with an issue.
+#[derive(Debug, Clone, Copy)]
+pub enum EngagementKind {
+    Open,
+    Click,
+    /// Followed the "view in browser" link
+    View,
+}
+
+impl EngagementKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EngagementKind::Open => "open",
+            EngagementKind::Click => "click",
+            EngagementKind::View => "view",
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("The tracking signature is invalid.")]
+pub struct InvalidSignature;
+
+/// The query parameters of tracking pixels and redirects.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct TrackingParameters {
+    pub recipient: Uuid,
+    /// The target of the link, for clicks
+    pub url: Option<String>,
+    pub signature: String,
+}
+
+/// Builds the per-recipient tracking URLs embedded in an issue.
+///
+/// Every URL carries an HMAC of the issue, the recipient and - for clicks -
+/// the target: recipients cannot be impersonated and the redirect
+/// endpoint cannot be abused to send people elsewhere.
+#[derive(Clone)]
+pub struct Tracker {
+    base_url: String,
+    secret: Secret<String>,
+}
+
+impl Tracker {
+    pub fn new(base_url: String, secret: Secret<String>) -> Self {
+        Self { base_url, secret }
+    }
+
+    pub fn open_url(&self, issue_id: Uuid, recipient: Uuid) -> String {
+        let parameters = TrackingParameters {
+            recipient,
+            url: None,
+            signature: self.sign(EngagementKind::Open, issue_id, recipient, None),
+        };
+        format!(
+            "{}/issues/{}/open?{}",
+            self.base_url,
+            issue_id,
+            serde_urlencoded::to_string(&parameters).unwrap()
+        )
+    }
+
+    pub fn click_url(&self, issue_id: Uuid, recipient: Uuid, target: &str) -> String {
+        let parameters = TrackingParameters {
+            recipient,
+            url: Some(target.to_owned()),
+            signature: self.sign(EngagementKind::Click, issue_id, recipient, Some(target)),
+        };
+        format!(
+            "{}/issues/{}/click?{}",
+            self.base_url,
+            issue_id,
+            serde_urlencoded::to_string(&parameters).unwrap()
+        )
+    }
+
+    /// The web view of the issue. It opens private issues as well.
+    pub fn view_url(&self, issue_id: Uuid, recipient: Uuid) -> String {
+        let parameters = TrackingParameters {
+            recipient,
+            url: None,
+            signature: self.sign(EngagementKind::View, issue_id, recipient, None),
+        };
+        format!(
+            "{}/issues/{}?{}",
+            self.base_url,
+            issue_id,
+            serde_urlencoded::to_string(&parameters).unwrap()
+        )
+    }
+
+    /// Returns the id of the recipient the URL was issued to.
+    pub fn verify(
+        &self,
+        kind: EngagementKind,
+        issue_id: Uuid,
+        parameters: &TrackingParameters,
+    ) -> Result<Uuid, InvalidSignature> {
+        let signature = hex::decode(&parameters.signature).map_err(|_| InvalidSignature)?;
+        // `verify_slice` compares in constant time
+        self.mac(
+            kind,
+            issue_id,
+            parameters.recipient,
+            parameters.url.as_deref(),
+        )
+        .verify_slice(&signature)
+        .map_err(|_| InvalidSignature)?;
+        Ok(parameters.recipient)
+    }
+
+    /// Append an invisible 1x1 image, loaded when the issue is opened.
+    pub fn add_open_pixel(&self, html: &str, issue_id: Uuid, recipient: Uuid) -> String {
+        let pixel = format!(
+            r#"<img src="{}" width="1" height="1" alt="" style="display:none">"#,
+            encode_minimal(&self.open_url(issue_id, recipient))
+        );
+        append_to_body(html, &pixel)
+    }
+
+    /// Append a link to the web view of the issue.
+    pub fn add_view_link(&self, html: &str, issue_id: Uuid, recipient: Uuid) -> String {
+        let link = format!(
+            r#"<p><a href="{}">View this issue in your browser</a></p>"#,
+            encode_minimal(&self.view_url(issue_id, recipient))
+        );
+        append_to_body(html, &link)
+    }
+
+    /// Point every `http(s)` link of the issue to the click redirect.
+    /// Anchors, `mailto:` links and the like are left alone.
+    pub fn rewrite_links(&self, html: &str, issue_id: Uuid, recipient: Uuid) -> String {
+        // ASCII lowercasing preserves byte offsets
+        let lowercase = html.to_ascii_lowercase();
+        let mut output = String::with_capacity(html.len());
+        let mut cursor = 0;
+        while let Some(offset) = lowercase[cursor..].find("href=") {
+            let value_start = cursor + offset + "href=".len();
+            output.push_str(&html[cursor..value_start]);
+            cursor = value_start;
+
+            let quote = match html[value_start..].chars().next() {
+                Some(quote @ ('"' | '\'')) => quote,
+                // Unquoted attribute values cannot hold much of a URL
+                _ => continue,
+            };
+            let value_end = match html[value_start + 1..].find(quote) {
+                Some(length) => value_start + 1 + length,
+                None => break,
+            };
+            let raw_target = &html[value_start + 1..value_end];
+            let target = decode_html(raw_target).unwrap_or_else(|_| raw_target.to_owned());
+
+            output.push(quote);
+            if is_trackable(&target) {
+                output.push_str(&encode_minimal(
+                    &self.click_url(issue_id, recipient, &target),
+                ));
+            } else {
+                output.push_str(raw_target);
+            }
+            cursor = value_end;
+        }
+        output.push_str(&html[cursor..]);
+        output
+    }
+
+    fn sign(
+        &self,
+        kind: EngagementKind,
+        issue_id: Uuid,
+        recipient: Uuid,
+        target: Option<&str>,
+    ) -> String {
+        hex::encode(
+            self.mac(kind, issue_id, recipient, target)
+                .finalize()
+                .into_bytes(),
+        )
+    }
+
+    fn mac(
+        &self,
+        kind: EngagementKind,
+        issue_id: Uuid,
+        recipient: Uuid,
+        target: Option<&str>,
+    ) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(self.secret.expose_secret().as_bytes()).unwrap();
+        mac.update(
+            format!(
+                "{}:{}:{}:{}",
+                kind.as_str(),
+                issue_id,
+                recipient,
+                target.unwrap_or_default()
+            )
+            .as_bytes(),
+        );
+        mac
+    }
+}
+
+fn append_to_body(html: &str, fragment: &str) -> String {
+    match html.rfind("</body>") {
+        Some(index) => format!("{}{}{}", &html[..index], fragment, &html[index..]),
+        None => format!("{}{}", html, fragment),
+    }
+}
+
+fn is_trackable(target: &str) -> bool {
+    let target = target.trim_start().to_ascii_lowercase();
+    target.starts_with("http://") || target.starts_with("https://")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EngagementKind, Tracker, TrackingParameters};
+    use claim::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn tracker() -> Tracker {
+        Tracker::new(
+            "http://127.0.0.1".into(),
+            Secret::new("a-very-secret-key".to_string()),
+        )
+    }
+
+    fn parameters(url: &str) -> TrackingParameters {
+        let query = reqwest::Url::parse(url)
+            .unwrap()
+            .query()
+            .unwrap()
+            .to_owned();
+        serde_urlencoded::from_str(&query).unwrap()
+    }
+
+    #[test]
+    fn a_click_url_is_verified() {
+        let (issue_id, recipient) = (Uuid::new_v4(), Uuid::new_v4());
+        let url = tracker().click_url(issue_id, recipient, "https://example.com/?a=1&b=2");
+
+        let parameters = parameters(&url);
+        assert_eq!(
+            parameters.url.as_deref(),
+            Some("https://example.com/?a=1&b=2")
+        );
+        assert_ok_eq!(
+            tracker().verify(EngagementKind::Click, issue_id, &parameters),
+            recipient
+        );
+    }
+
+    #[test]
+    fn a_click_url_pointing_elsewhere_is_rejected() {
+        let issue_id = Uuid::new_v4();
+        let url = tracker().click_url(issue_id, Uuid::new_v4(), "https://example.com");
+
+        let mut parameters = parameters(&url);
+        parameters.url = Some("https://evil.example.com".into());
+        assert_err!(tracker().verify(EngagementKind::Click, issue_id, &parameters));
+    }
+
+    #[test]
+    fn an_open_url_for_another_recipient_is_rejected() {
+        let issue_id = Uuid::new_v4();
+        let url = tracker().open_url(issue_id, Uuid::new_v4());
+
+        let mut parameters = parameters(&url);
+        parameters.recipient = Uuid::new_v4();
+        assert_err!(tracker().verify(EngagementKind::Open, issue_id, &parameters));
+    }
+
+    #[test]
+    fn an_open_url_cannot_be_used_as_a_click_url() {
+        let issue_id = Uuid::new_v4();
+        let url = tracker().open_url(issue_id, Uuid::new_v4());
+
+        assert_err!(tracker().verify(EngagementKind::Click, issue_id, &parameters(&url)));
+    }
+
+    #[test]
+    fn a_view_url_cannot_be_used_as_an_open_url() {
+        let (issue_id, recipient) = (Uuid::new_v4(), Uuid::new_v4());
+        let url = tracker().view_url(issue_id, recipient);
+
+        assert_ok_eq!(
+            tracker().verify(EngagementKind::View, issue_id, &parameters(&url)),
+            recipient
+        );
+        assert_err!(tracker().verify(EngagementKind::Open, issue_id, &parameters(&url)));
+    }
+
+    #[test]
+    fn only_web_links_are_rewritten() {
+        let html = r#"<a href="https://example.com/?a=1&amp;b=2">web</a> <a href='#top'>anchor</a> <a HREF="mailto:a@b.c">mail</a>"#;
+
+        let rewritten = tracker().rewrite_links(html, Uuid::new_v4(), Uuid::new_v4());
+
+        assert!(rewritten.starts_with(r#"<a href="http://127.0.0.1/issues/"#));
+        assert!(rewritten.contains(r#"<a href='#top'>anchor</a>"#));
+        assert!(rewritten.contains(r#"<a HREF="mailto:a@b.c">mail</a>"#));
+    }
+
+    #[test]
+    fn the_open_pixel_goes_at_the_end_of_the_body() {
+        let html = "<html><body><p>Hello</p></body></html>";
+
+        let tracked = tracker().add_open_pixel(html, Uuid::new_v4(), Uuid::new_v4());
+
+        assert!(tracked.starts_with("<html><body><p>Hello</p><img src="));
+        assert!(tracked.ends_with("</body></html>"));
+    }
+}
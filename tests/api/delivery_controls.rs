@@ -0,0 +1,164 @@
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Publish an issue to two subscribers, without delivering it.
+async fn publish_issue(app: &TestApp) -> Uuid {
+    create_confirmed_subscriber(app, "ursula_le_guin@gmail.com").await;
+    create_confirmed_subscriber(app, "octavia_butler@gmail.com").await;
+    app.test_user.login(app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn paused_issues_are_not_delivered_until_resumed() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = publish_issue(&app).await;
+
+    // Act - Part 1 - Pause
+    let response = app.post_delivery_control(issue_id, "pause").await;
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/{}", issue_id));
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    drop(_mock_guard);
+
+    // Assert - Part 1
+    let html_page = app.get_newsletter_issue_html(issue_id).await;
+    assert!(html_page.contains("<p><i>The delivery has been paused.</i></p>"));
+    assert!(html_page.contains("<p>Delivery: paused.</p>"));
+    assert!(html_page.contains(r#"action="/admin/newsletters/"#));
+    assert!(html_page.contains("Resume</button>"));
+    let progress = app.get_delivery_progress(issue_id).await;
+    assert_eq!(progress["queued"], 2);
+    assert_eq!(progress["delivered"], 0);
+
+    // Act - Part 2 - Resume
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.post_delivery_control(issue_id, "resume").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 2
+    let progress = app.get_delivery_progress(issue_id).await;
+    assert_eq!(progress["delivery_status"], "active");
+    assert_eq!(progress["queued"], 0);
+    assert_eq!(progress["delivered"], 2);
+}
+
+#[tokio::test]
+async fn cancelling_an_issue_drops_the_remaining_emails() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = publish_issue(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_delivery_control(issue_id, "cancel").await;
+    assert_is_redirect_to(&response, &format!("/admin/newsletters/{}", issue_id));
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let html_page = app.get_newsletter_issue_html(issue_id).await;
+    assert!(html_page
+        .contains("<p><i>The delivery has been cancelled - 2 emails will not go out.</i></p>"));
+    let progress = app.get_delivery_progress(issue_id).await;
+    assert_eq!(progress["delivery_status"], "cancelled");
+    assert_eq!(progress["queued"], 0);
+    assert_eq!(progress["cancelled"], 2);
+    let outcomes = sqlx::query!("SELECT outcome FROM issue_delivery_log")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(outcomes.iter().all(|r| r.outcome == "cancelled"));
+}
+
+#[tokio::test]
+async fn an_issue_cannot_be_cancelled_twice() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = publish_issue(&app).await;
+    app.post_delivery_control(issue_id, "cancel").await;
+    app.get_newsletter_issue_html(issue_id).await;
+
+    // Act
+    app.post_delivery_control(issue_id, "cancel").await;
+
+    // Assert
+    let html_page = app.get_newsletter_issue_html(issue_id).await;
+    assert!(html_page.contains("<p><i>The delivery has already been cancelled.</i></p>"));
+    assert!(html_page.contains("<p>Delivery: cancelled.</p>"));
+    assert!(!html_page.contains("Cancel</button>"));
+    let n_cancelled = sqlx::query!(
+        "SELECT COUNT(*) AS \"count!\" FROM issue_delivery_log WHERE outcome = 'cancelled'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_cancelled, 2);
+}
+
+#[tokio::test]
+async fn the_issue_page_refreshes_while_emails_are_going_out() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = publish_issue(&app).await;
+
+    // Act
+    let html_page = app.get_newsletter_issue_html(issue_id).await;
+
+    // Assert
+    assert!(html_page.contains(r#"<meta http-equiv="refresh" content="5">"#));
+    assert!(html_page.contains("<tr><td>2</td><td>0</td><td>0</td><td>0</td><td>0</td></tr>"));
+    assert!(html_page.contains("Pause</button>"));
+    assert!(html_page.contains("Cancel</button>"));
+}
+
+#[tokio::test]
+async fn the_progress_of_an_unknown_issue_is_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/admin/newsletters/{}/progress",
+            &app.address,
+            Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,150 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::SubscriberEmail;
+use zero2prod::issue_delivery_worker::{
+    enqueue_transactional_email, try_execute_task, ExecutionOutcome, QueueScheduler,
+};
+
+async fn publish_issue(app: &TestApp) {
+    app.test_user.login(app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+}
+
+async fn enqueue_password_reset(app: &TestApp, email: &str) {
+    let mut transaction = app.db_pool.begin().await.unwrap();
+    enqueue_transactional_email(
+        &mut transaction,
+        &SubscriberEmail::parse(email.into()).unwrap(),
+        "Reset your password",
+        "<p>Follow the link to reset your password.</p>",
+        "Follow the link to reset your password.",
+        0,
+    )
+    .await
+    .unwrap();
+    transaction.commit().await.unwrap();
+}
+
+async fn execute_task(app: &TestApp, scheduler: &QueueScheduler) -> ExecutionOutcome {
+    try_execute_task(
+        &app.db_pool,
+        &app.email_client,
+        &app.throttle,
+        scheduler,
+        &app.tracker,
+        &app.base_url,
+        &app.hmac_secret,
+    )
+    .await
+    .unwrap()
+}
+
+async fn n_delivered_issues(app: &TestApp) -> i64 {
+    sqlx::query!(
+        "SELECT COUNT(*) AS \"count!\" FROM issue_delivery_log WHERE outcome = 'delivered'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count
+}
+
+#[tokio::test]
+async fn transactional_emails_jump_the_queue() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    publish_issue(&app).await;
+    enqueue_password_reset(&app, "octavia_butler@gmail.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let outcome = execute_task(&app, &QueueScheduler::new(0.0)).await;
+
+    // Assert
+    assert!(matches!(outcome, ExecutionOutcome::TaskCompleted));
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "octavia_butler@gmail.com");
+    assert_eq!(body["Subject"], "Reset your password");
+    assert_eq!(n_delivered_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn bulk_deliveries_keep_their_share_of_the_queue() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    publish_issue(&app).await;
+    for _ in 0..3 {
+        enqueue_password_reset(&app, "octavia_butler@gmail.com").await;
+    }
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    let scheduler = QueueScheduler::new(0.5);
+
+    // Act
+    execute_task(&app, &scheduler).await;
+    execute_task(&app, &scheduler).await;
+
+    // Assert
+    assert_eq!(n_delivered_issues(&app).await, 1);
+    let n_queued = sqlx::query!(
+        "SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue WHERE kind = 'transactional'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_queued, 2);
+}
+
+#[tokio::test]
+async fn transactional_emails_are_sent_while_an_issue_is_paused() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    publish_issue(&app).await;
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    app.post_delivery_control(issue_id, "pause").await;
+    enqueue_password_reset(&app, "octavia_butler@gmail.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(n_delivered_issues(&app).await, 0);
+}
@@ -0,0 +1,112 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_issue(app: &TestApp) {
+    app.test_user.login(app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+}
+
+async fn delivery_outcome(app: &TestApp, email: &str) -> String {
+    sqlx::query!(
+        "SELECT outcome FROM issue_delivery_log WHERE subscriber_email = $1",
+        email,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .outcome
+}
+
+#[tokio::test]
+async fn subscribers_who_unsubscribe_mid_send_are_skipped() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    create_confirmed_subscriber(&app, "octavia_butler@gmail.com").await;
+    publish_issue(&app).await;
+
+    // Act - Part 1 - Unsubscribe once the issue is queued
+    let token = sqlx::query!(
+        r#"
+        SELECT t.subscription_token
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE s.email = 'octavia_butler@gmail.com'
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .subscription_token;
+    app.post_unsubscribe(&serde_json::json!({ "subscription_token": token }))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act - Part 2 - Deliver
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(
+        delivery_outcome(&app, "ursula_le_guin@gmail.com").await,
+        "delivered"
+    );
+    assert_eq!(
+        delivery_outcome(&app, "octavia_butler@gmail.com").await,
+        "skipped"
+    );
+    // Mock verifies on Drop that the newsletter went out once,
+    // along with the unsubscribe receipt
+}
+
+#[tokio::test]
+async fn issues_go_to_the_current_address_of_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    publish_issue(&app).await;
+    sqlx::query!(
+        "UPDATE subscriptions SET email = 'ursula@le-guin.example.com' WHERE email = 'ursula_le_guin@gmail.com'"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula@le-guin.example.com");
+    assert_eq!(
+        delivery_outcome(&app, "ursula@le-guin.example.com").await,
+        "delivered"
+    );
+}
@@ -0,0 +1,227 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::delivery_throttle::DeliveryThrottle;
+use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+
+/// Publish an issue, as if a worker had died after handing it to the
+/// provider. Returns the message key of the delivery.
+async fn publish_and_crash(app: &TestApp) -> Uuid {
+    create_confirmed_subscriber(app, "ursula_le_guin@gmail.com").await;
+    app.test_user.login(app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    let message_key = sqlx::query!("SELECT message_key FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .message_key;
+    sqlx::query!(
+        "INSERT INTO issue_delivery_attempts (message_key, started_at) VALUES ($1, now())",
+        message_key,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    message_key
+}
+
+fn outbound_messages(total_count: u64) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(serde_json::json!({
+        "TotalCount": total_count,
+        "Messages": [],
+    }))
+}
+
+async fn delivery_outcome(app: &TestApp) -> String {
+    sqlx::query!("SELECT outcome FROM issue_delivery_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .outcome
+}
+
+#[tokio::test]
+async fn each_delivery_carries_a_message_key() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    let message_key = sqlx::query!("SELECT message_key FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .message_key;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Metadata"]["message_key"], message_key.to_string());
+    // The attempt is forgotten along with the task
+    let n_attempts = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_attempts")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_attempts, 0);
+}
+
+#[tokio::test]
+async fn an_email_already_accepted_by_the_provider_is_not_sent_again() {
+    // Arrange
+    let app = spawn_app().await;
+    let message_key = publish_and_crash(&app).await;
+    Mock::given(path("/messages/outbound"))
+        .and(method("GET"))
+        .and(query_param("metadata_message_key", message_key.to_string()))
+        .respond_with(outbound_messages(1))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(delivery_outcome(&app).await, "delivered");
+    // Mocks verify on Drop that we asked the provider, and did not resend
+}
+
+#[tokio::test]
+async fn an_email_already_accepted_by_the_provider_does_not_wait_for_the_throttle() {
+    // Arrange
+    let app = spawn_app().await;
+    publish_and_crash(&app).await;
+    Mock::given(path("/messages/outbound"))
+        .and(method("GET"))
+        .respond_with(outbound_messages(1))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    // The daily quota is already used up
+    let throttle = DeliveryThrottle::new(1000.0, 1000, Some(0));
+
+    // Act
+    let outcome = try_execute_task(
+        &app.db_pool,
+        &app.email_client,
+        &throttle,
+        &app.scheduler,
+        &app.tracker,
+        &app.base_url,
+        &app.hmac_secret,
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    assert!(matches!(outcome, ExecutionOutcome::TaskCompleted));
+    assert_eq!(delivery_outcome(&app).await, "delivered");
+}
+
+#[tokio::test]
+async fn an_email_the_provider_never_got_is_sent_with_the_same_key() {
+    // Arrange
+    let app = spawn_app().await;
+    let message_key = publish_and_crash(&app).await;
+    Mock::given(path("/messages/outbound"))
+        .and(method("GET"))
+        .respond_with(outbound_messages(0))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(delivery_outcome(&app).await, "delivered");
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Metadata"]["message_key"], message_key.to_string());
+}
+
+#[tokio::test]
+async fn the_task_stays_queued_if_the_provider_cannot_be_asked() {
+    // Arrange
+    let app = spawn_app().await;
+    publish_and_crash(&app).await;
+    Mock::given(path("/messages/outbound"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let outcome = try_execute_task(
+        &app.db_pool,
+        &app.email_client,
+        &app.throttle,
+        &app.scheduler,
+        &app.tracker,
+        &app.base_url,
+        &app.hmac_secret,
+    )
+    .await;
+
+    // Assert
+    assert!(outcome.is_err());
+    let n_queued = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 1);
+}
@@ -0,0 +1,194 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::delivery_throttle::DeliveryThrottle;
+use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+
+async fn publish_issue(app: &TestApp) {
+    app.test_user.login(app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+}
+
+async fn execute_task(app: &TestApp, throttle: &DeliveryThrottle) -> ExecutionOutcome {
+    try_execute_task(
+        &app.db_pool,
+        &app.email_client,
+        throttle,
+        &app.scheduler,
+        &app.tracker,
+        &app.base_url,
+        &app.hmac_secret,
+    )
+    .await
+    .unwrap()
+}
+
+async fn queue_length(app: &TestApp) -> i64 {
+    sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn a_429_pauses_delivery_until_retry_after() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    publish_issue(&app).await;
+
+    // Act - Part 1 - The provider rate limits us
+    let outcome = execute_task(&app, &app.throttle).await;
+
+    // Assert - Part 1
+    assert!(matches!(outcome, ExecutionOutcome::Throttled(wait) if wait == Duration::from_secs(1)));
+    assert_eq!(queue_length(&app).await, 1);
+    // Every worker waits
+    assert!(matches!(
+        execute_task(&app, &app.throttle).await,
+        ExecutionOutcome::Throttled(_)
+    ));
+
+    // Act - Part 2 - Try again later
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 2
+    assert_eq!(queue_length(&app).await, 0);
+    let outcome = sqlx::query!("SELECT outcome FROM issue_delivery_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .outcome;
+    assert_eq!(outcome, "delivered");
+    // Mocks verify on Drop that we have retried once
+}
+
+#[tokio::test]
+async fn delivery_pauses_once_the_daily_quota_is_reached() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    create_confirmed_subscriber(&app, "octavia_butler@gmail.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    publish_issue(&app).await;
+    let throttle = DeliveryThrottle::new(1000.0, 1000, Some(1));
+
+    // Act
+    let first = execute_task(&app, &throttle).await;
+    let second = execute_task(&app, &throttle).await;
+
+    // Assert
+    assert!(matches!(first, ExecutionOutcome::TaskCompleted));
+    assert!(matches!(second, ExecutionOutcome::BulkQuotaReached(wait) if wait > Duration::ZERO));
+    assert_eq!(queue_length(&app).await, 1);
+    // Mock verifies on Drop that we have sent a single email
+}
+
+#[tokio::test]
+async fn an_email_refused_by_the_daily_quota_does_not_use_up_a_token() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    publish_issue(&app).await;
+    // A single token, and the daily quota is already used up
+    let throttle = DeliveryThrottle::new(0.01, 1, Some(0));
+    sqlx::query!("UPDATE delivery_throttle SET tokens = 1, refilled_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let outcome = execute_task(&app, &throttle).await;
+
+    // Assert
+    assert!(matches!(outcome, ExecutionOutcome::BulkQuotaReached(_)));
+    let tokens = sqlx::query!("SELECT tokens FROM delivery_throttle")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .tokens;
+    assert_eq!(tokens, 1.0);
+}
+
+#[tokio::test]
+async fn confirmation_emails_are_not_held_back_by_the_daily_quota() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    // The daily quota is already used up
+    let throttle = DeliveryThrottle::new(1000.0, 1000, Some(0));
+
+    // Act
+    let outcome = execute_task(&app, &throttle).await;
+
+    // Assert
+    assert!(matches!(outcome, ExecutionOutcome::TaskCompleted));
+    assert_eq!(queue_length(&app).await, 0);
+    // Mock verifies on Drop that the confirmation email went out
+}
+
+#[tokio::test]
+async fn the_token_bucket_spaces_out_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    create_confirmed_subscriber(&app, "octavia_butler@gmail.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    publish_issue(&app).await;
+    // A single token, refilled every 100 seconds
+    let throttle = DeliveryThrottle::new(0.01, 1, None);
+    sqlx::query!("UPDATE delivery_throttle SET tokens = 1, refilled_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let first = execute_task(&app, &throttle).await;
+    let second = execute_task(&app, &throttle).await;
+
+    // Assert
+    assert!(matches!(first, ExecutionOutcome::TaskCompleted));
+    assert!(matches!(second, ExecutionOutcome::Throttled(wait) if wait > Duration::from_secs(90)));
+    assert_eq!(queue_length(&app).await, 1);
+}
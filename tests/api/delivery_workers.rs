@@ -0,0 +1,165 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod::shutdown::Shutdown;
+
+async fn delivered(app: &TestApp) -> i64 {
+    sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+async fn queued(app: &TestApp, kind: &str) -> i64 {
+    sqlx::query!(
+        "SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue WHERE kind = $1",
+        kind
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count
+}
+
+#[tokio::test]
+async fn idle_workers_are_woken_up_when_an_issue_is_published() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    create_confirmed_subscriber(&app, "octavia_butler@gmail.com").await;
+    create_confirmed_subscriber(&app, "nk_jemisin@gmail.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+    app.spawn_workers();
+    // Let the workers find the queue empty and go idle
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // Act
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    // Assert - well before the workers would poll the queue again
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while delivered(&app).await < 3 {
+        assert!(Instant::now() < deadline, "The workers were not woken up.");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    // Mock verifies on Drop that each subscriber got a single email
+}
+
+#[tokio::test]
+async fn workers_finish_their_current_task_on_shutdown() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    create_confirmed_subscriber(&app, "octavia_butler@gmail.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    let mut configuration = app.configuration.clone();
+    configuration.delivery.workers = 1;
+    // The email in flight must outlive the shutdown, not time out
+    configuration.email_client.timeout_milliseconds = 10_000;
+    let shutdown = Shutdown::new();
+    let workers = tokio::spawn(run_worker_until_stopped(configuration, shutdown.clone()));
+    // Let the worker start sending the first email
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // Act
+    shutdown.trigger();
+    tokio::time::timeout(Duration::from_secs(5), workers)
+        .await
+        .expect("The workers did not stop.")
+        .unwrap()
+        .unwrap();
+
+    // Assert
+    assert_eq!(delivered(&app).await, 1);
+    let queued = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(queued, 1);
+    // Mock verifies on Drop that a single email went out
+}
+
+#[tokio::test]
+async fn confirmation_emails_go_out_once_the_daily_quota_is_reached() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    // Today's quota is already used up
+    sqlx::query!(
+        "INSERT INTO delivery_daily_counts (day, sent) VALUES ((now() AT TIME ZONE 'utc')::date, 1)"
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let mut configuration = app.configuration.clone();
+    configuration.delivery.workers = 1;
+    configuration.delivery.daily_quota = Some(1);
+    let shutdown = Shutdown::new();
+    let workers = tokio::spawn(run_worker_until_stopped(configuration, shutdown.clone()));
+    // Let the worker run into the quota
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    // Act
+    app.post_subscriptions("name=octavia%20butler&email=octavia_butler%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while queued(&app, "transactional").await > 0 {
+        assert!(
+            Instant::now() < deadline,
+            "The confirmation email was held back."
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(queued(&app, "bulk").await, 1);
+    shutdown.trigger();
+    workers.await.unwrap().unwrap();
+    // Mock verifies on Drop that only the confirmation email went out
+}
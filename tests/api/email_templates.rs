@@ -0,0 +1,133 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_edit_email_templates() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_email_template("confirmation").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn confirmation_emails_use_the_edited_template_and_the_layout() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Edit the template
+    let response = app
+        .post_email_template(
+            "confirmation",
+            &serde_json::json!({
+                "html_body": "<p>Hey {{name}}, <a href=\"{{{confirmation_link}}}\">confirm</a></p>",
+                "text_body": "Hey {{name}}, confirm at {{confirmation_link}}",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/email-templates/confirmation");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_email_template_html("confirmation").await;
+    assert!(html_page.contains("<p><i>The template has been saved.</i></p>"));
+
+    // Act - Part 3 - Subscribe
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(html_body.contains("<p>Hey le guin, <a href=\""));
+    assert!(text_body.starts_with("Hey le guin, confirm at "));
+    // The shared footer
+    assert!(html_body.contains("The zero2prod newsletter"));
+    assert!(text_body.ends_with("The zero2prod newsletter\n"));
+    let confirmation_links = app.get_confirmation_links(email_request);
+    assert_eq!(confirmation_links.html, confirmation_links.plain_text);
+}
+
+#[tokio::test]
+async fn templates_with_unknown_placeholders_are_not_saved() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Try to save a broken template
+    let response = app
+        .post_email_template(
+            "confirmation",
+            &serde_json::json!({
+                "html_body": "<p>Hey {{nmae}}</p>",
+                "text_body": "Hey {{name}}",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/email-templates/confirmation");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_email_template_html("confirmation").await;
+
+    // Assert
+    assert!(html_page.contains("The template is not valid"));
+    let saved = sqlx::query!("SELECT name FROM email_templates")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn unsaved_edits_are_previewed_with_sample_data() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_preview_email_template(
+            "unsubscribe_receipt",
+            &serde_json::json!({
+                "html_body": "<p>Bye {{name}}</p>",
+                "text_body": "Bye {{name}}",
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Bye Ursula"));
+    let saved = sqlx::query!("SELECT name FROM email_templates")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn unknown_templates_are_a_404() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_email_template("not-a-template").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,212 @@
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::FeedDigestSettings;
+use zero2prod::feed_digest::try_run_digest;
+
+/// A local RSS feed, with one item per title.
+struct TestFeed {
+    path: std::path::PathBuf,
+}
+
+impl TestFeed {
+    fn new() -> Self {
+        Self {
+            path: std::env::temp_dir().join(format!("{}.xml", Uuid::new_v4())),
+        }
+    }
+
+    fn publish(&self, titles: &[&str]) {
+        let items: String = titles
+            .iter()
+            .map(|title| {
+                format!(
+                    "<item><guid>{title}</guid><title>{title}</title>\
+                    <link>https://blog.example.com/{title}</link>\
+                    <description>&lt;p&gt;All about {title}&lt;/p&gt;</description></item>",
+                )
+            })
+            .collect();
+        std::fs::write(
+            &self.path,
+            format!(
+                r#"<?xml version="1.0"?>
+<rss version="2.0"><channel><title>Blog</title><link>https://blog.example.com</link>
+<description>Blog</description>{items}</channel></rss>"#
+            ),
+        )
+        .unwrap();
+    }
+
+    fn settings(&self, auto_publish: bool) -> FeedDigestSettings {
+        FeedDigestSettings {
+            source: self.path.to_str().unwrap().to_owned(),
+            interval_seconds: 3600,
+            template: "templates/digest/digest.md.hbs".into(),
+            title: "Blog digest".into(),
+            list: "newsletter".into(),
+            auto_publish,
+        }
+    }
+}
+
+impl Drop for TestFeed {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[tokio::test]
+async fn the_items_found_on_the_first_run_are_not_collected() {
+    // Arrange
+    let app = spawn_app().await;
+    let feed = TestFeed::new();
+    feed.publish(&["hello-world"]);
+
+    // Act
+    let issue_id = try_run_digest(&app.db_pool, &feed.settings(false))
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(issue_id.is_none());
+    let n_issues = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn new_items_are_collected_into_a_draft() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    let feed = TestFeed::new();
+    feed.publish(&["hello-world"]);
+    try_run_digest(&app.db_pool, &feed.settings(false))
+        .await
+        .unwrap();
+    feed.publish(&["second-post", "hello-world"]);
+
+    // Act
+    let issue_id = try_run_digest(&app.db_pool, &feed.settings(false))
+        .await
+        .unwrap()
+        .expect("No digest was created.");
+
+    // Assert
+    let issue = sqlx::query!(
+        "SELECT status, title, html_content, text_content FROM newsletter_issues \
+        WHERE newsletter_issue_id = $1",
+        issue_id,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(issue.status, "draft");
+    assert!(issue.title.starts_with("Blog digest - "));
+    assert!(issue
+        .html_content
+        .contains(r#"<a href="https://blog.example.com/second-post" rel="noopener noreferrer">second-post</a>"#));
+    assert!(issue.text_content.contains("All about second-post"));
+    assert!(!issue.text_content.contains("hello-world"));
+    let n_tasks = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tasks, 0);
+    // Nothing new on the next run
+    assert!(try_run_digest(&app.db_pool, &feed.settings(false))
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn admins_can_publish_a_draft_digest() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    let feed = TestFeed::new();
+    feed.publish(&[]);
+    try_run_digest(&app.db_pool, &feed.settings(false))
+        .await
+        .unwrap();
+    feed.publish(&["hello-world"]);
+    let issue_id = try_run_digest(&app.db_pool, &feed.settings(false))
+        .await
+        .unwrap()
+        .unwrap();
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Review the drafts
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("Blog digest - "));
+
+    // Act - Part 2 - Publish
+    let response = app.post_publish_draft(issue_id).await;
+    assert_is_redirect_to(&response, "/admin/newsletters/drafts");
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("<p><i>The draft has been published"));
+    assert!(html_page.contains("<p>No drafts.</p>"));
+
+    // Act - Part 3 - Publish again
+    app.post_publish_draft(issue_id).await;
+    let html_page = app.get_drafts_html().await;
+    assert!(html_page.contains("<p><i>The draft has already been published.</i></p>"));
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the digest once
+}
+
+#[tokio::test]
+async fn digests_can_be_published_automatically() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    let feed = TestFeed::new();
+    feed.publish(&[]);
+    try_run_digest(&app.db_pool, &feed.settings(true))
+        .await
+        .unwrap();
+    feed.publish(&["hello-world"]);
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    try_run_digest(&app.db_pool, &feed.settings(true))
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["Subject"]
+        .as_str()
+        .unwrap()
+        .starts_with("Blog digest - "));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("/subscriptions/unsubscribe?subscription_token="));
+}
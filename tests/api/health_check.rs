@@ -1,4 +1,7 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
 
 #[tokio::test]
 async fn health_check_works() {
@@ -10,7 +13,7 @@ async fn health_check_works() {
 
     // Act
     let response = client
-        .get(&format!("{}/health_check", &app.address))
+        .get(format!("{}/health_check", &app.address))
         .send()
         .await
         .expect("Failed to execute request.");
@@ -19,3 +22,132 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
+
+async fn get_readiness(app: &TestApp) -> (u16, serde_json::Value) {
+    let response = reqwest::get(&format!("{}/health/ready", &app.address))
+        .await
+        .expect("Failed to execute request.");
+    let status = response.status().as_u16();
+    (status, response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn readiness_reports_on_every_component() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let (status, readiness) = get_readiness(&app).await;
+
+    // Assert
+    assert_eq!(status, 200);
+    assert_eq!(readiness["status"], "ready");
+    for component in ["database", "migrations", "redis"] {
+        assert_eq!(readiness["components"][component]["status"], "ok");
+        assert_eq!(readiness["components"][component]["required"], true);
+        assert!(readiness["components"][component]["latency_ms"].is_u64());
+    }
+    // Workers are optional, and there are none yet
+    assert_eq!(readiness["components"]["workers"]["status"], "missing");
+    assert_eq!(readiness["components"]["workers"]["required"], false);
+    // The email provider is only checked if asked to
+    assert!(readiness["components"]["email_provider"].is_null());
+}
+
+#[tokio::test]
+async fn readiness_reports_the_worker_heartbeat() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    app.spawn_workers();
+
+    // Assert
+    let mut workers = serde_json::Value::Null;
+    for _ in 0..50 {
+        workers = get_readiness(&app).await.1["components"]["workers"].clone();
+        if workers["status"] == "ok" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(workers["status"], "ok");
+    assert!(workers["detail"]
+        .as_str()
+        .unwrap()
+        .starts_with("last heartbeat"));
+}
+
+#[tokio::test]
+async fn an_unreachable_email_provider_makes_the_instance_unready() {
+    // Arrange
+    let app = spawn_app_with(|c| c.application.readiness.check_email_provider = true).await;
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let (status, readiness) = get_readiness(&app).await;
+
+    // Assert
+    assert_eq!(status, 503);
+    assert_eq!(readiness["status"], "not_ready");
+    assert_eq!(readiness["components"]["email_provider"]["status"], "error");
+    assert_eq!(readiness["components"]["database"]["status"], "ok");
+}
+
+#[tokio::test]
+async fn the_email_provider_is_not_checked_on_every_request() {
+    // Arrange
+    let app = spawn_app_with(|c| c.application.readiness.check_email_provider = true).await;
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let (first, _) = get_readiness(&app).await;
+    let (second, readiness) = get_readiness(&app).await;
+
+    // Assert
+    assert_eq!(first, 200);
+    assert_eq!(second, 200);
+    assert_eq!(readiness["components"]["email_provider"]["status"], "ok");
+    // Mock verifies on Drop that the provider was asked once
+}
+
+#[tokio::test]
+async fn slow_checks_time_out() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.application.readiness.check_email_provider = true;
+        c.application.readiness.timeout_milliseconds = 200;
+    })
+    .await;
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let (status, readiness) = get_readiness(&app).await;
+
+    // Assert
+    assert_eq!(status, 503);
+    assert_eq!(
+        readiness["components"]["email_provider"]["status"],
+        "timeout"
+    );
+    assert!(
+        readiness["components"]["email_provider"]["latency_ms"]
+            .as_u64()
+            .unwrap()
+            < 2000
+    );
+}
@@ -1,15 +1,29 @@
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
+use hmac::{Hmac, Mac};
 use once_cell::sync::Lazy;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+use opentelemetry_sdk::trace::TracerProvider;
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
-use wiremock::MockServer;
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::{get_configuration, DatabaseSettings, Settings};
+use zero2prod::delivery_throttle::DeliveryThrottle;
 use zero2prod::email_client::EmailClient;
-use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use zero2prod::issue_delivery_worker::{
+    run_worker_until_stopped, try_execute_task, ExecutionOutcome, QueueScheduler,
+};
+use zero2prod::metrics::AdminServer;
+use zero2prod::shutdown::Shutdown;
+use zero2prod::signed_link::{LinkPurpose, SignedLink};
 use zero2prod::startup::get_connection_pool;
 use zero2prod::startup::Application;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
+use zero2prod::tracking::Tracker;
 
 pub struct TestUser {
     pub user_id: Uuid,
@@ -59,19 +73,36 @@ impl TestUser {
     }
 }
 
+/// Collects the spans of every test: filter them by trace id.
+pub static SPAN_EXPORTER: Lazy<InMemorySpanExporter> = Lazy::new(InMemorySpanExporter::default);
+
 static TRACING: Lazy<()> = Lazy::new(|| {
     let default_filter_level = "info".to_string();
     let subscriber_name = "test".to_string();
+    let tracer = TracerProvider::builder()
+        .with_simple_exporter(SPAN_EXPORTER.clone())
+        .build()
+        .tracer("test");
     // We cannot assign the output of `get_subscriber` to a variable
     // based on the value `TEST_LOG` because the sink is part of the type
     // returned by `get_subscriber`, therefore they are not the
     // same type. We could work around it, but this is the most
     // straight-forward way of moving forward
     if std::env::var("TEST_LOG").is_ok() {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
+        let subscriber = get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::stdout,
+            Some(tracer),
+        );
         init_subscriber(subscriber);
     } else {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink);
+        let subscriber = get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::sink,
+            Some(tracer),
+        );
         init_subscriber(subscriber);
     };
 });
@@ -88,15 +119,23 @@ pub struct TestApp {
     pub db_pool: PgPool,
     pub email_server: MockServer,
     pub port: u16,
+    pub admin_address: String,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
     pub email_client: EmailClient,
+    pub throttle: DeliveryThrottle,
+    pub scheduler: QueueScheduler,
+    pub webhook_secret: Secret<String>,
+    pub tracker: Tracker,
+    pub base_url: String,
+    pub hmac_secret: Secret<String>,
+    pub configuration: Settings,
 }
 
 impl TestApp {
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -104,6 +143,73 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_unsubscribe<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/subscriptions/unsubscribe", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_subscriber_data_request<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/subscriptions/data", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_subscriber_data_erasure<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/subscriptions/data/erase", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Deliver a webhook notification, signed as the email provider would.
+    pub async fn post_email_webhook(
+        &self,
+        provider: &str,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        let body = serde_json::to_vec(body).unwrap();
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(self.webhook_secret.expose_secret().as_bytes()).unwrap();
+        mac.update(&body);
+        let signature = hex::encode(mac.finalize().into_bytes());
+        self.post_raw_email_webhook(provider, body, &signature)
+            .await
+    }
+
+    pub async fn post_raw_email_webhook(
+        &self,
+        provider: &str,
+        body: Vec<u8>,
+        signature: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/webhooks/email/{}", &self.address, provider))
+            .header("Content-Type", "application/json")
+            .header("X-Webhook-Signature", signature)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     /// Extract the confirmation links embedded in
     /// the request to the email API
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
@@ -122,14 +228,14 @@ impl TestApp {
             confirmation_link.set_port(Some(self.port)).unwrap();
             confirmation_link
         };
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
         ConfirmationLinks { html, plain_text }
     }
 
     pub async fn get_publish_newsletter(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -144,19 +250,282 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/newsletters", &self.address))
+            .post(format!("{}/admin/newsletters", &self.address))
+            // Lists are repeated keys, which `form` cannot serialize
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(serde_html_form::to_string(body).unwrap())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_preview_newsletter<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/newsletters/preview", &self.address))
+            // Lists are repeated keys, which `form` cannot serialize
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(serde_html_form::to_string(body).unwrap())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_drafts_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters/drafts", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_publish_draft(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/drafts/{}/publish",
+                &self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_newsletter_issue_html(&self, issue_id: Uuid) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters/{}", &self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_delivery_progress(&self, issue_id: Uuid) -> serde_json::Value {
+        self.api_client
+            .get(format!(
+                "{}/admin/newsletters/{}/progress",
+                &self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .json()
+            .await
+            .unwrap()
+    }
+
+    /// `action` is one of `pause`, `resume` or `cancel`.
+    pub async fn post_delivery_control(&self, issue_id: Uuid, action: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/{}",
+                &self.address, issue_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_email_template(&self, name: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/email-templates/{}", &self.address, name))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_email_template_html(&self, name: &str) -> String {
+        self.get_email_template(name).await.text().await.unwrap()
+    }
+
+    pub async fn post_email_template<Body>(&self, name: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/email-templates/{}", &self.address, name))
             .form(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_preview_email_template<Body>(
+        &self,
+        name: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/email-templates/{}/preview",
+                &self.address, name
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_lists_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/lists", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_list<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/lists", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_subscribers_html(&self, segment: &str) -> String {
+        self.api_client
+            .get(format!("{}/admin/subscribers", &self.address))
+            .query(&[("segment", segment)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_add_subscriber_tag<Body>(
+        &self,
+        subscriber_id: uuid::Uuid,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/tags",
+                &self.address, subscriber_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_profile_fields_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/profile-fields", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_profile_field<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/profile-fields", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// A freshly signed link to the preference centre of a subscriber.
+    pub fn preferences_link(&self, subscriber_id: Uuid) -> SignedLink {
+        SignedLink::new(
+            LinkPurpose::Preferences,
+            subscriber_id,
+            chrono::Duration::hours(1),
+            &self.hmac_secret,
+        )
+    }
+
+    pub async fn get_preferences(&self, link: &SignedLink) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/subscriptions/preferences?{}",
+                &self.address,
+                link.query_string()
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_preferences_html(&self, link: &SignedLink) -> String {
+        self.get_preferences(link).await.text().await.unwrap()
+    }
+
+    pub async fn post_preferences<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/subscriptions/preferences", &self.address))
+            // Lists are repeated keys, which `form` cannot serialize
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(serde_html_form::to_string(body).unwrap())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_issue_archive_html(&self, page: u32) -> String {
+        self.api_client
+            .get(format!("{}/issues", &self.address))
+            .query(&[("page", page)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_issue(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/issues/{}", &self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_issue_feed(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/issues/feed.xml", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             // This reqwest method makes sure that the body
             // is URL-encoded and the `Content-Type` header is
             // set accordingly
@@ -170,7 +539,7 @@ impl TestApp {
     // therefore we do not expose the underlying reqwest::Response
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -181,7 +550,7 @@ impl TestApp {
 
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -192,9 +561,21 @@ impl TestApp {
         self.get_admin_dashboard().await.text().await.unwrap()
     }
 
+    pub async fn get_metrics(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/metrics", &self.admin_address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_metrics_text(&self) -> String {
+        self.get_metrics().await.text().await.unwrap()
+    }
+
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -205,7 +586,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/password", &self.address))
+            .post(format!("{}/admin/password", &self.address))
             .form(body)
             .send()
             .await
@@ -218,28 +599,94 @@ impl TestApp {
 
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .post(format!("{}/admin/logout", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    /// Run the delivery workers in the background, as the application does.
+    pub fn spawn_workers(&self) {
+        tokio::spawn(run_worker_until_stopped(
+            self.configuration.clone(),
+            Shutdown::new(),
+        ));
+    }
+
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
+            match try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.throttle,
+                &self.scheduler,
+                &self.tracker,
+                &self.base_url,
+                &self.hmac_secret,
+            )
+            .await
+            .unwrap()
             {
-                break;
+                ExecutionOutcome::EmptyQueue => break,
+                ExecutionOutcome::Throttled(wait) => tokio::time::sleep(wait).await,
+                ExecutionOutcome::BulkQuotaReached(wait) => self.scheduler.hold_bulk(wait),
+                ExecutionOutcome::TaskCompleted => {}
             }
         }
     }
 }
 
+/// Subscribe `email` and follow the link of the confirmation email.
+/// Returns the id of the subscriber.
+pub async fn create_confirmed_subscriber(app: &TestApp, email: &str) -> Uuid {
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": "le guin",
+        "email": email,
+    }))
+    .unwrap();
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create confirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_link = app.get_confirmation_links(email_request).html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the subscriber.")
+        .id
+}
+
 /// Spin up an instance of our application
 /// an returns its address (i.e. http://localhost:XXXX)
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spin up an instance of our application, with the configuration
+/// tweaked by `configure`
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     // The first time `initialize` is invoked the code
     // in `TRACING` is executed.
     // All other invocations will instead skip execution.
@@ -254,7 +701,9 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = Uuid::new_v4().to_string();
         // Use a random OS port
         c.application.port = 0;
+        c.application.admin_port = 0;
         c.email_client.base_url = email_server.uri();
+        configure(&mut c);
         c
     };
     // Create and migrate the database
@@ -265,7 +714,11 @@ pub async fn spawn_app() -> TestApp {
         .await
         .expect("Failed to build application.");
     let port = application.port();
-    let _ = tokio::spawn(application.run_until_stopped());
+    tokio::spawn(application.run_until_stopped(Shutdown::new()));
+    let admin_server =
+        AdminServer::build(&configuration).expect("Failed to build the admin server.");
+    let admin_port = admin_server.port();
+    tokio::spawn(admin_server.run_until_stopped(Shutdown::new()));
 
     // Create the `reqwest` client
     let api_client = reqwest::Client::builder()
@@ -277,11 +730,22 @@ pub async fn spawn_app() -> TestApp {
     let test_app = TestApp {
         address: format!("http://localhost:{}", port),
         port,
+        admin_address: format!("http://localhost:{}", admin_port),
         db_pool: get_connection_pool(&configuration.database),
         email_server,
         test_user: TestUser::generate(),
         api_client,
-        email_client: configuration.email_client.client(),
+        email_client: configuration.email_client.clone().client(),
+        throttle: configuration.delivery.throttle(),
+        scheduler: configuration.delivery.scheduler(),
+        webhook_secret: configuration.email_client.webhook_secret.clone(),
+        tracker: Tracker::new(
+            configuration.application.base_url.clone(),
+            configuration.application.hmac_secret.clone(),
+        ),
+        base_url: configuration.application.base_url.clone(),
+        hmac_secret: configuration.application.hmac_secret.clone(),
+        configuration,
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
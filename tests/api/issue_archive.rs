@@ -0,0 +1,194 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Publish an issue as the logged-in admin, returning its id.
+async fn publish_issue(app: &TestApp, title: &str, is_public: bool) -> Uuid {
+    let mut newsletter_request_body = serde_json::json!({
+        "title": title,
+        "text_content": "Dear {{name}}, here is the news.",
+        "html_content": "<p>Dear {{name}}, here is the news.</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    if is_public {
+        newsletter_request_body["is_public"] = "true".into();
+    }
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE title = $1",
+        title
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch the issue.")
+    .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn public_issues_are_listed_and_viewable_by_anyone() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_issue(&app, "Spring update", true).await;
+    app.post_logout().await;
+
+    // Act
+    let archive = app.get_issue_archive_html(1).await;
+    let response = app.get_issue(issue_id).await;
+
+    // Assert
+    assert!(archive.contains(&format!(
+        r#"<a href="/issues/{}">Spring update</a>"#,
+        issue_id
+    )));
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>Spring update</h1>"));
+    assert!(html_page.contains("<p>Dear reader, here is the news.</p>"));
+}
+
+#[tokio::test]
+async fn private_issues_are_neither_listed_nor_viewable() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_issue(&app, "Members only", false).await;
+
+    // Act
+    let archive = app.get_issue_archive_html(1).await;
+    let response = app.get_issue(issue_id).await;
+
+    // Assert
+    assert!(!archive.contains("Members only"));
+    assert!(archive.contains("<p>No issues here.</p>"));
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_archive_is_paginated() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    for i in 1..=21 {
+        publish_issue(&app, &format!("Issue #{}", i), true).await;
+    }
+
+    // Act
+    let first_page = app.get_issue_archive_html(1).await;
+    let second_page = app.get_issue_archive_html(2).await;
+
+    // Assert
+    assert!(first_page.contains(">Issue #21</a>"));
+    assert!(first_page.contains(">Issue #2</a>"));
+    assert!(!first_page.contains(">Issue #1</a>"));
+    assert!(first_page.contains(r#"<a href="/issues?page=2">Older issues</a>"#));
+    assert!(second_page.contains(">Issue #1</a>"));
+    assert!(second_page.contains(r#"<a href="/issues?page=1">Newer issues</a>"#));
+    assert!(!second_page.contains("Older issues"));
+}
+
+#[tokio::test]
+async fn the_feed_lists_public_issues_only() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let public_issue = publish_issue(&app, "Spring update", true).await;
+    publish_issue(&app, "Members only", false).await;
+
+    // Act
+    let response = app.get_issue_feed().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/atom+xml; charset=utf-8"
+    );
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains(&format!("<id>urn:uuid:{}</id>", public_issue)));
+    assert!(feed.contains("<title>Spring update</title>"));
+    assert!(feed.contains("&lt;p&gt;Dear reader, here is the news.&lt;/p&gt;"));
+    assert!(!feed.contains("Members only"));
+}
+
+#[tokio::test]
+async fn recipients_can_view_private_issues_in_their_browser() {
+    // Arrange
+    let app = spawn_app().await;
+    let mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_links(email_request).html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    drop(mock_guard);
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.test_user.login(&app).await;
+    publish_issue(&app, "Members only", false).await;
+    app.dispatch_all_pending_emails().await;
+    app.post_logout().await;
+
+    // Act
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let text_body = body["TextBody"].as_str().unwrap();
+    let raw_link = text_body
+        .split("View this issue in your browser: ")
+        .nth(1)
+        .unwrap()
+        .trim();
+    let mut view_link = reqwest::Url::parse(raw_link).unwrap();
+    view_link.set_port(Some(app.port)).unwrap();
+    let response = reqwest::get(view_link.clone()).await.unwrap();
+
+    // Assert
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("View this issue in your browser"));
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("<h1>Members only</h1>"));
+    // The signature is tied to the recipient
+    let tampered: Vec<_> = view_link
+        .query_pairs()
+        .map(|(k, v)| {
+            if k == "recipient" {
+                (k.into_owned(), Uuid::new_v4().to_string())
+            } else {
+                (k.into_owned(), v.into_owned())
+            }
+        })
+        .collect();
+    view_link.query_pairs_mut().clear().extend_pairs(tampered);
+    let response = reqwest::get(view_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
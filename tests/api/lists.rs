@@ -0,0 +1,243 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_list(app: &TestApp, slug: &str, name: &str) {
+    let response = app
+        .post_create_list(&serde_json::json!({ "slug": slug, "name": name }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/lists");
+}
+
+/// Subscribe to a list and follow the confirmation link,
+/// returning the subscription token of the list.
+async fn subscribe_and_confirm(app: &TestApp, email: &str, list: &str) -> String {
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": "le guin",
+        "email": email,
+        "list": list,
+    }))
+    .unwrap();
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_link = app.get_confirmation_links(email_request).html;
+    reqwest::get(confirmation_link.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    confirmation_link
+        .query_pairs()
+        .find(|(k, _)| k == "subscription_token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+async fn list_status(app: &TestApp, email: &str, list: &str) -> String {
+    sqlx::query!(
+        r#"
+        SELECT ls.status
+        FROM list_subscriptions ls
+        JOIN lists l USING (list_id)
+        JOIN subscriptions s ON s.id = ls.subscriber_id
+        WHERE s.email = $1 AND l.slug = $2
+        "#,
+        email,
+        list,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch the list subscription.")
+    .status
+}
+
+#[tokio::test]
+async fn admins_can_create_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    create_list(&app, "rust-weekly", "Rust Weekly").await;
+
+    // Assert
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("<p><i>The Rust Weekly list has been created.</i></p>"));
+    assert!(html_page.contains("<td>rust-weekly</td>"));
+}
+
+#[tokio::test]
+async fn lists_with_an_invalid_slug_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    create_list(&app, "Rust Weekly", "Rust Weekly").await;
+
+    // Assert
+    let html_page = app.get_lists_html().await;
+    assert!(html_page.contains("Rust Weekly is not a valid list slug."));
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&list=not-a-list".into(),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_same_address_can_join_several_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_list(&app, "rust-weekly", "Rust Weekly").await;
+
+    // Act
+    subscribe_and_confirm(&app, "ursula_le_guin@gmail.com", "newsletter").await;
+    subscribe_and_confirm(&app, "ursula_le_guin@gmail.com", "rust-weekly").await;
+
+    // Assert
+    let n_subscribers = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscribers, 1);
+    for list in ["newsletter", "rust-weekly"] {
+        assert_eq!(
+            list_status(&app, "ursula_le_guin@gmail.com", list).await,
+            "confirmed"
+        );
+    }
+}
+
+#[tokio::test]
+async fn unsubscribing_only_leaves_the_list_of_the_token() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_list(&app, "rust-weekly", "Rust Weekly").await;
+    subscribe_and_confirm(&app, "ursula_le_guin@gmail.com", "newsletter").await;
+    let token = subscribe_and_confirm(&app, "ursula_le_guin@gmail.com", "rust-weekly").await;
+
+    // Act
+    let response = app
+        .post_unsubscribe(&serde_json::json!({ "subscription_token": token }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        list_status(&app, "ursula_le_guin@gmail.com", "rust-weekly").await,
+        "unsubscribed"
+    );
+    assert_eq!(
+        list_status(&app, "ursula_le_guin@gmail.com", "newsletter").await,
+        "confirmed"
+    );
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn issues_only_go_to_the_lists_they_are_published_to() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_list(&app, "rust-weekly", "Rust Weekly").await;
+    create_list(&app, "go-weekly", "Go Weekly").await;
+    subscribe_and_confirm(&app, "ursula_le_guin@gmail.com", "newsletter").await;
+    subscribe_and_confirm(&app, "octavia_butler@gmail.com", "rust-weekly").await;
+    subscribe_and_confirm(&app, "octavia_butler@gmail.com", "go-weekly").await;
+    subscribe_and_confirm(&app, "nk_jemisin@gmail.com", "go-weekly").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "lists": ["rust-weekly", "go-weekly"],
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let recipients = sqlx::query!("SELECT subscriber_email FROM issue_delivery_log")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let mut recipients: Vec<_> = recipients.into_iter().map(|r| r.subscriber_email).collect();
+    recipients.sort();
+    assert_eq!(
+        recipients,
+        vec!["nk_jemisin@gmail.com", "octavia_butler@gmail.com"]
+    );
+    // Mock verifies on Drop that each subscriber got a single copy
+}
+
+#[tokio::test]
+async fn issues_for_an_unknown_list_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Submit newsletter form
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "lists": ["not-a-list"],
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_publish_newsletter_html().await;
+
+    // Assert
+    assert!(html_page.contains("<p><i>There is no list called not-a-list.</i></p>"));
+}
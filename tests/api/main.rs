@@ -1,8 +1,26 @@
 mod admin_dashboard;
 mod change_password;
+mod delivery_controls;
+mod delivery_priorities;
+mod delivery_recipients;
+mod delivery_recovery;
+mod delivery_throttle;
+mod delivery_workers;
+mod email_templates;
+mod feed_digest;
 mod health_check;
 mod helpers;
+mod issue_archive;
+mod lists;
 mod login;
+mod metrics;
 mod newsletter;
+mod preferences;
+mod segments;
+mod subscriber_data;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod telemetry;
+mod tracking;
+mod webhooks;
@@ -0,0 +1,97 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app};
+use uuid::Uuid;
+
+// Metrics are shared by every test of the process: only their
+// presence can be asserted, not their values
+
+#[tokio::test]
+async fn metrics_are_served_on_the_admin_port_only() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let admin_response = app.get_metrics().await;
+    let public_response = app
+        .api_client
+        .get(format!("{}/metrics", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(admin_response.status().as_u16(), 200);
+    assert!(admin_response.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+    assert_eq!(public_response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn requests_are_counted_by_route() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    app.api_client
+        .get(format!("{}/issues/{}", &app.address, Uuid::new_v4()))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    let metrics = app.get_metrics_text().await;
+    assert!(metrics
+        .contains(r#"http_requests_total{method="GET",route="/issues/{issue_id}",status="404"}"#));
+    assert!(metrics.contains(
+        r#"http_request_duration_seconds_count{method="GET",route="/issues/{issue_id}"}"#
+    ));
+}
+
+#[tokio::test]
+async fn subscription_and_login_outcomes_are_counted() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    app.post_subscriptions("name=le%20guin&email=definitely-not-an-email".into())
+        .await;
+    app.post_login(&serde_json::json!({
+        "username": "random-username",
+        "password": "random-password"
+    }))
+    .await;
+
+    // Assert
+    let metrics = app.get_metrics_text().await;
+    assert!(metrics.contains(r#"subscriptions_total{outcome="subscribed"}"#));
+    assert!(metrics.contains(r#"subscriptions_total{outcome="invalid"}"#));
+    assert!(metrics.contains(r#"subscription_confirmations_total{outcome="confirmed"}"#));
+    assert!(metrics.contains(r#"logins_total{outcome="failure"}"#));
+    assert!(metrics.contains(r#"emails_total{outcome="sent",provider="postmark"}"#));
+}
+
+#[tokio::test]
+async fn the_delivery_queue_and_the_pool_are_sampled_on_scrape() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    app.test_user.login(&app).await;
+
+    // Act
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    // Assert
+    let metrics = app.get_metrics_text().await;
+    assert!(metrics.contains("delivery_queue_depth{kind=\"bulk\"} 1\n"));
+    assert!(metrics.contains("delivery_queue_depth{kind=\"transactional\"} 0\n"));
+    assert!(metrics.contains("delivery_queue_oldest_task_age_seconds{kind=\"bulk\"}"));
+    assert!(metrics.contains(r#"db_pool_connections{pool="api",state="idle"}"#));
+}
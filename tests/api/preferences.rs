@@ -0,0 +1,414 @@
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::signed_link::SignedLink;
+use zero2prod::subscriber_digest::try_send_due_digests;
+
+async fn create_profile_fields(app: &TestApp) {
+    app.test_user.login(app).await;
+    for field in [
+        serde_json::json!({ "key": "company", "label": "Company", "kind": "text" }),
+        serde_json::json!({
+            "key": "company_size",
+            "label": "Company size",
+            "kind": "enum",
+            "options": "small, large",
+        }),
+        serde_json::json!({ "key": "beta", "label": "Beta tester", "kind": "boolean" }),
+    ] {
+        let response = app.post_create_profile_field(&field).await;
+        assert_is_redirect_to(&response, "/admin/profile-fields");
+    }
+}
+
+/// The preference form, as submitted by the subscriber.
+fn preferences(link: &SignedLink, extra: serde_json::Value) -> serde_json::Value {
+    let mut form = serde_json::json!({
+        "subscriber_id": link.subscriber_id,
+        "expires_at": link.expires_at,
+        "signature": link.signature,
+        "digest_frequency": "immediate",
+        "lists": ["newsletter"],
+    });
+    for (key, value) in extra.as_object().unwrap() {
+        form[key] = value.clone();
+    }
+    form
+}
+
+async fn profile(app: &TestApp, subscriber_id: Uuid) -> Vec<(String, String)> {
+    sqlx::query!(
+        r#"
+        SELECT field_key, value FROM subscriber_profile_values
+        WHERE subscriber_id = $1
+        ORDER BY field_key
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.field_key, r.value))
+    .collect()
+}
+
+async fn list_status(app: &TestApp, subscriber_id: Uuid, list: &str) -> String {
+    sqlx::query!(
+        r#"
+        SELECT ls.status
+        FROM list_subscriptions ls
+        JOIN lists l USING (list_id)
+        WHERE ls.subscriber_id = $1 AND l.slug = $2
+        "#,
+        subscriber_id,
+        list,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch the list subscription.")
+    .status
+}
+
+#[tokio::test]
+async fn admins_can_create_profile_fields() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    create_profile_fields(&app).await;
+
+    // Assert
+    let html_page = app.get_profile_fields_html().await;
+    assert!(html_page.contains("<p><i>The Beta tester field has been created.</i></p>"));
+    assert!(html_page.contains(
+        "<tr><td>company_size</td><td>Company size</td><td>enum</td><td>small, large</td></tr>"
+    ));
+}
+
+#[tokio::test]
+async fn enum_fields_without_options_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_create_profile_field(&serde_json::json!({
+            "key": "company_size",
+            "label": "Company size",
+            "kind": "enum",
+            "options": " , ",
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/profile-fields");
+    let html_page = app.get_profile_fields_html().await;
+    assert!(html_page.contains("<p><i>Enum fields need at least one option.</i></p>"));
+    assert!(!html_page.contains("<td>company_size</td>"));
+}
+
+#[tokio::test]
+async fn a_tampered_preferences_link_is_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    let mut link = app.preferences_link(subscriber_id);
+    link.subscriber_id = Uuid::new_v4();
+
+    // Act
+    let response = app.get_preferences(&link).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_preference_centre_shows_the_profile_fields() {
+    // Arrange
+    let app = spawn_app().await;
+    create_profile_fields(&app).await;
+    let subscriber_id = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+
+    // Act
+    let html_page = app
+        .get_preferences_html(&app.preferences_link(subscriber_id))
+        .await;
+
+    // Assert
+    assert!(html_page.contains(r#"<input type="text" name="profile.company" value="">"#));
+    assert!(html_page.contains(r#"<option value="large">large</option>"#));
+    assert!(html_page.contains(r#"<input type="checkbox" name="profile.beta" value="true">"#));
+    assert!(
+        html_page.contains(r#"<input type="checkbox" name="lists" value="newsletter" checked>"#)
+    );
+}
+
+#[tokio::test]
+async fn subscribers_can_edit_their_profile() {
+    // Arrange
+    let app = spawn_app().await;
+    create_profile_fields(&app).await;
+    let subscriber_id = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    let link = app.preferences_link(subscriber_id);
+
+    // Act - Part 1 - Fill in the profile
+    let response = app
+        .post_preferences(&preferences(
+            &link,
+            serde_json::json!({
+                "profile.company": " Acme ",
+                "profile.company_size": "large",
+                "profile.beta": "true",
+            }),
+        ))
+        .await;
+    let location = format!("/subscriptions/preferences?{}", link.query_string());
+    assert_is_redirect_to(&response, &location);
+
+    // Assert - Part 1
+    let html_page = app.get_preferences_html(&link).await;
+    assert!(html_page.contains("<p><i>Your preferences have been saved.</i></p>"));
+    assert!(html_page.contains(r#"name="profile.company" value="Acme""#));
+    assert_eq!(
+        profile(&app, subscriber_id).await,
+        vec![
+            ("beta".to_string(), "true".to_string()),
+            ("company".to_string(), "Acme".to_string()),
+            ("company_size".to_string(), "large".to_string()),
+        ]
+    );
+
+    // Act - Part 2 - Clear it
+    app.post_preferences(&preferences(&link, serde_json::json!({})))
+        .await;
+
+    // Assert - Part 2
+    assert_eq!(
+        profile(&app, subscriber_id).await,
+        vec![("beta".to_string(), "false".to_string())]
+    );
+}
+
+#[tokio::test]
+async fn invalid_profile_values_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_profile_fields(&app).await;
+    let subscriber_id = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    let link = app.preferences_link(subscriber_id);
+
+    // Act
+    app.post_preferences(&preferences(
+        &link,
+        serde_json::json!({
+            "profile.company": "Acme",
+            "profile.company_size": "huge",
+        }),
+    ))
+    .await;
+
+    // Assert
+    let html_page = app.get_preferences_html(&link).await;
+    assert!(html_page.contains("<p><i>huge is not a valid choice for Company size.</i></p>"));
+    assert!(profile(&app, subscriber_id).await.is_empty());
+}
+
+#[tokio::test]
+async fn subscribers_can_switch_lists_and_pick_a_digest_frequency() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_create_list(&serde_json::json!({ "slug": "rust-weekly", "name": "Rust Weekly" }))
+        .await;
+    let subscriber_id = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    let link = app.preferences_link(subscriber_id);
+
+    // Act
+    app.post_preferences(&preferences(
+        &link,
+        serde_json::json!({ "lists": ["rust-weekly"], "digest_frequency": "weekly" }),
+    ))
+    .await;
+
+    // Assert
+    assert_eq!(
+        list_status(&app, subscriber_id, "newsletter").await,
+        "unsubscribed"
+    );
+    assert_eq!(
+        list_status(&app, subscriber_id, "rust-weekly").await,
+        "confirmed"
+    );
+    let saved = sqlx::query!("SELECT status, digest_frequency FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+    assert_eq!(saved.digest_frequency, "weekly");
+    // Issues to the new list carry an unsubscribe link
+    let n_tokens = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM subscription_tokens t
+        JOIN lists l USING (list_id)
+        WHERE l.slug = 'rust-weekly'
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_tokens, 1);
+}
+
+#[tokio::test]
+async fn subscribers_who_picked_a_digest_get_the_issues_in_a_single_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    create_confirmed_subscriber(&app, "octavia_butler@gmail.com").await;
+    let link = app.preferences_link(subscriber_id);
+    app.post_preferences(&preferences(
+        &link,
+        serde_json::json!({ "digest_frequency": "daily" }),
+    ))
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+    app.test_user.login(&app).await;
+    for title in ["First issue", "Second issue"] {
+        app.post_publish_newsletter(&serde_json::json!({
+            "title": title,
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    }
+    let templates = app.configuration.email_templates.templates();
+    let send_due_digests = || {
+        try_send_due_digests(
+            &app.db_pool,
+            &templates,
+            &app.tracker,
+            &app.base_url,
+            &app.hmac_secret,
+        )
+    };
+
+    // Act - Part 1 - The issues go out
+    app.dispatch_all_pending_emails().await;
+    let digests = send_due_digests().await.unwrap();
+
+    // Assert - Part 1 - Only to the subscriber who wants every issue
+    let sent = app.email_server.received_requests().await.unwrap();
+    let recipients: Vec<_> = sent
+        .iter()
+        .skip(2)
+        .map(|r| serde_json::from_slice::<serde_json::Value>(&r.body).unwrap()["To"].clone())
+        .collect();
+    assert_eq!(
+        recipients,
+        vec!["octavia_butler@gmail.com", "octavia_butler@gmail.com"]
+    );
+    assert_eq!(digests, 0);
+
+    // Act - Part 2 - A day later
+    sqlx::query!("UPDATE digest_queue SET held_at = held_at - interval '1 day'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let digests = send_due_digests().await.unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 2
+    assert_eq!(digests, 1);
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula_le_guin@gmail.com");
+    assert_eq!(body["Subject"], "Your newsletter digest");
+    let text = body["TextBody"].as_str().unwrap();
+    assert!(text.find("First issue").unwrap() < text.find("Second issue").unwrap());
+    let held = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM digest_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(held, 0);
+    // Mock verifies on Drop that each issue went out once, and the digest
+}
+
+#[tokio::test]
+async fn leaving_every_list_unsubscribes_the_address() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    let link = app.preferences_link(subscriber_id);
+
+    // Act
+    app.post_preferences(&preferences(&link, serde_json::json!({ "lists": [] })))
+        .await;
+
+    // Assert
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn issues_can_link_to_the_preference_centre() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Manage your preferences: {{preferences_url}}",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let links: Vec<_> = linkify::LinkFinder::new()
+        .links(body["TextBody"].as_str().unwrap())
+        .filter(|l| *l.kind() == linkify::LinkKind::Url)
+        .collect();
+    let mut preferences_link = reqwest::Url::parse(links[0].as_str()).unwrap();
+    assert_eq!(preferences_link.path(), "/subscriptions/preferences");
+    preferences_link.set_port(Some(app.port)).unwrap();
+    let response = reqwest::get(preferences_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,158 @@
+use crate::helpers::{assert_is_redirect_to, create_confirmed_subscriber, spawn_app, TestApp};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn tag(app: &TestApp, subscriber_id: Uuid, tag: &str) {
+    let response = app
+        .post_add_subscriber_tag(subscriber_id, &serde_json::json!({ "tag": tag }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+}
+
+fn issue(segment: &str) -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "segment": segment,
+        "idempotency_key": Uuid::new_v4().to_string(),
+    })
+}
+
+#[tokio::test]
+async fn admins_can_tag_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    app.test_user.login(&app).await;
+
+    // Act
+    tag(&app, subscriber_id, "Beta").await;
+
+    // Assert
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("<p><i>The subscriber has been tagged beta.</i></p>"));
+    assert!(html_page.contains(r#"name="tag" value="beta""#));
+}
+
+#[tokio::test]
+async fn invalid_tags_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    app.test_user.login(&app).await;
+
+    // Act
+    tag(&app, subscriber_id, "paid user").await;
+
+    // Assert
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("paid user is not a valid tag."));
+    let n_tags = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM subscriber_tags")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tags, 0);
+}
+
+#[tokio::test]
+async fn the_subscribers_page_can_be_filtered_by_segment() {
+    // Arrange
+    let app = spawn_app().await;
+    let beta_tester = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    create_confirmed_subscriber(&app, "octavia_butler@gmail.com").await;
+    app.test_user.login(&app).await;
+    tag(&app, beta_tester, "beta").await;
+
+    // Act
+    let html_page = app.get_subscribers_html("tag:beta").await;
+
+    // Assert
+    assert!(html_page.contains("ursula_le_guin@gmail.com"));
+    assert!(!html_page.contains("octavia_butler@gmail.com"));
+}
+
+#[tokio::test]
+async fn issues_only_go_to_the_subscribers_matching_the_segment() {
+    // Arrange
+    let app = spawn_app().await;
+    let beta_tester = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    let paying_beta_tester = create_confirmed_subscriber(&app, "octavia_butler@gmail.com").await;
+    create_confirmed_subscriber(&app, "nk_jemisin@gmail.com").await;
+    app.test_user.login(&app).await;
+    tag(&app, beta_tester, "beta").await;
+    tag(&app, paying_beta_tester, "beta").await;
+    tag(&app, paying_beta_tester, "paid").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&issue("tag:beta and not tag:paid"))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[3];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula_le_guin@gmail.com");
+    let saved = sqlx::query!("SELECT segment FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.segment.as_deref(), Some("tag:beta and not tag:paid"));
+    // Mock verifies on Drop that we have sent the newsletter email
+}
+
+#[tokio::test]
+async fn issues_with_an_invalid_segment_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_publish_newsletter(&issue("tag:beta and")).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains("<p><i>The segment ends unexpectedly.</i></p>"));
+}
+
+#[tokio::test]
+async fn the_preview_shows_the_size_of_the_audience() {
+    // Arrange
+    let app = spawn_app().await;
+    let beta_tester = create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    create_confirmed_subscriber(&app, "octavia_butler@gmail.com").await;
+    app.test_user.login(&app).await;
+    tag(&app, beta_tester, "beta").await;
+
+    // Act
+    let everybody = app.post_preview_newsletter(&issue("")).await;
+    let beta_testers = app.post_preview_newsletter(&issue("tag:beta")).await;
+
+    // Assert
+    let everybody = everybody.text().await.unwrap();
+    assert!(everybody.contains("<p>Audience: 2 subscribers.</p>"));
+    let beta_testers = beta_testers.text().await.unwrap();
+    assert!(beta_testers.contains("<p>Audience: 1 subscribers.</p>"));
+    assert!(beta_testers.contains("matching <code>tag:beta</code>"));
+}
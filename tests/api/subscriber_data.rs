@@ -0,0 +1,196 @@
+use crate::helpers::{spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Subscribe, then ask for a link to manage the subscriber data.
+/// Returns the link received by email.
+async fn request_data_management_link(app: &TestApp) -> reqwest::Url {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Addresses are matched regardless of their case
+    let response = app
+        .post_subscriber_data_request(&serde_json::json!({
+            "email": "Ursula_Le_Guin@Gmail.com"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request).html
+}
+
+#[tokio::test]
+async fn requesting_data_for_an_unknown_address_does_not_send_an_email() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriber_data_request(&serde_json::json!({
+            "email": "nobody@example.com"
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_emailed_link_allows_downloading_the_subscriber_data() {
+    // Arrange
+    let app = spawn_app().await;
+    let manage_link = request_data_management_link(&app).await;
+    let mut export_link = manage_link.clone();
+    export_link.set_path("/subscriptions/data/export");
+
+    // Act
+    let response = reqwest::get(export_link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let bundle: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        bundle["subscription"]["email"],
+        serde_json::json!("ursula_le_guin@gmail.com")
+    );
+    assert_eq!(bundle["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(bundle["consent_events"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn a_tampered_link_is_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+    let manage_link = request_data_management_link(&app).await;
+    let tampered_query: String = manage_link
+        .query_pairs()
+        .map(|(k, v)| match k.as_ref() {
+            "subscriber_id" => format!("{}={}", k, uuid::Uuid::new_v4()),
+            _ => format!("{}={}", k, v),
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    let mut export_link = manage_link.clone();
+    export_link.set_path("/subscriptions/data/export");
+    export_link.set_query(Some(&tampered_query));
+
+    // Act
+    let response = reqwest::get(export_link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn erasing_anonymises_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let manage_link = request_data_management_link(&app).await;
+    let form: std::collections::HashMap<_, _> = manage_link.query_pairs().into_owned().collect();
+
+    // Act
+    let response = app.post_subscriber_data_erasure(&form).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert!(saved.email.ends_with("@erased.invalid"));
+    assert_eq!(saved.name, "erased");
+    assert_eq!(saved.status, "erased");
+    let n_tokens = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_tokens, 0);
+    let n_identifying_events = sqlx::query!(
+        "SELECT COUNT(*) as \"count!\" FROM consent_events \
+        WHERE ip_address IS NOT NULL OR user_agent IS NOT NULL"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_identifying_events, 0);
+}
+
+#[tokio::test]
+async fn erasing_anonymises_the_delivery_history_but_keeps_suppressions() {
+    // Arrange
+    let app = spawn_app().await;
+    let manage_link = request_data_management_link(&app).await;
+    let form: std::collections::HashMap<_, _> = manage_link.query_pairs().into_owned().collect();
+    app.test_user.login(&app).await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+    // A delivery recorded under another spelling of the address
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_log (newsletter_issue_id, subscriber_email, outcome, recorded_at)
+        SELECT newsletter_issue_id, 'Ursula_Le_Guin@Gmail.com', 'delivered', now()
+        FROM newsletter_issues
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO email_suppressions (email, reason, provider, created_at)
+        VALUES ('ursula_le_guin@gmail.com', 'hard_bounce', 'postmark', now())
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app.post_subscriber_data_erasure(&form).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let logged = sqlx::query!("SELECT subscriber_email FROM issue_delivery_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(logged.subscriber_email.ends_with("@erased.invalid"));
+    // The address must not be mailed again if it ever comes back
+    let n_suppressions = sqlx::query!(
+        "SELECT COUNT(*) as \"count!\" FROM email_suppressions \
+        WHERE email = 'ursula_le_guin@gmail.com'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_suppressions, 1);
+}
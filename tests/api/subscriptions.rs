@@ -111,6 +111,7 @@ async fn subscribe_sends_a_confirmation_email_for_valid_data() {
 
     // Act
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     // Assert
     // Mock asserts on drop
@@ -130,10 +131,11 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
 
     // Act
     app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
 
     // Assert
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
 
     // The two links should be identical
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
@@ -154,3 +156,70 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     // Assert
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscription_form_double_submissions_are_handled_gracefully() {
+    // Arrange
+    let app = spawn_app().await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&idempotency_key={}",
+        idempotency_key
+    );
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Submit the same form twice
+    let response1 = app.post_subscriptions(body.clone()).await;
+    let response2 = app.post_subscriptions(body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response1.status().as_u16(), 200);
+    assert_eq!(response2.status().as_u16(), 200);
+    let n_subscribers = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count subscriptions.")
+        .count;
+    assert_eq!(n_subscribers, 1);
+    // Mock asserts on drop that a single confirmation email went out
+}
+
+#[tokio::test]
+async fn the_same_idempotency_key_does_not_clash_across_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    for email in ["ursula_le_guin%40gmail.com", "octavia_butler%40gmail.com"] {
+        let body = format!(
+            "name=a%20writer&email={}&idempotency_key={}",
+            email, idempotency_key
+        );
+        let response = app.post_subscriptions(body).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let n_subscribers = sqlx::query!("SELECT COUNT(*) as \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count subscriptions.")
+        .count;
+    assert_eq!(n_subscribers, 2);
+}
@@ -0,0 +1,111 @@
+use crate::helpers::spawn_app;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn unsubscribing_with_an_unknown_token_is_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_unsubscribe(&serde_json::json!({
+            "subscription_token": "not-a-real-token"
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn unsubscribing_marks_the_subscriber_and_records_the_event() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_all_pending_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let subscription_token = confirmation_links
+        .html
+        .query_pairs()
+        .find(|(k, _)| k == "subscription_token")
+        .unwrap()
+        .1
+        .into_owned();
+
+    // Act
+    let response = app
+        .post_unsubscribe(&serde_json::json!({
+            "subscription_token": subscription_token
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "unsubscribed");
+    let last_event =
+        sqlx::query!("SELECT event_type FROM consent_events ORDER BY occurred_at DESC LIMIT 1")
+            .fetch_one(&app.db_pool)
+            .await
+            .expect("Failed to fetch consent events.");
+    assert_eq!(last_event.event_type, "unsubscribed");
+}
+
+#[tokio::test]
+async fn unsubscribing_twice_sends_a_single_receipt() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    app.post_subscriptions(body.into()).await;
+    let subscription_token = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .subscription_token;
+    // Leave the confirmation email out of the count
+    sqlx::query!("DELETE FROM issue_delivery_queue")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Submit the form twice
+    for _ in 0..2 {
+        let response = app
+            .post_unsubscribe(&serde_json::json!({
+                "subscription_token": subscription_token
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let n_events = sqlx::query!(
+        "SELECT COUNT(*) AS \"count!\" FROM consent_events WHERE event_type = 'unsubscribed'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_events, 1);
+    // Mock verifies on Drop that a single receipt went out
+}
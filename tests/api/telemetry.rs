@@ -0,0 +1,160 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, SPAN_EXPORTER};
+use opentelemetry_sdk::export::trace::SpanData;
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::matchers::{header_regex, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// A random trace id, and the `traceparent` of a remote span in that trace.
+fn remote_parent() -> (String, String, String) {
+    let trace_id = Uuid::new_v4().simple().to_string();
+    let span_id = Uuid::new_v4().simple().to_string()[..16].to_string();
+    let traceparent = format!("00-{}-{}-01", trace_id, span_id);
+    (trace_id, span_id, traceparent)
+}
+
+/// The spans of the trace exported so far. Spans are exported once they
+/// end, possibly after the response was sent: wait for them a little.
+async fn exported_spans(trace_id: &str, at_least: usize) -> Vec<SpanData> {
+    let mut spans = vec![];
+    for _ in 0..50 {
+        spans = SPAN_EXPORTER
+            .get_finished_spans()
+            .unwrap()
+            .into_iter()
+            .filter(|s| s.span_context.trace_id().to_string() == trace_id)
+            .collect();
+        if spans.len() >= at_least {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    spans
+}
+
+#[tokio::test]
+async fn incoming_requests_continue_the_trace_of_their_traceparent() {
+    // Arrange
+    let app = spawn_app().await;
+    let (trace_id, span_id, traceparent) = remote_parent();
+
+    // Act
+    app.api_client
+        .get(format!("{}/health_check", &app.address))
+        .header("traceparent", traceparent)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let spans = exported_spans(&trace_id, 1).await;
+    assert!(spans
+        .iter()
+        .any(|s| s.parent_span_id.to_string() == span_id));
+}
+
+#[tokio::test]
+async fn the_trace_is_propagated_to_the_email_provider() {
+    // Arrange
+    let app = spawn_app().await;
+    let (trace_id, _, traceparent) = remote_parent();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(header_regex(
+            "traceparent",
+            "^00-[0-9a-f]{32}-[0-9a-f]{16}-01$",
+        ))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("traceparent", traceparent)
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    // The confirmation email carries the trace of the worker sending it...
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let sent_in = email_request
+        .headers
+        .get(&"traceparent".into())
+        .unwrap()
+        .last()
+        .as_str()
+        .split('-')
+        .nth(1)
+        .unwrap()
+        .to_string();
+    // ...which links back to the subscription request
+    let spans = exported_spans(&sent_in, 1).await;
+    assert!(spans
+        .iter()
+        .filter(|s| s.name == "try_execute_task")
+        .flat_map(|s| s.links.iter())
+        .any(|l| l.span_context.trace_id().to_string() == trace_id));
+}
+
+#[tokio::test]
+async fn deliveries_are_linked_to_the_publication_of_the_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    app.test_user.login(&app).await;
+    let (trace_id, _, traceparent) = remote_parent();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.api_client
+        .post(format!("{}/admin/newsletters", &app.address))
+        .header("traceparent", traceparent)
+        .form(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .send()
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let mut linked = false;
+    for _ in 0..50 {
+        linked = SPAN_EXPORTER
+            .get_finished_spans()
+            .unwrap()
+            .iter()
+            .filter(|s| s.name == "try_execute_task")
+            .flat_map(|s| s.links.iter())
+            .any(|l| l.span_context.trace_id().to_string() == trace_id);
+        if linked {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(linked);
+}
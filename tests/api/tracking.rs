@@ -0,0 +1,147 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Publish an issue and return the HTML body delivered to the subscriber.
+async fn publish_and_deliver(app: &TestApp, track: bool) -> String {
+    create_confirmed_subscriber(app, "ursula_le_guin@gmail.com").await;
+    app.test_user.login(app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let mut newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": r#"<html><body><p>Read <a href="https://example.com/article?a=1&amp;b=2">this</a></p></body></html>"#,
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    if track {
+        newsletter_request_body["track_opens"] = "true".into();
+        newsletter_request_body["track_clicks"] = "true".into();
+    }
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    body["HtmlBody"].as_str().unwrap().to_owned()
+}
+
+/// Extract the tracking links of a delivered issue, pointed at the test server.
+fn get_tracking_link(app: &TestApp, html: &str, kind: &str) -> reqwest::Url {
+    let raw_link = linkify::LinkFinder::new()
+        .links(html)
+        .map(|l| htmlescape::decode_html(l.as_str()).unwrap())
+        .find(|l| l.contains(&format!("/{}?", kind)))
+        .unwrap();
+    let mut link = reqwest::Url::parse(&raw_link).unwrap();
+    assert_eq!(link.host_str().unwrap(), "127.0.0.1");
+    link.set_port(Some(app.port)).unwrap();
+    link
+}
+
+#[tokio::test]
+async fn untracked_issues_are_delivered_as_written() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let html = publish_and_deliver(&app, false).await;
+
+    // Assert
+    assert!(html.contains(r#"href="https://example.com/article?a=1&amp;b=2""#));
+    assert!(!html.contains("/open?"));
+}
+
+#[tokio::test]
+async fn clicks_are_recorded_and_redirected_to_the_target() {
+    // Arrange
+    let app = spawn_app().await;
+    let html = publish_and_deliver(&app, true).await;
+    let click_link = get_tracking_link(&app, &html, "click");
+
+    // Act
+    let response = app.api_client.get(click_link).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(
+        response.headers().get("Location").unwrap(),
+        "https://example.com/article?a=1&b=2"
+    );
+    let event = sqlx::query!("SELECT event_type, url FROM issue_engagement_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch engagement events.");
+    assert_eq!(event.event_type, "click");
+    assert_eq!(
+        event.url.as_deref(),
+        Some("https://example.com/article?a=1&b=2")
+    );
+}
+
+#[tokio::test]
+async fn opens_are_recorded_through_the_pixel() {
+    // Arrange
+    let app = spawn_app().await;
+    let html = publish_and_deliver(&app, true).await;
+    let open_link = get_tracking_link(&app, &html, "open");
+
+    // Act
+    let response = app.api_client.get(open_link).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers().get("Content-Type").unwrap(), "image/gif");
+    let event = sqlx::query!("SELECT event_type FROM issue_engagement_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch engagement events.");
+    assert_eq!(event.event_type, "open");
+
+    // The dashboard reports the open rate
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains("<td>100.0%</td>"));
+}
+
+#[tokio::test]
+async fn forged_click_links_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let html = publish_and_deliver(&app, true).await;
+    let mut click_link = get_tracking_link(&app, &html, "click");
+    let query: Vec<(String, String)> = click_link
+        .query_pairs()
+        .map(|(k, v)| {
+            let v = if k == "url" {
+                "https://evil.example.com".to_string()
+            } else {
+                v.into_owned()
+            };
+            (k.into_owned(), v)
+        })
+        .collect();
+    click_link.query_pairs_mut().clear().extend_pairs(query);
+
+    // Act
+    let response = app.api_client.get(click_link).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    let events = sqlx::query!("SELECT event_type FROM issue_engagement_events")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(events.is_empty());
+}
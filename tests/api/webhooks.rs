@@ -0,0 +1,218 @@
+use crate::helpers::{create_confirmed_subscriber, spawn_app};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+fn hard_bounce(email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": "HardBounce",
+        "Email": email,
+        "Description": "The server was unable to deliver your message (ex: unknown user, mailbox not found)."
+    })
+}
+
+#[tokio::test]
+async fn a_hard_bounce_suppresses_the_address() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+
+    // Act
+    let response = app
+        .post_email_webhook("postmark", &hard_bounce("ursula_le_guin@gmail.com"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "bounced");
+    let suppression = sqlx::query!("SELECT email, reason, provider FROM email_suppressions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the suppression list.");
+    assert_eq!(suppression.email, "ursula_le_guin@gmail.com");
+    assert_eq!(suppression.reason, "bounced");
+    assert_eq!(suppression.provider, "postmark");
+}
+
+#[tokio::test]
+async fn addresses_are_suppressed_regardless_of_their_case() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+
+    // Act
+    let response = app
+        .post_email_webhook("postmark", &hard_bounce("Ursula_Le_Guin@Gmail.com"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "bounced");
+    let suppression = sqlx::query!("SELECT email FROM email_suppressions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the suppression list.");
+    assert_eq!(suppression.email, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn a_spam_complaint_suppresses_the_address() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+
+    // Act
+    let response = app
+        .post_email_webhook(
+            "postmark",
+            &serde_json::json!({
+                "RecordType": "SpamComplaint",
+                "Email": "ursula_le_guin@gmail.com"
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "complained");
+}
+
+#[tokio::test]
+async fn following_a_confirmation_link_does_not_lift_a_suppression() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    let subscription_token = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .subscription_token;
+    app.post_email_webhook("postmark", &hard_bounce("ursula_le_guin@gmail.com"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(format!(
+        "{}/subscriptions/confirm?subscription_token={}",
+        app.address, subscription_token
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "bounced");
+}
+
+#[tokio::test]
+async fn suppressed_addresses_get_no_confirmation_email() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    app.post_email_webhook("postmark", &hard_bounce("ursula_le_guin@gmail.com"))
+        .await
+        .error_for_status()
+        .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Submit the subscription form again, with another spelling
+    app.post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40Gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    // Mock verifies on Drop that no email went out
+}
+
+#[tokio::test]
+async fn webhooks_with_an_invalid_signature_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = serde_json::to_vec(&hard_bounce("ursula_le_guin@gmail.com")).unwrap();
+
+    // Act
+    let response = app
+        .post_raw_email_webhook("postmark", body, &"00".repeat(32))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    let suppressions = sqlx::query!("SELECT email FROM email_suppressions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(suppressions.is_empty());
+}
+
+#[tokio::test]
+async fn webhooks_from_an_unknown_provider_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_email_webhook("not-a-provider", &hard_bounce("ursula_le_guin@gmail.com"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_suppressed_addresses() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    // The address bounces but its subscription is still marked as confirmed,
+    // e.g. because it was re-confirmed afterwards.
+    app.post_email_webhook("postmark", &hard_bounce("ursula_le_guin@gmail.com"))
+        .await;
+    sqlx::query!("UPDATE subscriptions SET status = 'confirmed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 303);
+}